@@ -0,0 +1,57 @@
+//! Compares ratio and speed of the matcher lookahead depths on the sample
+//! strings:
+//!
+//! ```text
+//! cargo bench --bench lookahead
+//! ```
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use smaz::Compressor;
+
+const SAMPLES: [&str; 15] = [
+    "This is a small string",
+    "foobar",
+    "the end",
+    "not-a-g00d-Exampl333",
+    "Smaz is a simple compression library",
+    "Nothing is more difficult, and therefore more precious, than to be able to decide",
+    "this is an example of what works very well with smaz",
+    "1000 numbers 2000 will 10 20 30 compress very little",
+    "and now a few italian sentences:",
+    "Nel mezzo del cammin di nostra vita, mi ritrovai in una selva oscura",
+    "Mi illumino di immenso",
+    "L'autore di questa libreria vive in Sicilia",
+    "try it against urls",
+    "http://google.com",
+    "http://programming.reddit.com",
+];
+
+const ITERATIONS: u32 = 2_000;
+
+fn main() {
+    let input_len: usize = SAMPLES.iter().map(|s| s.len()).sum();
+    for depth in 0..3 {
+        let compressor = Compressor::new().greedy_lookahead(depth);
+        let output_len: usize = SAMPLES
+            .iter()
+            .map(|s| compressor.compress(s.as_bytes()).len())
+            .sum();
+
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            for s in SAMPLES.iter() {
+                black_box(compressor.compress(black_box(s.as_bytes())));
+            }
+        }
+        let elapsed = start.elapsed();
+        println!(
+            "lookahead {} {:>5} bytes ({:.1}%) {:>8.0} ns/iter",
+            depth,
+            output_len,
+            100.0 * output_len as f64 / input_len as f64,
+            elapsed.as_nanos() as f64 / ITERATIONS as f64
+        );
+    }
+}
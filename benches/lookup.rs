@@ -0,0 +1,48 @@
+//! Compares codebook lookup cost, run with and without the `no-hashmap`
+//! feature:
+//!
+//! ```text
+//! cargo bench --bench lookup
+//! cargo bench --bench lookup --features no-hashmap
+//! ```
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use smaz::{compress, compress_with, BuiltinCodebook};
+
+const TEXT: &str = "Nothing is more difficult, and therefore more precious, than to be able to decide. \
+                    this is an example of what works very well with smaz http://programming.reddit.com";
+
+const ITERATIONS: u32 = 20_000;
+
+/// Bytes that start no entry of the English codebook, as in binary data.
+fn binary() -> Vec<u8> {
+    (0..TEXT.len()).map(|i| 0x80 | i as u8).collect()
+}
+
+fn bench(name: &str, f: impl Fn() -> Vec<u8>) {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        black_box(f());
+    }
+    let elapsed = start.elapsed();
+    println!(
+        "{:<10} {:>8.0} ns/iter ({:.1} MB/s)",
+        name,
+        elapsed.as_nanos() as f64 / ITERATIONS as f64,
+        (TEXT.len() as f64 * ITERATIONS as f64) / elapsed.as_secs_f64() / 1e6
+    );
+}
+
+fn main() {
+    let codebook = BuiltinCodebook::English.codebook();
+    bench("default", || compress(black_box(TEXT.as_bytes())));
+    bench("codebook", || {
+        compress_with(black_box(TEXT.as_bytes()), codebook)
+    });
+
+    let binary = binary();
+    bench("binary", || compress(black_box(&binary)));
+    bench("binary cb", || compress_with(black_box(&binary), codebook));
+}
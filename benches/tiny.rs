@@ -0,0 +1,41 @@
+//! Measures compression of inputs of 3 to 7 bytes, where the cost of a call
+//! outweighs the cost of matching:
+//!
+//! ```text
+//! cargo bench --bench tiny
+//! ```
+
+use std::hint::black_box;
+use std::time::Instant;
+
+use smaz::compress;
+
+const WORDS: [&str; 12] = [
+    "the", "foobar", "the end", "42", "string", "http://", "smaz", "1000", "XQZ 7", "café",
+    "hello", "a b c",
+];
+
+const ITERATIONS: u32 = 200_000;
+
+fn main() {
+    for len in 3..8 {
+        let inputs: Vec<Vec<u8>> = WORDS
+            .iter()
+            .map(|w| w.bytes().cycle().take(len).collect())
+            .collect();
+        let start = Instant::now();
+        for _ in 0..ITERATIONS {
+            for input in &inputs {
+                black_box(compress(black_box(input)));
+            }
+        }
+        let elapsed = start.elapsed();
+        let calls = ITERATIONS as f64 * inputs.len() as f64;
+        println!(
+            "{} bytes {:>6.1} ns/call ({:.1} M calls/s)",
+            len,
+            elapsed.as_nanos() as f64 / calls,
+            calls / elapsed.as_secs_f64() / 1e6
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! Generates lookup tables for the default codebook, so looking up an entry
+//! needs no runtime initialization: direct tables for entries of one and two
+//! bytes, and for longer ones a perfect hash table, or with the `no-hashmap`
+//! feature a direct table for entries of one byte and a sorted array for the
+//! others.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+const CODEBOOK: [&str; 254] = include!("src/codebook.in");
+
+/// Number of slots in the table, must be a power of two.
+const TABLE_SIZE: usize = 4096;
+
+/// Marks an empty slot.
+const EMPTY: u8 = 255;
+
+// Must match `hash` in src/codebook.rs.
+fn hash(seed: u32, bytes: &[u8]) -> usize {
+    let mut h = seed ^ 0x811c_9dc5;
+    for b in bytes {
+        h ^= *b as u32;
+        h = h.wrapping_mul(0x0100_0193);
+    }
+    (h ^ (h >> 16)) as usize & (TABLE_SIZE - 1)
+}
+
+fn build_table(seed: u32) -> Option<Vec<u8>> {
+    let mut table = vec![EMPTY; TABLE_SIZE];
+    for (i, entry) in CODEBOOK.iter().enumerate() {
+        let slot = hash(seed, entry.as_bytes());
+        if table[slot] != EMPTY {
+            return None;
+        }
+        table[slot] = i as u8;
+    }
+    Some(table)
+}
+
+/// Returns the entries sorted by their bytes, for binary search.
+fn sorted_table() -> String {
+    let mut sorted: Vec<(&[u8], usize)> = CODEBOOK
+        .iter()
+        .enumerate()
+        .map(|(i, e)| (e.as_bytes(), i))
+        .collect();
+    sorted.sort();
+    let items: Vec<String> = sorted
+        .iter()
+        .map(|(e, i)| format!("(&{:?}, {})", e, i))
+        .collect();
+    format!(
+        "static DEFAULT_SORTED: [(&[u8], u8); {}] = [{}];\n",
+        sorted.len(),
+        items.join(", ")
+    )
+}
+
+/// Returns the set of first bytes of the entries, as a `ByteSet` of
+/// src/codebook.rs.
+fn starts() -> String {
+    let mut set = [0u64; 4];
+    for entry in CODEBOOK.iter() {
+        let b = entry.as_bytes()[0] as usize;
+        set[b / 64] |= 1 << (b % 64);
+    }
+    format!("static DEFAULT_STARTS: ByteSet = ByteSet({:?});\n", set)
+}
+
+/// Returns the direct lookup table for the entries of one byte.
+fn singles_table() -> String {
+    let mut singles = vec![EMPTY; 256];
+    for (i, entry) in CODEBOOK.iter().enumerate() {
+        if let [a] = *entry.as_bytes() {
+            singles[a as usize] = i as u8;
+        }
+    }
+    format!("static DEFAULT_SINGLES: [u8; 256] = {:?};\n", singles)
+}
+
+/// Returns the direct lookup table for the entries of two bytes, indexed by
+/// the pair as a big-endian `u16`. It takes 64 KiB, so it is left out with the
+/// `no-hashmap` feature, where the sorted array serves those entries too.
+fn pairs_table() -> String {
+    let mut pairs = vec![EMPTY; 1 << 16];
+    for (i, entry) in CODEBOOK.iter().enumerate() {
+        if let [a, b] = *entry.as_bytes() {
+            pairs[(a as usize) << 8 | b as usize] = i as u8;
+        }
+    }
+    format!("static DEFAULT_PAIRS: [u8; 65536] = {:?};\n", pairs)
+}
+
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+    println!("cargo:rerun-if-changed=src/codebook.in");
+
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("default_table.rs");
+    if env::var_os("CARGO_FEATURE_NO_HASHMAP").is_some() {
+        fs::write(path, sorted_table() + &starts() + &singles_table()).unwrap();
+        return;
+    }
+
+    let (seed, table) = (0..)
+        .find_map(|seed| build_table(seed).map(|table| (seed, table)))
+        .unwrap();
+
+    let mut out = String::new();
+    out.push_str(&format!("const DEFAULT_SEED: u32 = {};\n", seed));
+    out.push_str(&format!(
+        "const DEFAULT_TABLE_SIZE: usize = {};\n",
+        TABLE_SIZE
+    ));
+    out.push_str(&format!(
+        "static DEFAULT_TABLE: [u8; {}] = {:?};\n",
+        TABLE_SIZE, table
+    ));
+    out.push_str(&starts());
+    out.push_str(&singles_table());
+    out.push_str(&pairs_table());
+    fs::write(path, out).unwrap();
+}
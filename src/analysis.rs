@@ -0,0 +1,1036 @@
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+use std::iter;
+use std::ops::Range;
+
+use crate::codebook::{DefaultCodebook, Dictionary};
+use crate::{compress_with, Codebook, Step, Steps, CODEBOOK_SIZE};
+
+/// Statistics gathered while compressing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct CompressReport {
+    /// Length of the input in bytes.
+    pub input_len: usize,
+    /// Length of the compressed output in bytes.
+    pub output_len: usize,
+    /// Number of codebook codes emitted.
+    pub hits: usize,
+    /// Number of verbatim runs emitted.
+    pub verbatim_runs: usize,
+    /// Number of input bytes stored verbatim.
+    pub verbatim_bytes: usize,
+}
+
+/// Returns compressed data along with statistics computed in the same pass.
+///
+/// The data is identical to the output of [`compress`](fn.compress.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_ex};
+///
+/// let (compressed, report) = compress_ex(b"the 42");
+/// assert_eq!(compress(b"the 42"), compressed);
+/// assert_eq!(2, report.hits);
+/// assert_eq!(1, report.verbatim_runs);
+/// assert_eq!(2, report.verbatim_bytes);
+/// ```
+pub fn compress_ex(input: &[u8]) -> (Vec<u8>, CompressReport) {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    let mut report = CompressReport {
+        input_len: input.len(),
+        ..CompressReport::default()
+    };
+
+    for step in Steps::new(input, &DefaultCodebook) {
+        match step {
+            Step::Code(_) => report.hits += 1,
+            Step::Verbatim(bytes) => {
+                report.verbatim_runs += 1;
+                report.verbatim_bytes += bytes.len();
+            }
+        }
+        step.write_to(&mut out);
+    }
+    report.output_len = out.len();
+    (out, report)
+}
+
+/// Counts of how often each code of the default codebook is emitted, kept
+/// across calls to [`compress_counted`](fn.compress_counted.html).
+///
+/// Entries still at zero after a representative corpus are candidates for
+/// replacement, and the counts can be given to
+/// [`Codebook::with_frequencies`](struct.Codebook.html#method.with_frequencies).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_counted, HitCounter, CODEBOOK};
+///
+/// let mut counter = HitCounter::new();
+/// for s in ["the end", "the start"] {
+///     compress_counted(s.as_bytes(), &mut counter);
+/// }
+/// let the = CODEBOOK.iter().position(|e| *e == "the").unwrap();
+/// assert_eq!(2, counter.report()[the]);
+/// let unused = counter.report().iter().filter(|n| **n == 0).count();
+/// assert!(unused > 240);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct HitCounter {
+    counts: Vec<u64>,
+}
+
+impl HitCounter {
+    /// Creates a counter with every count at zero.
+    pub fn new() -> HitCounter {
+        HitCounter {
+            counts: vec![0; CODEBOOK_SIZE],
+        }
+    }
+
+    /// Returns the count of each code, indexed by code.
+    pub fn report(&self) -> &[u64] {
+        &self.counts
+    }
+}
+
+impl Default for HitCounter {
+    fn default() -> HitCounter {
+        HitCounter::new()
+    }
+}
+
+/// Returns compressed data, adding each code emitted to the counts of
+/// `counter`.
+///
+/// The data is identical to the output of [`compress`](fn.compress.html).
+pub fn compress_counted(input: &[u8], counter: &mut HitCounter) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    for step in Steps::new(input, &DefaultCodebook) {
+        if let Step::Code(code) = step {
+            counter.counts[code as usize] += 1;
+        }
+        step.write_to(&mut out);
+    }
+    out
+}
+
+/// Returns the number of leading bytes [`compress`](fn.compress.html) gives
+/// for both inputs.
+///
+/// Compression is greedy and left to right, so inputs starting alike compress
+/// to data starting alike, and the shared length can cluster or sort similar
+/// inputs. Both inputs are compressed lazily, token by token, and compression
+/// stops at the first difference.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{common_compressed_prefix_len, compress};
+///
+/// let (a, b) = (b"the end of it", b"the end is near");
+/// // "the", " e" and "nd " compress alike
+/// assert_eq!(3, common_compressed_prefix_len(a, b));
+/// assert_eq!(compress(a)[..3], compress(b)[..3]);
+/// ```
+pub fn common_compressed_prefix_len(a: &[u8], b: &[u8]) -> usize {
+    fn bytes(input: &[u8]) -> impl Iterator<Item = u8> + '_ {
+        Steps::new(input, &DefaultCodebook).flat_map(|step| {
+            let mut out = Vec::with_capacity(2);
+            step.write_to(&mut out);
+            out
+        })
+    }
+    bytes(a).zip(bytes(b)).take_while(|(x, y)| x == y).count()
+}
+
+/// Returns `true` if [`compress`](fn.compress.html) of the input is exactly
+/// `stored`.
+///
+/// The input is compressed token by token and each token checked against
+/// `stored` in place, so no output is allocated and compression stops at the
+/// first difference.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_eq};
+///
+/// let stored = compress(b"the end");
+/// assert!(compress_eq(b"the end", &stored));
+/// assert!(!compress_eq(b"the end!", &stored));
+/// ```
+pub fn compress_eq(input: &[u8], stored: &[u8]) -> bool {
+    first_difference(input, stored).is_none()
+}
+
+/// Returns the offset in `stored` of the first token
+/// [`compress`](fn.compress.html) writes differently for the input, or of the
+/// bytes left over after its output, or `None` if there is no difference.
+pub(crate) fn first_difference(input: &[u8], stored: &[u8]) -> Option<usize> {
+    let mut rest = stored;
+    for step in Steps::new(input, &DefaultCodebook) {
+        let tail = match step {
+            Step::Code(code) => rest.strip_prefix(&[code]),
+            Step::Verbatim([b]) => rest.strip_prefix(&[254, *b]),
+            Step::Verbatim(bytes) => rest
+                .strip_prefix(&[255, (bytes.len() - 1) as u8])
+                .and_then(|tail| tail.strip_prefix(bytes)),
+        };
+        match tail {
+            Some(tail) => rest = tail,
+            None => return Some(stored.len() - rest.len()),
+        }
+    }
+    Some(stored.len() - rest.len()).filter(|_| !rest.is_empty())
+}
+
+/// Returns how many bytes of [`compress`](fn.compress.html) output each input
+/// byte costs.
+///
+/// The output of each token is shared evenly by the input bytes it encodes: a
+/// code matching `n` bytes costs `1 / n` per byte, a single verbatim byte costs
+/// 2 with its marker, and a verbatim run of `n` bytes costs `(n + 2) / n` per
+/// byte with its marker and length. The contributions sum to the compressed
+/// length, up to floating point rounding, so they can be drawn as a heatmap of
+/// which parts of the input compress well.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::byte_contributions;
+///
+/// // "the", " " and the verbatim run "42"
+/// let costs = byte_contributions(b"the 42");
+/// assert_eq!(vec![1.0 / 3.0, 1.0 / 3.0, 1.0 / 3.0, 1.0, 2.0, 2.0], costs);
+/// ```
+pub fn byte_contributions(input: &[u8]) -> Vec<f32> {
+    let mut costs = Vec::with_capacity(input.len());
+    let mut steps = Steps::new(input, &DefaultCodebook);
+    let mut start = 0;
+    while let Some(step) = steps.next() {
+        let n = steps.index - start;
+        costs.extend(iter::repeat_n(step.encoded_len() as f32 / n as f32, n));
+        start = steps.index;
+    }
+    costs
+}
+
+/// Returns the number of tokens [`compress`](fn.compress.html) emits for the
+/// input.
+///
+/// A token is either a codebook code or a verbatim run, whatever its length: a
+/// run of up to 256 bytes counts as a single token, and a longer stretch of
+/// verbatim bytes counts as one token per 256-byte run. This equals the number
+/// of items of [`tokenize`](fn.tokenize.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::token_count;
+///
+/// // "the", " " and the verbatim run "42"
+/// assert_eq!(3, token_count(b"the 42"));
+/// ```
+pub fn token_count(input: &[u8]) -> usize {
+    Steps::new(input, &DefaultCodebook).count()
+}
+
+/// The action [`compress`](fn.compress.html) takes at a position.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceAction {
+    /// The codebook code of the match.
+    Code(u8),
+    /// No entry matches, so the byte is added to the current verbatim run.
+    Verbatim(u8),
+}
+
+/// Returns a hexdump of compressed data with one line per byte, annotated with
+/// its role in the format of [`compress`](fn.compress.html).
+///
+/// Each line holds the offset and the byte in hexadecimal, then one of: a
+/// `code` with its codebook entry, a `verbatim byte` or `verbatim run` marker,
+/// the `length` of a run, or a `verbatim` payload byte. Malformed input does
+/// not stop the dump: a marker missing its bytes is annotated as `truncated`.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, hexdump_annotated};
+///
+/// let dump = hexdump_annotated(&compress(b"the 4"));
+/// assert_eq!("0000  01  code 1 \"the\"\n0001  00  code 0 \" \"\n0002  fe  verbatim byte\n0003  34  verbatim \"4\"\n", dump);
+/// ```
+pub fn hexdump_annotated(input: &[u8]) -> String {
+    let mut out = String::new();
+    let mut line = |pos: usize, note: &str| {
+        let _ = writeln!(out, "{:04x}  {:02x}  {}", pos, input[pos], note);
+    };
+
+    let mut pos = 0;
+    while pos < input.len() {
+        let (payload, note) = match input[pos] {
+            254 => (1, "verbatim byte".to_string()),
+            255 => (
+                input.get(pos + 1).map_or(0, |len| usize::from(*len) + 1),
+                "verbatim run".to_string(),
+            ),
+            code => {
+                let entry = DefaultCodebook.entry(code).unwrap_or(b"");
+                (0, format!("code {} \"{}\"", code, entry.escape_ascii()))
+            }
+        };
+        let cut = pos + 1 + usize::from(input[pos] == 255) + payload > input.len();
+        if cut {
+            line(pos, &format!("{}, truncated", note));
+        } else {
+            line(pos, &note);
+        }
+        pos += 1;
+
+        if input[pos - 1] == 255 && pos < input.len() {
+            line(pos, &format!("length {}", payload));
+            pos += 1;
+        }
+        for _ in 0..payload {
+            if pos >= input.len() {
+                break;
+            }
+            line(
+                pos,
+                &format!("verbatim \"{}\"", [input[pos]].escape_ascii()),
+            );
+            pos += 1;
+        }
+    }
+    out
+}
+
+/// A single decision of [`compress`](fn.compress.html), see
+/// [`trace`](fn.trace.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceStep {
+    /// Position in the input.
+    pub position: usize,
+    /// Match lengths looked up, longest first. Empty if no entry starts with
+    /// the byte at the position.
+    pub tried: Vec<usize>,
+    /// The action taken.
+    pub action: TraceAction,
+}
+
+/// Returns every decision [`compress`](fn.compress.html) takes for the input.
+///
+/// At each position the longest match is searched by looking up lengths from
+/// 7 bytes down, and either its code is emitted or the byte is stored
+/// verbatim. Consecutive verbatim bytes form runs of at most 256 bytes.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{trace, TraceAction};
+///
+/// let steps = trace(b"the 4");
+/// assert_eq!(3, steps.len());
+/// assert_eq!(vec![5, 4, 3], steps[0].tried);
+/// assert_eq!(TraceAction::Code(1), steps[0].action);
+/// assert_eq!(TraceAction::Verbatim(b'4'), steps[2].action);
+/// ```
+pub fn trace(input: &[u8]) -> Vec<TraceStep> {
+    let mut steps = Vec::new();
+    let mut position = 0;
+    while position < input.len() {
+        let rest = &input[position..];
+        let mut tried = Vec::new();
+        let mut action = TraceAction::Verbatim(rest[0]);
+        let mut len = 1;
+
+        // Mirrors `Dictionary::find_match`.
+        if DefaultCodebook.can_start(rest[0]) {
+            for i in (1..=rest.len().min(DefaultCodebook.max_len())).rev() {
+                tried.push(i);
+                if let Some(code) = DefaultCodebook.lookup(&rest[..i]) {
+                    action = TraceAction::Code(code);
+                    len = i;
+                    break;
+                }
+            }
+        }
+
+        steps.push(TraceStep {
+            position,
+            tried,
+            action,
+        });
+        position += len;
+    }
+    steps
+}
+
+/// Returns a report of how [`compress_with`](fn.compress_with.html) encodes
+/// each byte of `range` of the input, one line per byte.
+///
+/// Each line holds the offset in hexadecimal and the byte, then the longest
+/// entry matching at the offset, `no match` if there is none, and what
+/// compression of the whole input does there: a `code` starting at the
+/// offset, a byte `in the code at` an earlier offset, or a `verbatim` byte.
+/// A byte can have a match and still be in another code, or in no code when a
+/// longer match ends past it; bytes with no match at all point at entries the
+/// codebook lacks.
+///
+/// # Panics
+///
+/// Panics if `range` is out of the bounds of the input.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{explain_substring, Codebook};
+///
+/// let report = explain_substring(b"the 42", 3..5, &Codebook::default());
+/// assert_eq!("0003  \" \"  longest \" \" (code 0), code 0\n0004  \"4\"  no match, verbatim\n", report);
+/// ```
+pub fn explain_substring(input: &[u8], range: Range<usize>, cb: &Codebook) -> String {
+    assert!(
+        range.start <= range.end && range.end <= input.len(),
+        "range is out of bounds"
+    );
+    let mut out = String::new();
+    let mut steps = Steps::new(input, cb);
+    let mut start = 0;
+    while start < range.end {
+        let step = steps.next().unwrap();
+        for pos in start.max(range.start)..steps.index.min(range.end) {
+            let longest = match cb.find_match(&input[pos..]) {
+                Some((code, len)) => format!(
+                    "longest \"{}\" (code {})",
+                    input[pos..pos + len].escape_ascii(),
+                    code
+                ),
+                None => "no match".to_string(),
+            };
+            let action = match step {
+                Step::Code(code) if pos == start => format!("code {}", code),
+                Step::Code(_) => format!("in the code at {:04x}", start),
+                Step::Verbatim(_) => "verbatim".to_string(),
+            };
+            let byte = input[pos..=pos].escape_ascii();
+            let _ = writeln!(out, "{:04x}  \"{}\"  {}, {}", pos, byte, longest, action);
+        }
+        start = steps.index;
+    }
+    out
+}
+
+/// Returns the number of bytes adding `entry` to the codebook would save when
+/// compressing the corpus, negative if it costs bytes.
+///
+/// The corpus is compressed with and without the entry, so every match the
+/// entry would win over existing entries, or lose to them, is accounted for.
+///
+/// # Panics
+///
+/// Panics if the codebook already has 254 entries, or `entry` is empty or
+/// longer than 7 bytes.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{marginal_savings, BuiltinCodebook, Codebook};
+///
+/// let codebook = Codebook::new(BuiltinCodebook::English.codebook().iter().take(200)).unwrap();
+/// let corpus = [&b"http://reddit.com/r/rust"[..], b"https://old.reddit.com"];
+/// assert!(marginal_savings(b"reddit", &corpus, &codebook) > 0);
+/// ```
+pub fn marginal_savings(entry: &[u8], corpus: &[&[u8]], codebook: &Codebook) -> i64 {
+    let mut extended = Codebook::new(codebook.iter().chain(Some(entry)))
+        .expect("codebook has room for an entry of 1 to 7 bytes");
+    if let Some(escape) = codebook.escape() {
+        extended = extended.with_escape(escape).unwrap();
+    }
+
+    let size = |codebook: &Codebook| -> i64 {
+        corpus
+            .iter()
+            .map(|input| compress_with(input, codebook).len() as i64)
+            .sum()
+    };
+    size(codebook) - size(&extended)
+}
+
+/// Results of compressing a corpus with two codebooks, see
+/// [`compare_codebooks`](fn.compare_codebooks.html).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct CodebookComparison {
+    /// Total length of the corpus in bytes.
+    pub input_len: usize,
+    /// Total compressed length with the first codebook.
+    pub a_len: usize,
+    /// Total compressed length with the second codebook.
+    pub b_len: usize,
+    /// Number of items compressing shorter with the first codebook.
+    pub a_wins: usize,
+    /// Number of items compressing shorter with the second codebook.
+    pub b_wins: usize,
+    /// Number of items compressing to the same length with both.
+    pub ties: usize,
+    /// Average over non-empty items of the compression ratio, compressed
+    /// length over length, with the first codebook minus that with the
+    /// second. Positive when the second codebook compresses better.
+    pub mean_ratio_diff: f64,
+}
+
+/// Returns the length [`compress_with`](fn.compress_with.html) gives for the
+/// input, without producing the data.
+fn compressed_len(input: &[u8], codebook: &Codebook) -> usize {
+    Steps::new(input, codebook)
+        .map(|step| step.encoded_len())
+        .sum()
+}
+
+/// Returns how two codebooks compare at compressing each item of a corpus.
+///
+/// Only the compressed lengths are computed, not the data.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compare_codebooks, BuiltinCodebook};
+///
+/// let corpus = [&b"https://example.com/index.html"[..], b"http://www.example.org"];
+/// let english = BuiltinCodebook::English.codebook();
+/// let url = BuiltinCodebook::Url.codebook();
+/// let comparison = compare_codebooks(&corpus, english, url);
+/// assert_eq!(2, comparison.b_wins);
+/// assert!(comparison.b_len < comparison.a_len);
+/// assert!(comparison.mean_ratio_diff > 0.0);
+/// ```
+pub fn compare_codebooks(corpus: &[&[u8]], a: &Codebook, b: &Codebook) -> CodebookComparison {
+    let mut comparison = CodebookComparison::default();
+    let mut ratio_diff = 0.0;
+    let mut items = 0;
+    for input in corpus {
+        let (a_len, b_len) = (compressed_len(input, a), compressed_len(input, b));
+        comparison.input_len += input.len();
+        comparison.a_len += a_len;
+        comparison.b_len += b_len;
+        match a_len.cmp(&b_len) {
+            Ordering::Less => comparison.a_wins += 1,
+            Ordering::Greater => comparison.b_wins += 1,
+            Ordering::Equal => comparison.ties += 1,
+        }
+        if !input.is_empty() {
+            ratio_diff += (a_len as f64 - b_len as f64) / input.len() as f64;
+            items += 1;
+        }
+    }
+    if items > 0 {
+        comparison.mean_ratio_diff = ratio_diff / items as f64;
+    }
+    comparison
+}
+
+/// Entries two codebooks share and do not share, see
+/// [`codebook_overlap`](fn.codebook_overlap.html).
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct OverlapReport {
+    /// Entries of both codebooks, in the order of the first.
+    pub shared: Vec<Vec<u8>>,
+    /// Entries only of the first codebook, in its order.
+    pub only_a: Vec<Vec<u8>>,
+    /// Entries only of the second codebook, in its order.
+    pub only_b: Vec<Vec<u8>>,
+    /// Number of shared entries over the number of distinct entries of
+    /// either codebook, from `0.0` for no shared entry to `1.0` for the same
+    /// entries.
+    pub jaccard: f64,
+}
+
+/// Returns the distinct entries of a codebook in code order, leaving out the
+/// escape, which never matches.
+fn distinct_entries(codebook: &Codebook) -> Vec<&[u8]> {
+    let mut seen = BTreeSet::new();
+    (0..codebook.len())
+        .filter_map(|code| Dictionary::entry(codebook, code as u8))
+        .filter(|entry| seen.insert(*entry))
+        .collect()
+}
+
+/// Returns the entries two codebooks share and those only one of them has.
+///
+/// Entries are compared as bytes, whatever their codes. Duplicate entries
+/// count once, and the escape code of a codebook, see
+/// [`Codebook::with_escape`](struct.Codebook.html#method.with_escape), is no
+/// entry. Codebooks trained on the same kind of data share most entries, and
+/// merging them gains little over either one.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{codebook_overlap, Codebook};
+///
+/// let a = Codebook::new(["the", "and", "of"]).unwrap();
+/// let b = Codebook::new(["of", "http", "the"]).unwrap();
+/// let overlap = codebook_overlap(&a, &b);
+/// assert_eq!(vec![b"the".to_vec(), b"of".to_vec()], overlap.shared);
+/// assert_eq!(vec![b"and".to_vec()], overlap.only_a);
+/// assert_eq!(vec![b"http".to_vec()], overlap.only_b);
+/// assert_eq!(0.5, overlap.jaccard);
+/// ```
+pub fn codebook_overlap(a: &Codebook, b: &Codebook) -> OverlapReport {
+    let (a, b) = (distinct_entries(a), distinct_entries(b));
+    let (in_a, in_b): (BTreeSet<&[u8]>, BTreeSet<&[u8]>) =
+        (a.iter().copied().collect(), b.iter().copied().collect());
+    let mut report = OverlapReport::default();
+    for entry in &a {
+        if in_b.contains(entry) {
+            report.shared.push(entry.to_vec());
+        } else {
+            report.only_a.push(entry.to_vec());
+        }
+    }
+    report.only_b = b
+        .iter()
+        .filter(|entry| !in_a.contains(*entry))
+        .map(|entry| entry.to_vec())
+        .collect();
+    let union = report.shared.len() + report.only_a.len() + report.only_b.len();
+    report.jaccard = match union {
+        0 => 1.0,
+        n => report.shared.len() as f64 / n as f64,
+    };
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{inputs, Rng, TEST_STRINGS};
+    use crate::{compress, flush_verbatim, tokenize, Token};
+
+    #[test]
+    fn test_compress_ex() {
+        for s in TEST_STRINGS.iter() {
+            let (compressed, report) = compress_ex(s.as_bytes());
+            assert_eq!(compress(s.as_bytes()), compressed);
+            assert_eq!(s.len(), report.input_len);
+            assert_eq!(compressed.len(), report.output_len);
+
+            let tokens: Vec<Token> = tokenize(s.as_bytes()).collect();
+            let hits = tokens
+                .iter()
+                .filter(|t| matches!(t, Token::Code(_)))
+                .count();
+            assert_eq!(hits, report.hits);
+            assert_eq!(tokens.len() - hits, report.verbatim_runs);
+
+            // A verbatim run costs one or two marker bytes.
+            let payload = report.verbatim_bytes + report.hits;
+            assert!(report.output_len >= payload + report.verbatim_runs);
+            assert!(report.output_len <= payload + 2 * report.verbatim_runs);
+            assert!(report.input_len >= payload);
+        }
+    }
+
+    #[test]
+    fn test_token_count() {
+        assert_eq!(0, token_count(b""));
+        // "fo", "o", "b", "ar"
+        assert_eq!(4, token_count(b"foobar"));
+        // "http://", "g", "o", "o", "g", "le", ".com"
+        assert_eq!(7, token_count(b"http://google.com"));
+        // "not", "-", "a", "-", "g", "00", "d", "-", "E", "x", "a", "m", "p",
+        // "l", "333"
+        assert_eq!(15, token_count(b"not-a-g00d-Exampl333"));
+        assert_eq!(4, token_count(&[0; 1000]));
+
+        for s in TEST_STRINGS.iter() {
+            assert_eq!(tokenize(s.as_bytes()).count(), token_count(s.as_bytes()));
+        }
+    }
+
+    /// Returns the compressed data the actions of a trace stand for.
+    fn replay(steps: &[TraceStep]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut verbatim = Vec::new();
+        for step in steps {
+            match step.action {
+                TraceAction::Code(code) => {
+                    out.extend(flush_verbatim(&verbatim));
+                    verbatim.clear();
+                    out.push(code);
+                }
+                TraceAction::Verbatim(b) => verbatim.push(b),
+            }
+        }
+        out.extend(flush_verbatim(&verbatim));
+        out
+    }
+
+    #[test]
+    fn test_trace() {
+        for s in TEST_STRINGS.iter() {
+            assert_eq!(compress(s.as_bytes()), replay(&trace(s.as_bytes())));
+        }
+
+        let mut rng = Rng::new(142);
+        for _ in 0..500 {
+            let input = rng.bytes(600);
+            let steps = trace(&input);
+            assert_eq!(compress(&input), replay(&steps));
+            for step in steps.iter() {
+                let rest = &input[step.position..];
+                let found = DefaultCodebook.find_match(rest);
+                match step.action {
+                    TraceAction::Code(code) => {
+                        assert_eq!(Some((code, *step.tried.last().unwrap())), found)
+                    }
+                    TraceAction::Verbatim(b) => {
+                        assert_eq!(None, found);
+                        assert_eq!(rest[0], b);
+                    }
+                }
+            }
+        }
+
+        let steps = trace(b"\xffoo");
+        assert!(steps[0].tried.is_empty());
+        assert_eq!(vec![2, 1], steps[1].tried);
+        assert!(trace(b"").is_empty());
+    }
+
+    #[test]
+    fn test_marginal_savings() {
+        let codebook = Codebook::new(
+            crate::CODEBOOK
+                .iter()
+                .filter(|e| !e.contains("re"))
+                .take(250),
+        )
+        .unwrap();
+        let urls: Vec<&[u8]> = [
+            "http://reddit.com",
+            "http://www.reddit.com/r/programming",
+            "https://old.reddit.com/user/smaz",
+        ]
+        .iter()
+        .map(|s| s.as_bytes())
+        .collect();
+
+        // every URL contains "reddit"
+        let saved = marginal_savings(b"reddit", &urls, &codebook);
+        assert!(saved >= 3, "saved {}", saved);
+
+        assert_eq!(0, marginal_savings(b"reddit", &[], &codebook));
+        assert_eq!(0, marginal_savings(b"zzzzz", &urls, &codebook));
+        let first = codebook.get(0).unwrap().to_vec();
+        assert_eq!(0, marginal_savings(&first, &urls, &codebook));
+
+        let escaped = codebook.clone().with_escape(3).unwrap();
+        assert!(marginal_savings(b"reddit", &urls, &escaped) >= 3);
+    }
+
+    #[test]
+    #[should_panic(expected = "codebook has room")]
+    fn test_marginal_savings_full() {
+        marginal_savings(b"reddit", &[], crate::BuiltinCodebook::English.codebook());
+    }
+
+    #[test]
+    #[should_panic(expected = "entry of 1 to 7 bytes")]
+    fn test_marginal_savings_too_long() {
+        let codebook = Codebook::new(["the", "and", "of"]).unwrap();
+        marginal_savings(b"subreddit", &[], &codebook);
+    }
+
+    #[test]
+    fn test_hexdump_annotated() {
+        assert_eq!("", hexdump_annotated(b""));
+        assert_eq!(
+            "0000  4d  code 77 \"st\"\n0001  72  code 114 \"ri\"\n0002  54  code 84 \"ng\"\n",
+            hexdump_annotated(&compress(b"string"))
+        );
+        assert_eq!(
+            [
+                "0000  ff  verbatim run",
+                "0001  01  length 2",
+                "0002  31  verbatim \"1\"",
+                "0003  30  verbatim \"0\"",
+                "0004  00  code 0 \" \"",
+                "0005  fe  verbatim byte",
+                "0006  0a  verbatim \"\\n\"",
+                "",
+            ]
+            .join("\n"),
+            hexdump_annotated(&[255, 1, b'1', b'0', 0, 254, b'\n'])
+        );
+
+        // malformed input is dumped to the end
+        assert_eq!(
+            "0000  ff  verbatim run, truncated\n0001  05  length 6\n0002  61  verbatim \"a\"\n",
+            hexdump_annotated(&[255, 5, b'a'])
+        );
+        assert_eq!(
+            "0000  ff  verbatim run, truncated\n",
+            hexdump_annotated(&[255])
+        );
+        assert_eq!(
+            "0000  fe  verbatim byte, truncated\n",
+            hexdump_annotated(&[254])
+        );
+        let mut rng = Rng::new(163);
+        for _ in 0..1000 {
+            let input = rng.bytes(50);
+            assert_eq!(input.len(), hexdump_annotated(&input).lines().count());
+        }
+    }
+
+    #[test]
+    fn test_common_compressed_prefix_len() {
+        for a in TEST_STRINGS.iter() {
+            for b in TEST_STRINGS.iter() {
+                let (x, y) = (compress(a.as_bytes()), compress(b.as_bytes()));
+                let shared = x.iter().zip(&y).take_while(|(x, y)| x == y).count();
+                assert_eq!(
+                    shared,
+                    common_compressed_prefix_len(a.as_bytes(), b.as_bytes())
+                );
+            }
+        }
+
+        let base = b"user:1234:profile:name";
+        let similar = common_compressed_prefix_len(base, b"user:1234:profile:mail");
+        let dissimilar = common_compressed_prefix_len(base, b"order:99:status");
+        assert!(similar > dissimilar + 5, "{} vs {}", similar, dissimilar);
+
+        // one verbatim stretch continuing past the run of the other
+        let (short, long) = (vec![1; 256], vec![1; 300]);
+        assert_eq!(258, common_compressed_prefix_len(&short, &long));
+        assert_eq!(0, common_compressed_prefix_len(b"", b"abc"));
+    }
+
+    #[test]
+    fn test_compress_eq() {
+        let mut rng = Rng::new(179);
+        let inputs = inputs(179);
+
+        for input in &inputs {
+            let stored = compress(input);
+            assert!(compress_eq(input, &stored));
+            if !stored.is_empty() {
+                assert!(!compress_eq(input, &stored[..stored.len() - 1]));
+                let mut changed = stored.clone();
+                let i = rng.next_u64() as usize % changed.len();
+                changed[i] ^= 1;
+                assert!(!compress_eq(input, &changed));
+            }
+            let mut longer = stored.clone();
+            longer.push(0);
+            assert!(!compress_eq(input, &longer));
+        }
+        for a in TEST_STRINGS.iter() {
+            for b in TEST_STRINGS.iter() {
+                let stored = compress(b.as_bytes());
+                assert_eq!(a == b, compress_eq(a.as_bytes(), &stored));
+            }
+        }
+    }
+
+    #[test]
+    fn test_byte_contributions() {
+        let mut inputs = inputs(181);
+        inputs.push(vec![1; 1000]);
+
+        for input in &inputs {
+            let costs = byte_contributions(input);
+            assert_eq!(input.len(), costs.len());
+            let sum: f64 = costs.iter().map(|c| *c as f64).sum();
+            let len = compress(input).len() as f64;
+            assert!(
+                (sum - len).abs() < 1e-3 * len.max(1.0),
+                "{} vs {}",
+                sum,
+                len
+            );
+        }
+
+        // a lone verbatim byte, then a run split at 256 bytes
+        assert_eq!(vec![2.0], byte_contributions(b"4"));
+        let costs = byte_contributions(&[1; 257]);
+        assert!(costs[..256].iter().all(|c| *c == 258.0 / 256.0));
+        assert_eq!(2.0, costs[256]);
+        assert!(byte_contributions(b"").is_empty());
+    }
+
+    #[test]
+    fn test_hit_counter() {
+        let code = |entry: &str| crate::CODEBOOK.iter().position(|e| *e == entry).unwrap();
+        let mut counter = HitCounter::new();
+        assert_eq!(
+            compress(b"the the the"),
+            compress_counted(b"the the the", &mut counter)
+        );
+        // greedy matching splits it into "the", " th", "e t" and "he"
+        let expected = [code("the"), code(" th"), code("e t"), code("he")];
+        for (i, count) in counter.report().iter().enumerate() {
+            assert_eq!(expected.contains(&i) as u64, *count, "code {}", i);
+        }
+        for _ in 0..3 {
+            compress_counted(b"the", &mut counter);
+        }
+        assert_eq!(4, counter.report()[code("the")]);
+
+        // the counts add up to the codes emitted
+        let mut counter = HitCounter::default();
+        let mut codes = 0;
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_counted(s.as_bytes(), &mut counter);
+            assert_eq!(compress(s.as_bytes()), compressed);
+            codes += compress_ex(s.as_bytes()).1.hits as u64;
+        }
+        assert_eq!(codes, counter.report().iter().sum::<u64>());
+        assert_eq!(CODEBOOK_SIZE, counter.report().len());
+    }
+
+    #[test]
+    fn test_explain_substring() {
+        let codebook = Codebook::default();
+        let input = b"call 555 0199 now, the end";
+
+        // digits match nothing
+        let digits = explain_substring(input, 5..13, &codebook);
+        assert_eq!(8, digits.lines().count());
+        for (line, pos) in digits.lines().zip(5..) {
+            let expected = if input[pos] == b' ' {
+                "code 0"
+            } else {
+                "no match, verbatim"
+            };
+            assert!(line.starts_with(&format!("{:04x}  ", pos)), "{}", line);
+            assert!(line.ends_with(expected), "{}", line);
+        }
+
+        // English words do, and every byte is in a code
+        let words = explain_substring(input, 19..26, &codebook);
+        assert_eq!(7, words.lines().count());
+        for line in words.lines() {
+            assert!(line.contains("longest"), "{}", line);
+            assert!(!line.ends_with("verbatim"), "{}", line);
+        }
+        assert!(words.starts_with("0013  \"t\"  longest \"the\" (code 1), code 1\n"));
+        assert!(words.contains("0014  \"h\"  longest \"he \" (code 19), in the code at 0013\n"));
+
+        // the encoding matches that of the whole input
+        let all = explain_substring(input, 0..input.len(), &codebook);
+        let codes = all
+            .lines()
+            .filter(|line| line.rsplit(", ").next().unwrap().starts_with("code "))
+            .count();
+        assert_eq!(compress_ex(input).1.hits, codes);
+        assert_eq!("", explain_substring(input, 4..4, &codebook));
+    }
+
+    #[test]
+    #[should_panic(expected = "range is out of bounds")]
+    fn test_explain_substring_out_of_bounds() {
+        explain_substring(b"the", 2..4, &Codebook::default());
+    }
+
+    #[test]
+    fn test_compare_codebooks() {
+        let urls: [&[u8]; 5] = [
+            b"https://github.com/silentsokolov/rust-smaz",
+            b"http://www.example.com/index.html",
+            b"https://docs.rs/smaz/latest/smaz/",
+            b"http://programming.reddit.com/r/rust",
+            b"https://www.google.com/search?q=smaz",
+        ];
+        let english = crate::BuiltinCodebook::English.codebook();
+        let url = crate::BuiltinCodebook::Url.codebook();
+        let comparison = compare_codebooks(&urls, english, url);
+        assert_eq!(5, comparison.b_wins, "{:?}", comparison);
+        assert_eq!((0, 0), (comparison.a_wins, comparison.ties));
+        assert!(comparison.b_len < comparison.a_len);
+        assert!(comparison.mean_ratio_diff > 0.0);
+
+        // the totals are those of compress_with
+        let total =
+            |codebook| -> usize { urls.iter().map(|u| compress_with(u, codebook).len()).sum() };
+        assert_eq!(
+            (total(english), total(url)),
+            (comparison.a_len, comparison.b_len)
+        );
+        assert_eq!(
+            urls.iter().map(|u| u.len()).sum::<usize>(),
+            comparison.input_len
+        );
+
+        // swapping the codebooks swaps the results
+        let swapped = compare_codebooks(&urls, url, english);
+        assert_eq!(
+            (comparison.a_len, comparison.b_wins),
+            (swapped.b_len, swapped.a_wins)
+        );
+        assert_eq!(-comparison.mean_ratio_diff, swapped.mean_ratio_diff);
+
+        let same = compare_codebooks(&[b"the end", b""], english, english);
+        assert_eq!((0, 0, 2), (same.a_wins, same.b_wins, same.ties));
+        assert_eq!(0.0, same.mean_ratio_diff);
+        assert_eq!(
+            CodebookComparison::default(),
+            compare_codebooks(&[], english, url)
+        );
+
+        let mut rng = Rng::new(196);
+        for _ in 0..200 {
+            let input = rng.bytes(600);
+            assert_eq!(
+                compress_with(&input, url).len(),
+                compressed_len(&input, url)
+            );
+        }
+    }
+
+    #[test]
+    fn test_codebook_overlap() {
+        let a = Codebook::new(["the", "and", "of", "ing", "on"]).unwrap();
+        let b = Codebook::new(["on", "http", "of", "www", "the", "com"]).unwrap();
+        let overlap = codebook_overlap(&a, &b);
+        let entries = |entries: &[&str]| -> Vec<Vec<u8>> {
+            entries.iter().map(|e| e.as_bytes().to_vec()).collect()
+        };
+        assert_eq!(entries(&["the", "of", "on"]), overlap.shared);
+        assert_eq!(entries(&["and", "ing"]), overlap.only_a);
+        assert_eq!(entries(&["http", "www", "com"]), overlap.only_b);
+        assert_eq!(3.0 / 8.0, overlap.jaccard);
+
+        let swapped = codebook_overlap(&b, &a);
+        assert_eq!(entries(&["on", "of", "the"]), swapped.shared);
+        assert_eq!(
+            (overlap.only_a, overlap.only_b),
+            (swapped.only_b, swapped.only_a)
+        );
+        assert_eq!(overlap.jaccard, swapped.jaccard);
+
+        // duplicates count once, and the escape is no entry
+        let c = Codebook::new(["of", "the", "of", "xyz"])
+            .unwrap()
+            .with_escape(3)
+            .unwrap();
+        let overlap = codebook_overlap(&a, &c);
+        assert_eq!(entries(&["the", "of"]), overlap.shared);
+        assert!(overlap.only_b.is_empty());
+        assert_eq!(2.0 / 5.0, overlap.jaccard);
+
+        let default = Codebook::default();
+        let same = codebook_overlap(&default, &default);
+        assert_eq!((CODEBOOK_SIZE, 1.0), (same.shared.len(), same.jaccard));
+        let disjoint = codebook_overlap(&a, &Codebook::new(["xyz"]).unwrap());
+        assert!(disjoint.shared.is_empty());
+        assert_eq!(0.0, disjoint.jaccard);
+    }
+}
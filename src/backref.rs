@@ -0,0 +1,214 @@
+use std::collections::HashMap;
+use std::result;
+
+use crate::codebook::Dictionary;
+use crate::reader::{write_varint, Reader};
+use crate::{compress_with, Codebook, CodebookError, DecompressError, Result, Step};
+
+/// Shortest repeat a back-reference can copy.
+const MIN_MATCH: usize = 4;
+
+/// Longest repeat a back-reference can copy.
+const MAX_MATCH: usize = MIN_MATCH + 255;
+
+/// Most earlier occurrences of a prefix tried for each position.
+const MAX_CANDIDATES: usize = 32;
+
+/// Returns the encoded length of a back-reference token.
+fn token_len(distance: usize) -> usize {
+    let mut varint = Vec::with_capacity(4);
+    write_varint(&mut varint, distance);
+    2 + varint.len()
+}
+
+/// Returns the longest earlier repeat of the input at `pos`, as its distance
+/// back and length.
+fn longest_repeat(
+    input: &[u8],
+    pos: usize,
+    seen: &HashMap<&[u8], Vec<usize>>,
+) -> Option<(usize, usize)> {
+    let candidates = seen.get(input.get(pos..pos + MIN_MATCH)?)?;
+    let rest = &input[pos..input.len().min(pos + MAX_MATCH)];
+    candidates
+        .iter()
+        .rev()
+        .take(MAX_CANDIDATES)
+        .map(|&start| {
+            let len = input[start..]
+                .iter()
+                .zip(rest)
+                .take_while(|(a, b)| a == b)
+                .count();
+            (pos - start, len)
+        })
+        .max_by_key(|&(distance, len)| (len, usize::MAX - distance))
+}
+
+/// Returns data compressed with a codebook, replacing repeats of earlier parts
+/// of the input with back-references.
+///
+/// A back-reference is the escape code of the codebook, the distance back from
+/// the current output position to the repeat as an unsigned LEB128 varint,
+/// and the length of the repeat minus 4, so from 4 to 259 bytes. The repeat
+/// may overlap the output it produces. A back-reference replaces a repeat only
+/// if it is shorter than the repeat compressed with the codebook.
+///
+/// # Errors
+///
+/// Returns [`CodebookError::MissingEscape`](enum.CodebookError.html) if the
+/// codebook has no escape code.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_backref, compress_with, decompress_backref, Codebook, CODEBOOK};
+///
+/// let codebook = Codebook::new(CODEBOOK.iter()).unwrap().with_escape(253).unwrap();
+/// let s = b"XJ-9000 unit, XJ-9000 unit, XJ-9000 unit";
+/// let compressed = compress_backref(s, &codebook).unwrap();
+/// assert!(compressed.len() < compress_with(s, &codebook).len());
+/// assert_eq!(s.to_vec(), decompress_backref(&compressed, &codebook).unwrap());
+/// ```
+pub fn compress_backref(
+    input: &[u8],
+    codebook: &Codebook,
+) -> result::Result<Vec<u8>, CodebookError> {
+    let escape = codebook.escape().ok_or(CodebookError::MissingEscape)?;
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    let mut seen: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    let mut literal = 0;
+    let mut pos = 0;
+
+    while pos < input.len() {
+        let repeat = longest_repeat(input, pos, &seen).filter(|&(distance, len)| {
+            len >= MIN_MATCH
+                && token_len(distance) < compress_with(&input[pos..pos + len], codebook).len()
+        });
+        let advance = match repeat {
+            Some((distance, len)) => {
+                out.extend(compress_with(&input[literal..pos], codebook));
+                out.push(escape);
+                write_varint(&mut out, distance);
+                out.push((len - MIN_MATCH) as u8);
+                literal = pos + len;
+                len
+            }
+            None => 1,
+        };
+        for i in pos..pos + advance {
+            if let Some(prefix) = input.get(i..i + MIN_MATCH) {
+                seen.entry(prefix).or_default().push(i);
+            }
+        }
+        pos += advance;
+    }
+    out.extend(compress_with(&input[literal..], codebook));
+    Ok(out)
+}
+
+/// Returns data decompressed from the format of
+/// [`compress_backref`](fn.compress_backref.html).
+///
+/// # Errors
+///
+/// If the compressed data is invalid, or a back-reference reaches before the
+/// start of the output, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_backref(input: &[u8], codebook: &Codebook) -> Result<Vec<u8>> {
+    let escape = codebook.escape().ok_or(DecompressError)?;
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        let step = reader.read_step()?;
+        if step != Step::Code(escape) {
+            codebook.expand(step, &mut out)?;
+            continue;
+        }
+
+        let distance = reader.read_varint()?;
+        let len = usize::from(reader.read_u8()?) + MIN_MATCH;
+        if distance == 0 || distance > out.len() {
+            return Err(DecompressError);
+        }
+        let start = out.len() - distance;
+        for i in start..start + len {
+            out.push(out[i]);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{escape_codebook, Rng, TEST_STRINGS};
+    use crate::CODEBOOK;
+
+    #[test]
+    fn test_roundtrip() {
+        let codebook = escape_codebook();
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_backref(s.as_bytes(), &codebook).unwrap();
+            assert!(compressed.len() <= compress_with(s.as_bytes(), &codebook).len());
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_backref(&compressed, &codebook).unwrap()[..]
+            );
+        }
+
+        let mut rng = Rng::new(156);
+        for _ in 0..500 {
+            let mut input = rng.bytes(200);
+            let copy = input.clone();
+            input.extend_from_slice(&copy[..copy.len() / 2]);
+            let compressed = compress_backref(&input, &codebook).unwrap();
+            assert_eq!(input, decompress_backref(&compressed, &codebook).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_repeated_phrase() {
+        let codebook = escape_codebook();
+        let s = b"id=Q7#kz/X2 ok; id=Q7#kz/X2 ok; and again id=Q7#kz/X2";
+        let compressed = compress_backref(s, &codebook).unwrap();
+        let plain = compress_with(s, &codebook);
+        assert!(
+            compressed.len() + 10 < plain.len(),
+            "{} {}",
+            compressed.len(),
+            plain.len()
+        );
+        assert_eq!(
+            s.to_vec(),
+            decompress_backref(&compressed, &codebook).unwrap()
+        );
+
+        // an overlapping repeat of a run
+        let s = [b'~'; 300];
+        let compressed = compress_backref(&s, &codebook).unwrap();
+        assert_eq!(vec![254, b'~', 253, 1, 255, 253, 1, 36], compressed);
+        assert_eq!(
+            s.to_vec(),
+            decompress_backref(&compressed, &codebook).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let codebook = escape_codebook();
+        assert!(decompress_backref(&[253, 1, 0], &codebook).is_err());
+        assert!(decompress_backref(&[254, b'a', 253, 0, 0], &codebook).is_err());
+        assert!(decompress_backref(&[254, b'a', 253, 2, 0], &codebook).is_err());
+        assert!(decompress_backref(&[254, b'a', 253, 1], &codebook).is_err());
+        assert_eq!(
+            b"aaaaa".to_vec(),
+            decompress_backref(&[254, b'a', 253, 1, 0], &codebook).unwrap()
+        );
+        assert_eq!(
+            Err(CodebookError::MissingEscape),
+            compress_backref(b"", &Codebook::new(CODEBOOK.iter()).unwrap())
+        );
+    }
+}
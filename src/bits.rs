@@ -0,0 +1,845 @@
+use crate::codebook::{DefaultCodebook, Dictionary};
+use crate::{compress, decompress, Codebook, DecompressError, Result, Step, Steps, CODEBOOK_SIZE};
+
+/// Appends bits to a byte vector, most significant bit first.
+#[derive(Debug, Default)]
+struct BitWriter {
+    out: Vec<u8>,
+    len: usize,
+}
+
+impl BitWriter {
+    /// Appends the `n` low bits of `value`, highest first.
+    fn write(&mut self, value: u32, n: usize) {
+        for i in (0..n).rev() {
+            if self.len % 8 == 0 {
+                self.out.push(0);
+            }
+            if value >> i & 1 == 1 {
+                *self.out.last_mut().unwrap() |= 0x80 >> (self.len % 8);
+            }
+            self.len += 1;
+        }
+    }
+
+    /// Returns the bytes, padding the last one with one bits.
+    fn finish(mut self) -> Vec<u8> {
+        let pad = (8 - self.len % 8) % 8;
+        self.write(0xff, pad);
+        self.out
+    }
+}
+
+/// Reads bits from a byte slice, most significant bit first.
+#[derive(Debug)]
+struct BitReader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> BitReader<'a> {
+    fn remaining(&self) -> usize {
+        self.input.len() * 8 - self.pos
+    }
+
+    /// Reads `n` bits as the low bits of a value.
+    fn read(&mut self, n: usize) -> Result<u32> {
+        if n > self.remaining() {
+            return Err(DecompressError);
+        }
+        let mut value = 0;
+        for _ in 0..n {
+            let bit = self.input[self.pos / 8] >> (7 - self.pos % 8) & 1;
+            value = value << 1 | bit as u32;
+            self.pos += 1;
+        }
+        Ok(value)
+    }
+
+    /// Returns `true` if only padding is left: fewer than 8 bits, all set.
+    fn at_padding(&self) -> bool {
+        let n = self.remaining();
+        n < 8 && (n == 0 || self.input[self.input.len() - 1] & (0xff >> (8 - n)) == 0xff >> (8 - n))
+    }
+}
+
+/// Codes of the default codebook by decreasing frequency in a sample of
+/// English prose, so the prefix code gives common codes fewer bits.
+static BY_RANK: [u8; CODEBOOK_SIZE] = [
+    6, 60, 13, 65, 45, 2, 1, 22, 71, 30, 23, 10, 11, 38, 121, 4, 19, 62, 36, 70, 83, 3, 12, 41, 87,
+    63, 15, 24, 0, 59, 90, 208, 9, 25, 35, 44, 91, 28, 47, 82, 96, 77, 148, 7, 32, 75, 102, 106,
+    205, 110, 135, 146, 64, 68, 85, 114, 139, 145, 150, 8, 69, 89, 108, 115, 163, 26, 72, 80, 120,
+    134, 152, 156, 184, 187, 214, 236, 50, 92, 33, 42, 88, 95, 133, 137, 173, 224, 29, 34, 54, 74,
+    79, 104, 130, 132, 165, 170, 174, 180, 194, 207, 212, 221, 231, 235, 240, 14, 16, 20, 37, 55,
+    113, 117, 123, 131, 151, 153, 161, 164, 200, 209, 249, 31, 46, 84, 109, 124, 125, 138, 149,
+    154, 175, 177, 185, 189, 191, 201, 223, 53, 58, 73, 105, 111, 116, 136, 142, 159, 56, 98, 143,
+    179, 188, 232, 233, 237, 250, 17, 66, 86, 99, 147, 162, 172, 183, 186, 190, 193, 199, 202, 213,
+    226, 248, 27, 48, 51, 78, 94, 128, 129, 141, 157, 171, 181, 195, 196, 216, 243, 247, 18, 93,
+    112, 118, 178, 198, 215, 217, 219, 227, 239, 242, 39, 61, 76, 97, 119, 122, 126, 140, 166, 229,
+    245, 246, 5, 43, 107, 127, 155, 160, 176, 192, 203, 206, 218, 220, 228, 234, 238, 251, 252, 21,
+    40, 49, 52, 57, 67, 81, 100, 101, 103, 144, 158, 167, 168, 169, 182, 197, 204, 210, 211, 222,
+    225, 230, 241, 244, 253,
+];
+
+/// Rank of each code, the inverse of `BY_RANK`.
+static RANKS: [u8; CODEBOOK_SIZE] = ranks();
+
+const fn ranks() -> [u8; CODEBOOK_SIZE] {
+    let mut ranks = [0; CODEBOOK_SIZE];
+    let mut i = 0;
+    while i < BY_RANK.len() {
+        ranks[BY_RANK[i] as usize] = i as u8;
+        i += 1;
+    }
+    ranks
+}
+
+/// Longest verbatim run of a single token.
+const MAX_RUN: usize = 17;
+
+/// Returns the number of bits a step takes in the bitstream.
+fn bit_len(step: Step) -> usize {
+    match step {
+        Step::Code(code) => match RANKS[code as usize] {
+            0..=7 => 5,
+            8..=39 => 7,
+            40..=167 => 9,
+            _ => 10,
+        },
+        Step::Verbatim(bytes) => bytes
+            .chunks(MAX_RUN)
+            .map(|run| {
+                if run.len() == 1 {
+                    12
+                } else {
+                    8 + 8 * run.len()
+                }
+            })
+            .sum(),
+    }
+}
+
+fn write_code(writer: &mut BitWriter, code: u8) {
+    let rank = RANKS[code as usize] as u32;
+    match rank {
+        0..=7 => writer.write(rank, 5),
+        8..=39 => writer.write(0b01 << 5 | (rank - 8), 7),
+        40..=167 => writer.write(0b10 << 7 | (rank - 40), 9),
+        _ => writer.write(0b110 << 7 | (rank - 168), 10),
+    }
+}
+
+/// Returns data compressed into a bitstream, for higher density than
+/// [`compress`](fn.compress.html) at the cost of slower decoding.
+///
+/// This format is experimental. Output is usually a few percent smaller on
+/// English text, and more when there are many verbatim bytes.
+///
+/// The input is split into codes and verbatim runs exactly like
+/// [`compress`](fn.compress.html), but each token takes a prefix code, written
+/// most significant bit first:
+///
+/// | Bits | Token |
+/// | --- | --- |
+/// | `00` + 3 bits | code of rank 0 to 7 |
+/// | `01` + 5 bits | code of rank 8 to 39, minus 8 |
+/// | `10` + 7 bits | code of rank 40 to 167, minus 40 |
+/// | `110` + 7 bits | code of rank 168 to 253, minus 168 |
+/// | `1110` + 8 bits | a single verbatim byte |
+/// | `1111` + 4 bits + bytes | a verbatim run of 2 to 17 bytes, its length minus two, then its bytes |
+///
+/// Codes are ranked by their frequency in English prose, so the most common
+/// take 5 bits. The last byte is padded with one bits, which no token can be
+/// confused with since all tokens starting with a one bit take at least 8
+/// bits.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_bits, decompress_bits};
+///
+/// let s = b"1000 numbers 2000 will 10 20 30 compress very little";
+/// let compressed = compress_bits(s);
+/// assert!(compressed.len() < compress(s).len());
+/// assert_eq!(s.to_vec(), decompress_bits(&compressed).unwrap());
+/// ```
+pub fn compress_bits(input: &[u8]) -> Vec<u8> {
+    let mut writer = BitWriter::default();
+    for step in Steps::new(input, &DefaultCodebook) {
+        match step {
+            Step::Code(code) => write_code(&mut writer, code),
+            Step::Verbatim(bytes) => {
+                for run in bytes.chunks(MAX_RUN) {
+                    if let [b] = run {
+                        writer.write(0b1110 << 8 | *b as u32, 12);
+                        continue;
+                    }
+                    writer.write(0b1111 << 4 | (run.len() - 2) as u32, 8);
+                    for b in run {
+                        writer.write(*b as u32, 8);
+                    }
+                }
+            }
+        }
+    }
+    writer.finish()
+}
+
+/// Returns data decompressed from the bitstream of
+/// [`compress_bits`](fn.compress_bits.html).
+///
+/// # Errors
+///
+/// If the compressed data is truncated, ends with padding other than one bits,
+/// or holds a code missing from the codebook, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_bits(input: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(input.len().saturating_mul(4));
+    let mut reader = BitReader { input, pos: 0 };
+
+    while !reader.at_padding() {
+        let rank = match reader.read(2)? {
+            0b00 => reader.read(3)?,
+            0b01 => reader.read(5)? + 8,
+            0b10 => reader.read(7)? + 40,
+            _ if reader.read(1)? == 0 => reader.read(7)? + 168,
+            _ => {
+                let len = if reader.read(1)? == 0 {
+                    1
+                } else {
+                    reader.read(4)? + 2
+                };
+                for _ in 0..len {
+                    out.push(reader.read(8)? as u8);
+                }
+                continue;
+            }
+        };
+        let code = *BY_RANK.get(rank as usize).ok_or(DecompressError)?;
+        DefaultCodebook.expand(Step::Code(code), &mut out)?;
+    }
+    Ok(out)
+}
+
+/// Tag of [`compress_adaptive`](fn.compress_adaptive.html) output in the
+/// format of [`compress`](fn.compress.html).
+const TAG_BYTES: u8 = 0;
+
+/// Tag of [`compress_adaptive`](fn.compress_adaptive.html) output in the
+/// format of [`compress_bits`](fn.compress_bits.html).
+const TAG_BITS: u8 = 1;
+
+/// Returns data compressed in whichever of the formats of
+/// [`compress`](fn.compress.html) and [`compress_bits`](fn.compress_bits.html)
+/// is shorter, after a byte telling which.
+///
+/// The bitstream gives codes fewer bits on English text, and verbatim bytes
+/// a shorter marker, but its runs hold at most 17 bytes, so long stretches
+/// of bytes matching no entry are shorter in the byte format. The lengths of
+/// both are computed from a single pass over the input, then only the shorter
+/// is written, the byte format on a tie as it decodes faster. The first byte
+/// is `0` for the byte format and `1` for the bitstream.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_adaptive, compress_bits, decompress_adaptive};
+///
+/// let s = b"the poem of the people";
+/// let compressed = compress_adaptive(s);
+/// assert_eq!([&[1][..], &compress_bits(s)].concat(), compressed);
+/// assert_eq!(s.to_vec(), decompress_adaptive(&compressed).unwrap());
+///
+/// let blob = vec![0x80; 1000];
+/// assert_eq!([&[0][..], &compress(&blob)].concat(), compress_adaptive(&blob));
+/// ```
+pub fn compress_adaptive(input: &[u8]) -> Vec<u8> {
+    let (bytes, bits) = Steps::new(input, &DefaultCodebook).fold((0, 0), |(bytes, bits), step| {
+        (bytes + step.encoded_len(), bits + bit_len(step))
+    });
+    if bits.div_ceil(8) < bytes {
+        [&[TAG_BITS][..], &compress_bits(input)].concat()
+    } else {
+        [&[TAG_BYTES][..], &compress(input)].concat()
+    }
+}
+
+/// Returns data decompressed from the output of
+/// [`compress_adaptive`](fn.compress_adaptive.html), in the format its first
+/// byte tells.
+///
+/// # Errors
+///
+/// If the data is empty, its first byte is neither `0` nor `1`, or the rest
+/// is invalid in the format it tells, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_adaptive(input: &[u8]) -> Result<Vec<u8>> {
+    match input.split_first() {
+        Some((&TAG_BYTES, data)) => decompress(data),
+        Some((&TAG_BITS, data)) => decompress_bits(data),
+        _ => Err(DecompressError),
+    }
+}
+
+/// Longest prefix code of [`compress_bits_with`](fn.compress_bits_with.html).
+const MAX_BITS: usize = 15;
+
+/// Canonical prefix code over the entries of a codebook, then a single
+/// verbatim byte, a verbatim run and the padding.
+#[derive(Debug)]
+struct Prefix {
+    /// Code and length of each symbol, with a zero length for the escape.
+    codes: Vec<(u32, usize)>,
+    /// Number of symbols of each length.
+    counts: [u32; MAX_BITS + 1],
+    /// Symbols in code order.
+    sorted: Vec<usize>,
+}
+
+impl Prefix {
+    fn new(codebook: &Codebook) -> Prefix {
+        let n = codebook.len();
+        let weight = |i: usize| match codebook.frequencies() {
+            Some(frequencies) => frequencies[i].saturating_add(1),
+            None => 1,
+        };
+        let present: Vec<usize> = (0..n)
+            .filter(|&i| codebook.escape() != Some(i as u8))
+            .collect();
+        let total = present
+            .iter()
+            .fold(0u64, |t, &i| t.saturating_add(weight(i)));
+        let average = (total / present.len().max(1) as u64).max(1);
+
+        let mut weights = vec![0; n + 3];
+        for &i in &present {
+            weights[i] = weight(i);
+        }
+        weights[n] = average;
+        weights[n + 1] = average;
+        weights[n + 2] = 1;
+        let symbols: Vec<usize> = present.into_iter().chain(n..n + 3).collect();
+        let pad = n + 2;
+
+        let mut lengths: Vec<usize> = limited_code_lengths(&weights, MAX_BITS)
+            .into_iter()
+            .map(usize::from)
+            .collect();
+        // The padding takes the last code of the longest length, all one bits.
+        let longest = *lengths.iter().max().unwrap();
+        if lengths[pad] < longest {
+            let s = (0..pad).rev().find(|&s| lengths[s] == longest).unwrap();
+            lengths.swap(s, pad);
+        }
+
+        let mut sorted = symbols;
+        sorted.sort_by_key(|&s| (lengths[s], s));
+        let mut counts = [0; MAX_BITS + 1];
+        let mut codes = vec![(0, 0); n + 3];
+        let (mut code, mut len) = (0, 0);
+        for &s in &sorted {
+            code <<= lengths[s] - len;
+            len = lengths[s];
+            codes[s] = (code, len);
+            counts[len] += 1;
+            code += 1;
+        }
+        Prefix {
+            codes,
+            counts,
+            sorted,
+        }
+    }
+
+    fn write(&self, writer: &mut BitWriter, symbol: usize) {
+        let (code, len) = self.codes[symbol];
+        writer.write(code, len);
+    }
+
+    fn read(&self, reader: &mut BitReader) -> Result<usize> {
+        let (mut code, mut first, mut index) = (0, 0, 0);
+        for &count in &self.counts[1..] {
+            code |= reader.read(1)?;
+            if code - first < count {
+                return Ok(self.sorted[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err(DecompressError)
+    }
+}
+
+/// Longest code of [`optimal_code_lengths`](fn.optimal_code_lengths.html).
+const MAX_CODE_LEN: usize = 12;
+
+/// Returns the code lengths of an optimal prefix code for symbols with the
+/// given frequencies, none longer than 12 bits.
+///
+/// Among the prefix codes with no code longer than 12 bits, the lengths are
+/// those of one with the smallest total length of the symbols weighed by their
+/// frequencies, found with the package-merge algorithm. When the Huffman code
+/// is no longer than 12 bits, that total is the one of the Huffman code.
+/// Symbols with a zero frequency get no code and a zero length, and a single
+/// symbol with a frequency gets a code of one bit. With two or more, the
+/// lengths `l` satisfy the Kraft equality, the sum of `2^-l` being exactly 1.
+///
+/// # Panics
+///
+/// Panics if more than 4096 symbols have a frequency, as 12 bits cannot give
+/// them all a code.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::optimal_code_lengths;
+///
+/// assert_eq!(vec![4, 4, 3, 3, 3, 1], optimal_code_lengths(&[5, 9, 12, 13, 16, 45]));
+/// assert_eq!(vec![0, 1, 0], optimal_code_lengths(&[0, 7, 0]));
+/// ```
+pub fn optimal_code_lengths(freqs: &[u32]) -> Vec<u8> {
+    assert!(
+        freqs.iter().filter(|&&f| f > 0).count() <= 1 << MAX_CODE_LEN,
+        "more than 4096 symbols have a frequency"
+    );
+    let weights: Vec<u64> = freqs.iter().map(|&f| u64::from(f)).collect();
+    limited_code_lengths(&weights, MAX_CODE_LEN)
+}
+
+/// Returns the lengths of an optimal prefix code for symbols with the given
+/// weights, none longer than `max_len` bits, by package-merge. Symbols with a
+/// zero weight get a zero length.
+fn limited_code_lengths(weights: &[u64], max_len: usize) -> Vec<u8> {
+    enum Node {
+        Leaf(usize),
+        Package(usize, usize),
+    }
+
+    let mut lengths = vec![0; weights.len()];
+    let mut leaves: Vec<(u64, usize)> = (0..weights.len())
+        .filter(|&s| weights[s] > 0)
+        .map(|s| (weights[s], s))
+        .collect();
+    debug_assert!(leaves.len() <= 1 << max_len);
+    if leaves.len() < 2 {
+        if let Some(&(_, s)) = leaves.first() {
+            lengths[s] = 1;
+        }
+        return lengths;
+    }
+    leaves.sort_unstable();
+
+    // Each item is the weight of a node, either a symbol or a package of two
+    // items of the level below.
+    let mut nodes: Vec<Node> = leaves.iter().map(|&(_, s)| Node::Leaf(s)).collect();
+    let leaves: Vec<(u64, usize)> = leaves
+        .iter()
+        .enumerate()
+        .map(|(i, &(w, _))| (w, i))
+        .collect();
+    let mut items = leaves.clone();
+    for _ in 1..max_len {
+        let mut level = leaves.clone();
+        for pair in items.chunks_exact(2) {
+            nodes.push(Node::Package(pair[0].1, pair[1].1));
+            level.push((pair[0].0.saturating_add(pair[1].0), nodes.len() - 1));
+        }
+        // stable, so symbols come before packages of the same weight
+        level.sort_by_key(|&(weight, _)| weight);
+        items = level;
+    }
+
+    // A symbol's length is the number of times it is in the first 2n - 2
+    // items of the top level.
+    let mut stack: Vec<usize> = items[..2 * leaves.len() - 2]
+        .iter()
+        .map(|&(_, node)| node)
+        .collect();
+    while let Some(node) = stack.pop() {
+        match nodes[node] {
+            Node::Leaf(s) => lengths[s] += 1,
+            Node::Package(a, b) => stack.extend([a, b]),
+        }
+    }
+    lengths
+}
+
+/// Returns data compressed into a bitstream like
+/// [`compress_bits`](fn.compress_bits.html), with a Huffman code built from the
+/// frequencies of `codebook`.
+///
+/// Each entry weighs its frequency plus one, or one if the codebook has no
+/// [`frequencies`](struct.Codebook.html#method.frequencies), and a single
+/// verbatim byte and a verbatim run each weigh the average entry. The escape
+/// entry, if any, takes no code. Code lengths are those of an optimal prefix
+/// code over these weights and an extra padding symbol of weight one, with
+/// no code longer than 15 bits, as in
+/// [`optimal_code_lengths`](fn.optimal_code_lengths.html).
+///
+/// Codes are assigned canonically, by increasing length and then by code, with
+/// the single verbatim byte and the verbatim run after all entries. A single
+/// verbatim byte is followed by the byte, and a verbatim run by 4 bits of its
+/// length minus two and its 2 to 17 bytes. The padding symbol always gets the
+/// code of all one bits and is never written, so the last byte can be padded
+/// with one bits like [`compress_bits`](fn.compress_bits.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_bits_with, decompress_bits_with, Codebook};
+///
+/// let codebook = Codebook::new(["ab", "cd"]).unwrap();
+/// let codebook = codebook.with_frequencies(&[1000, 0]).unwrap();
+/// let compressed = compress_bits_with(b"abababcd", &codebook);
+/// assert_eq!(1, compressed.len());
+/// assert_eq!(b"abababcd".to_vec(), decompress_bits_with(&compressed, &codebook).unwrap());
+/// ```
+pub fn compress_bits_with(input: &[u8], codebook: &Codebook) -> Vec<u8> {
+    let prefix = Prefix::new(codebook);
+    let verbatim = codebook.len();
+    let mut writer = BitWriter::default();
+    for step in Steps::new(input, codebook) {
+        match step {
+            Step::Code(code) => prefix.write(&mut writer, code as usize),
+            Step::Verbatim(bytes) => {
+                for run in bytes.chunks(MAX_RUN) {
+                    if let [b] = run {
+                        prefix.write(&mut writer, verbatim);
+                        writer.write(*b as u32, 8);
+                        continue;
+                    }
+                    prefix.write(&mut writer, verbatim + 1);
+                    writer.write((run.len() - 2) as u32, 4);
+                    for b in run {
+                        writer.write(*b as u32, 8);
+                    }
+                }
+            }
+        }
+    }
+    writer.finish()
+}
+
+/// Returns data decompressed from the bitstream of
+/// [`compress_bits_with`](fn.compress_bits_with.html), which must be given the
+/// same codebook and frequencies.
+///
+/// # Errors
+///
+/// If the compressed data is truncated, ends with padding other than one bits,
+/// or holds the padding code, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_bits_with(input: &[u8], codebook: &Codebook) -> Result<Vec<u8>> {
+    let prefix = Prefix::new(codebook);
+    let verbatim = codebook.len();
+    let mut out = Vec::with_capacity(input.len().saturating_mul(4));
+    let mut reader = BitReader { input, pos: 0 };
+
+    while !reader.at_padding() {
+        let symbol = prefix.read(&mut reader)?;
+        let len = match symbol {
+            s if s < verbatim => {
+                codebook.expand(Step::Code(s as u8), &mut out)?;
+                continue;
+            }
+            s if s == verbatim => 1,
+            s if s == verbatim + 1 => reader.read(4)? + 2,
+            _ => return Err(DecompressError),
+        };
+        for _ in 0..len {
+            out.push(reader.read(8)? as u8);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cmp::Reverse;
+    use std::collections::BinaryHeap;
+
+    use super::*;
+    use crate::tests::{inputs, Rng, TEST_STRINGS};
+
+    /// Returns the bytes of a string of bits, ignoring spaces.
+    fn from_bits(bits: &str) -> Vec<u8> {
+        let bits: Vec<u8> = bits
+            .bytes()
+            .filter(|b| *b != b' ')
+            .map(|b| b - b'0')
+            .collect();
+        bits.chunks(8)
+            .map(|c| c.iter().fold(0, |v, b| v << 1 | b))
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_bits(s.as_bytes());
+            assert_eq!(s.as_bytes(), &decompress_bits(&compressed).unwrap()[..]);
+        }
+
+        let mut rng = Rng::new(143);
+        for _ in 0..1000 {
+            let input = rng.bytes(600);
+            assert_eq!(input, decompress_bits(&compress_bits(&input)).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_ratio() {
+        let prose: Vec<&str> = TEST_STRINGS[..9]
+            .iter()
+            .copied()
+            .filter(|s| !s.is_empty())
+            .collect();
+        let bits: usize = prose
+            .iter()
+            .map(|s| compress_bits(s.as_bytes()).len())
+            .sum();
+        let bytes: usize = prose.iter().map(|s| compress(s.as_bytes()).len()).sum();
+        assert!(bits < bytes, "{} bytes vs {} bytes", bits, bytes);
+    }
+
+    #[test]
+    fn test_layout() {
+        // "o" has rank 0
+        assert_eq!(from_bits("00 000 111"), compress_bits(b"o"));
+        // "e" has rank 5 and "\xff" is a single verbatim byte
+        assert_eq!(
+            from_bits("00 101 1110 11111111 1111111"),
+            compress_bits(b"e\xff")
+        );
+        assert!(compress_bits(b"").is_empty());
+
+        for code in 0..CODEBOOK_SIZE as u8 {
+            assert_eq!(code, BY_RANK[RANKS[code as usize] as usize]);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let compressed = compress_bits(b"this is a small string");
+        for i in 0..compressed.len() {
+            let truncated = &compressed[..i];
+            if let Ok(v) = decompress_bits(truncated) {
+                assert!(v.len() < 22);
+            }
+        }
+        // rank 254 does not exist
+        assert!(decompress_bits(&from_bits("110 1010110 111111")).is_err());
+        assert_eq!(
+            vec![253],
+            compress(&decompress_bits(&from_bits("110 1010101 111111")).unwrap())
+        );
+        // padding must be one bits
+        assert!(decompress_bits(&from_bits("00 000 011")).is_err());
+        assert!(decompress_bits(&from_bits("1111 0000")).is_err());
+        assert_eq!(
+            b"o".to_vec(),
+            decompress_bits(&from_bits("00 000 111")).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_frequencies() {
+        let codebook = Codebook::default();
+        let mut frequencies = vec![0; codebook.len()];
+        for s in TEST_STRINGS.iter() {
+            for step in Steps::new(s.as_bytes(), &codebook) {
+                if let Step::Code(code) = step {
+                    frequencies[code as usize] += 1;
+                }
+            }
+        }
+        let hinted = codebook.clone().with_frequencies(&frequencies).unwrap();
+
+        let size = |codebook: &Codebook| -> usize {
+            TEST_STRINGS
+                .iter()
+                .map(|s| compress_bits_with(s.as_bytes(), codebook).len())
+                .sum()
+        };
+        let (plain, biased) = (size(&codebook), size(&hinted));
+        assert!(biased < plain, "{} bytes vs {} bytes", biased, plain);
+
+        let mut rng = Rng::new(165);
+        for codebook in [&codebook, &hinted] {
+            for s in TEST_STRINGS.iter() {
+                let compressed = compress_bits_with(s.as_bytes(), codebook);
+                assert_eq!(
+                    s.as_bytes(),
+                    &decompress_bits_with(&compressed, codebook).unwrap()[..]
+                );
+            }
+            for _ in 0..300 {
+                let input = rng.bytes(600);
+                let compressed = compress_bits_with(&input, codebook);
+                assert_eq!(input, decompress_bits_with(&compressed, codebook).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn test_prefix() {
+        let skewed: Vec<u64> = (0..254).map(|i| 1 << (i % 60)).collect();
+        let codebook = Codebook::default().with_frequencies(&skewed).unwrap();
+        let escaped = Codebook::new(["ab", "cd", "|"])
+            .unwrap()
+            .with_escape(2)
+            .unwrap();
+        for codebook in [Codebook::default(), codebook, escaped] {
+            let prefix = Prefix::new(&codebook);
+            let lengths: Vec<usize> = prefix.codes.iter().map(|c| c.1).collect();
+            assert!(lengths.iter().all(|&len| len <= MAX_BITS));
+            // the padding has the code of all one bits
+            let (code, len) = *prefix.codes.last().unwrap();
+            assert_eq!((1 << len) - 1, code);
+            // the code is complete
+            let kraft: f64 = lengths
+                .iter()
+                .filter(|&&len| len > 0)
+                .map(|&len| 0.5f64.powi(len as i32))
+                .sum();
+            assert_eq!(1.0, kraft);
+        }
+        // the padding code is never valid data
+        let codebook = Codebook::new(["ab", "cd"]).unwrap();
+        assert!(decompress_bits_with(&[0xff, 0xff], &codebook).is_err());
+        assert!(decompress_bits_with(&[0xff], &codebook).is_err());
+    }
+
+    /// Returns the Huffman code length of each of `symbols`, and zero for others.
+    fn huffman(weights: &[u64], symbols: &[usize]) -> Vec<usize> {
+        let mut parents = vec![usize::MAX; weights.len()];
+        let mut heap: BinaryHeap<_> = symbols.iter().map(|&s| Reverse((weights[s], s))).collect();
+        while heap.len() > 1 {
+            let Reverse((a, x)) = heap.pop().unwrap();
+            let Reverse((b, y)) = heap.pop().unwrap();
+            let node = parents.len();
+            parents.push(usize::MAX);
+            parents[x] = node;
+            parents[y] = node;
+            heap.push(Reverse((a.saturating_add(b), node)));
+        }
+
+        let mut lengths = vec![0; weights.len()];
+        for &s in symbols {
+            let mut node = s;
+            while parents[node] != usize::MAX {
+                node = parents[node];
+                lengths[s] += 1;
+            }
+        }
+        lengths
+    }
+
+    #[test]
+    fn test_optimal_code_lengths() {
+        fn kraft(lengths: &[u8]) -> f64 {
+            lengths
+                .iter()
+                .filter(|&&len| len > 0)
+                .map(|&len| 0.5f64.powi(len as i32))
+                .sum()
+        }
+        fn cost(freqs: &[u32], lengths: &[u8]) -> u64 {
+            freqs
+                .iter()
+                .zip(lengths)
+                .map(|(&f, &l)| f as u64 * l as u64)
+                .sum()
+        }
+
+        assert_eq!(vec![1, 1], optimal_code_lengths(&[1, 1000]));
+        assert_eq!(vec![2, 2, 2, 2], optimal_code_lengths(&[3, 3, 3, 3]));
+        assert_eq!(vec![1, 2, 3, 3], optimal_code_lengths(&[8, 4, 2, 2]));
+        assert_eq!(vec![0, 0], optimal_code_lengths(&[0, 0]));
+        assert!(optimal_code_lengths(&[]).is_empty());
+
+        // Fibonacci frequencies give a Huffman code 19 bits deep
+        let mut fibonacci = vec![1u32, 1];
+        while fibonacci.len() < 20 {
+            fibonacci.push(fibonacci[fibonacci.len() - 1] + fibonacci[fibonacci.len() - 2]);
+        }
+        let lengths = optimal_code_lengths(&fibonacci);
+        assert_eq!(Some(&12), lengths.iter().max());
+        assert_eq!(1.0, kraft(&lengths));
+
+        // without the limit, the total equals that of a Huffman code
+        let mut rng = Rng::new(188);
+        for _ in 0..200 {
+            let n = 2 + rng.next_u64() as usize % 300;
+            let freqs: Vec<u32> = (0..n)
+                .map(|_| (rng.next_u64() % 100) as u32 * (rng.next_u64() % 2) as u32)
+                .collect();
+            let lengths = optimal_code_lengths(&freqs);
+            assert!(lengths.iter().all(|&len| len as usize <= MAX_CODE_LEN));
+            assert!(freqs
+                .iter()
+                .zip(&lengths)
+                .all(|(&f, &l)| (f == 0) == (l == 0)));
+
+            let weights: Vec<u64> = freqs.iter().map(|&f| f as u64).collect();
+            let symbols: Vec<usize> = (0..n).filter(|&s| freqs[s] > 0).collect();
+            if symbols.len() >= 2 {
+                assert_eq!(1.0, kraft(&lengths));
+                let huffman: Vec<u8> = huffman(&weights, &symbols)
+                    .iter()
+                    .map(|&l| l as u8)
+                    .collect();
+                if huffman.iter().all(|&len| len as usize <= MAX_CODE_LEN) {
+                    assert_eq!(cost(&freqs, &huffman), cost(&freqs, &lengths));
+                }
+            }
+        }
+
+        let full = optimal_code_lengths(&[1; 4096]);
+        assert!(full.iter().all(|&len| len == 12));
+    }
+
+    #[test]
+    #[should_panic(expected = "more than 4096 symbols have a frequency")]
+    fn test_optimal_code_lengths_too_many() {
+        optimal_code_lengths(&[1; 4097]);
+    }
+
+    #[test]
+    fn test_adaptive() {
+        let inputs = inputs(199);
+        for input in &inputs {
+            let compressed = compress_adaptive(input);
+            assert_eq!(*input, decompress_adaptive(&compressed).unwrap());
+            // the estimate is exact, so the shorter format is picked
+            let (bytes, bits) = (compress(input).len(), compress_bits(input).len());
+            assert_eq!(1 + bytes.min(bits), compressed.len());
+            assert_eq!(bits < bytes, compressed[0] == TAG_BITS);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_choice() {
+        // mostly codes of the shortest bit length
+        let text = b"the poem of the people";
+        let compressed = compress_adaptive(text);
+        assert_eq!(TAG_BITS, compressed[0]);
+        // 10 bytes and the tag, where the byte format takes 12 bytes
+        assert_eq!(11, compressed.len());
+        assert_eq!(text.to_vec(), decompress_adaptive(&compressed).unwrap());
+
+        // long ranges of bytes matching no entry take fewer markers as runs
+        let mut rng = Rng::new(199);
+        let noise: Vec<u8> = (0..2000).map(|_| rng.next_u64() as u8 | 0x80).collect();
+        let compressed = compress_adaptive(&noise);
+        assert_eq!(TAG_BYTES, compressed[0]);
+        assert_eq!(noise, decompress_adaptive(&compressed).unwrap());
+
+        assert_eq!(vec![TAG_BYTES], compress_adaptive(b""));
+        assert!(decompress_adaptive(&[TAG_BYTES]).unwrap().is_empty());
+        assert!(decompress_adaptive(&[]).is_err());
+        assert!(decompress_adaptive(&[2, 0]).is_err());
+        assert!(decompress_adaptive(&[TAG_BYTES, 254]).is_err());
+    }
+}
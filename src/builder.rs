@@ -0,0 +1,257 @@
+use std::collections::BTreeMap;
+
+use crate::codebook::MAX_ENTRIES;
+use crate::{Codebook, Step, Steps, MAX_MATCH_LEN};
+
+/// Builds a codebook from sample data.
+///
+/// Every substring of up to 7 bytes of the samples is counted, and the 254
+/// substrings saving the most bytes, their count times their length, become
+/// the entries, most saving first. Ties are broken by the bytes of the
+/// substrings, so the same samples always build the same codebook.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_with, decompress_with, CodebookBuilder};
+///
+/// let codebook = CodebookBuilder::new()
+///     .add(["GET /index.html", "GET /about.html"])
+///     .add_weighted(["POST /login"], 2.0)
+///     .build();
+/// let compressed = compress_with(b"GET /login.html", &codebook);
+/// assert!(compressed.len() < 5);
+/// assert_eq!(b"GET /login.html".to_vec(), decompress_with(&compressed, &codebook).unwrap());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CodebookBuilder {
+    counts: BTreeMap<Vec<u8>, f64>,
+}
+
+impl CodebookBuilder {
+    /// Creates a builder with no samples.
+    pub fn new() -> CodebookBuilder {
+        CodebookBuilder::default()
+    }
+
+    /// Adds samples, each occurrence of a substring counting once.
+    pub fn add<I, S>(&mut self, samples: I) -> &mut CodebookBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<[u8]>,
+    {
+        self.add_weighted(samples, 1.0)
+    }
+
+    /// Adds samples, each occurrence of a substring counting `weight` times.
+    ///
+    /// Giving recent samples a higher weight than older ones tunes the
+    /// codebook to the current data.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `weight` is negative or not finite.
+    pub fn add_weighted<I, S>(&mut self, samples: I, weight: f64) -> &mut CodebookBuilder
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<[u8]>,
+    {
+        assert!(
+            weight.is_finite() && weight >= 0.0,
+            "weight must be finite and not negative"
+        );
+        for sample in samples {
+            let sample = sample.as_ref();
+            for start in 0..sample.len() {
+                for end in start + 1..=sample.len().min(start + MAX_MATCH_LEN) {
+                    *self
+                        .counts
+                        .entry(sample[start..end].to_vec())
+                        .or_insert(0.0) += weight;
+                }
+            }
+        }
+        self
+    }
+
+    /// Returns the codebook of the substrings saving the most bytes.
+    pub fn build(&self) -> Codebook {
+        Codebook::new(self.ranked(MAX_ENTRIES)).unwrap()
+    }
+
+    /// Returns up to `n` substrings saving the most bytes, most saving first.
+    fn ranked(&self, n: usize) -> Vec<&[u8]> {
+        let mut scored: Vec<(f64, &[u8])> = self
+            .counts
+            .iter()
+            .filter(|(_, count)| **count > 0.0)
+            .map(|(entry, count)| (count * entry.len() as f64, &entry[..]))
+            .collect();
+        scored.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(b.1)));
+        scored.truncate(n);
+        scored.into_iter().map(|(_, entry)| entry).collect()
+    }
+}
+
+/// Returns the fewest entries a codebook built from the corpus needs so that
+/// codes cover at least `target_coverage` of its bytes, up to 254.
+///
+/// The codebooks tried are the first entries of the one
+/// [`CodebookBuilder`](struct.CodebookBuilder.html) builds from the corpus,
+/// and coverage is the fraction of the corpus bytes compressed to codes rather
+/// than stored verbatim. If even 254 entries fall short of the target, 254 is
+/// returned. A result of at most 15 means a
+/// [`compress_nibble`](fn.compress_nibble.html) codebook would do.
+///
+/// The builder ranks overlapping substrings on their own, so the result is
+/// usually well above the number of distinct phrases of the corpus. Every size
+/// is tried in turn, since with greedy matching one more entry can lower the
+/// coverage, so this compresses the corpus up to 254 times.
+///
+/// # Panics
+///
+/// Panics if `target_coverage` is not between 0 and 1.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::suggest_codebook_size;
+///
+/// let corpus = [&b"yes"[..], b"no", b"yes", b"yes"];
+/// assert_eq!(7, suggest_codebook_size(&corpus, 1.0));
+/// ```
+pub fn suggest_codebook_size(corpus: &[&[u8]], target_coverage: f64) -> usize {
+    assert!(
+        (0.0..=1.0).contains(&target_coverage),
+        "target coverage must be between 0 and 1"
+    );
+    let total: usize = corpus.iter().map(|s| s.len()).sum();
+    let mut builder = CodebookBuilder::new();
+    builder.add(corpus);
+    let ranked = builder.ranked(MAX_ENTRIES);
+
+    for n in 0..=ranked.len() {
+        let codebook = Codebook::new(&ranked[..n]).unwrap();
+        let covered: usize = corpus
+            .iter()
+            .flat_map(|s| Steps::new(s, &codebook))
+            .map(|step| match step {
+                Step::Code(code) => codebook.get(code).map_or(0, |e| e.len()),
+                Step::Verbatim(_) => 0,
+            })
+            .sum();
+        if covered as f64 >= target_coverage * total as f64 {
+            return n;
+        }
+    }
+    MAX_ENTRIES
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress_with;
+    use crate::tests::Rng;
+
+    /// Returns a corpus of random words over `letters`.
+    fn corpus(rng: &mut Rng, letters: &[u8]) -> Vec<Vec<u8>> {
+        let words: Vec<Vec<u8>> = (0..60)
+            .map(|_| {
+                let len = 3 + rng.next_u64() as usize % 5;
+                (0..len)
+                    .map(|_| letters[rng.next_u64() as usize % letters.len()])
+                    .collect()
+            })
+            .collect();
+        (0..200)
+            .map(|_| {
+                let mut line = Vec::new();
+                for _ in 0..6 {
+                    line.extend_from_slice(&words[rng.next_u64() as usize % words.len()]);
+                    line.push(b' ');
+                }
+                line
+            })
+            .collect()
+    }
+
+    fn compressed_len(corpus: &[Vec<u8>], codebook: &Codebook) -> usize {
+        corpus
+            .iter()
+            .map(|s| compress_with(s, codebook).len())
+            .sum()
+    }
+
+    #[test]
+    fn test_build() {
+        let codebook = CodebookBuilder::new().add(["abab"]).build();
+        let entries: Vec<&[u8]> = codebook.iter().collect();
+        assert_eq!(
+            vec![&b"ab"[..], b"abab", b"aba", b"bab", b"a", b"b", b"ba"],
+            entries
+        );
+        assert!(CodebookBuilder::new().build().is_empty());
+        assert!(CodebookBuilder::new()
+            .add_weighted(["foo"], 0.0)
+            .build()
+            .is_empty());
+
+        let mut rng = Rng::new(139);
+        let samples = corpus(&mut rng, b"abcdefghijklmnopqrstuvwxyz");
+        let codebook = CodebookBuilder::new().add(&samples).build();
+        assert_eq!(MAX_ENTRIES, codebook.len());
+        assert_eq!(codebook, CodebookBuilder::new().add(&samples).build());
+    }
+
+    #[test]
+    fn test_weighted() {
+        let mut rng = Rng::new(139);
+        let old = corpus(&mut rng, b"abcdefghijklm");
+        let new = corpus(&mut rng, b"nopqrstuvwxyz");
+
+        let even = CodebookBuilder::new().add(&old).add(&new).build();
+        let recent = CodebookBuilder::new()
+            .add_weighted(&old, 0.1)
+            .add_weighted(&new, 10.0)
+            .build();
+
+        let is_new = |e: &[u8]| e.iter().all(|b| *b == b' ' || *b >= b'n');
+        let even_new = even.iter().filter(|e| is_new(e)).count();
+        let recent_new = recent.iter().filter(|e| is_new(e)).count();
+        assert!(recent_new > even_new);
+        assert!(compressed_len(&new, &recent) < compressed_len(&new, &even));
+    }
+
+    #[test]
+    #[should_panic(expected = "weight must be finite and not negative")]
+    fn test_negative_weight() {
+        CodebookBuilder::new().add_weighted(["foo"], -1.0);
+    }
+
+    #[test]
+    fn test_suggest_codebook_size() {
+        let mut rng = Rng::new(159);
+        let diverse = corpus(
+            &mut rng,
+            b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789.,;:!?-",
+        );
+        let diverse: Vec<&[u8]> = diverse.iter().take(50).map(|s| &s[..]).collect();
+        let repetitive = vec![&b"status=ok; "[..]; 100];
+
+        let few = suggest_codebook_size(&repetitive, 0.95);
+        let many = suggest_codebook_size(&diverse, 0.95);
+        assert!(few * 10 < many, "{} {}", few, many);
+
+        assert_eq!(0, suggest_codebook_size(&repetitive, 0.0));
+        assert_eq!(0, suggest_codebook_size(&[], 1.0));
+        // a single byte repeated is covered by one entry
+        assert_eq!(1, suggest_codebook_size(&[b"zzzzzzzzzzzzzz"], 1.0));
+        assert_eq!(MAX_ENTRIES, suggest_codebook_size(&diverse, 1.0));
+    }
+
+    #[test]
+    #[should_panic(expected = "target coverage must be between 0 and 1")]
+    fn test_suggest_codebook_size_coverage() {
+        suggest_codebook_size(&[b"foo"], 1.5);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::codebook::{build_static, Codebook, DEFAULT_CODEBOOK};
+use crate::CODEBOOK_SIZE;
+
+lazy_static! {
+    static ref LATIN_CODEBOOK: Codebook = build_static(&LATIN);
+    static ref URL_CODEBOOK: Codebook = build_static(&URL);
+    static ref HTML_CODEBOOK: Codebook = build_static(&HTML);
+}
+
+/// Builds the codebooks the crate builds lazily, on first use.
+///
+/// [`compress`](fn.compress.html) and [`decompress`](fn.decompress.html) look
+/// up tables generated at build time and never build anything, but
+/// [`Codebook::default`](struct.Codebook.html) and the codebooks of
+/// [`BuiltinCodebook`](enum.BuiltinCodebook.html) are built the first time
+/// they are used, which builds their lookup indexes. Call this at startup so that no
+/// call made later pays for it; calls after the first do nothing.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_with, warm_up, BuiltinCodebook};
+///
+/// warm_up();
+/// let codebook = BuiltinCodebook::Url.codebook();
+/// assert_eq!(1, compress_with(b"http://", codebook).len());
+/// ```
+pub fn warm_up() {
+    lazy_static::initialize(&DEFAULT_CODEBOOK);
+    lazy_static::initialize(&LATIN_CODEBOOK);
+    lazy_static::initialize(&URL_CODEBOOK);
+    lazy_static::initialize(&HTML_CODEBOOK);
+}
+
+/// Codebooks shipped with the crate.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_with, decompress_with, BuiltinCodebook};
+///
+/// let codebook = BuiltinCodebook::Url.codebook();
+/// let compressed = compress_with(b"https://example.com/index.html", codebook);
+/// let decompressed = decompress_with(&compressed, codebook).unwrap();
+/// assert_eq!(b"https://example.com/index.html".to_vec(), decompressed);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum BuiltinCodebook {
+    /// The original smaz codebook, tuned for English text.
+    #[default]
+    English,
+    /// A codebook for Romance languages.
+    Latin,
+    /// A codebook for URLs.
+    Url,
+    /// A codebook for HTML markup.
+    Html,
+}
+
+impl BuiltinCodebook {
+    /// Returns the codebook.
+    pub fn codebook(self) -> &'static Codebook {
+        match self {
+            BuiltinCodebook::English => &DEFAULT_CODEBOOK,
+            BuiltinCodebook::Latin => &LATIN_CODEBOOK,
+            BuiltinCodebook::Url => &URL_CODEBOOK,
+            BuiltinCodebook::Html => &HTML_CODEBOOK,
+        }
+    }
+}
+
+/// Common fragments of Italian, Spanish, French and Portuguese text.
+static LATIN: [&str; CODEBOOK_SIZE] = [
+    " ", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r",
+    "s", "t", "u", "v", "w", "x", "y", "z", ",", ".", "\n", "'", "-", ":", ";", "e ", "a ", "o ",
+    "i ", "s ", "n ", "l ", "r ", "t ", "u ", " d", " c", " p", " s", " l", " a", " e", " i", " m",
+    " n", " t", " v", " f", " r", " q", " u", " o", " g", " b", " di ", " de ", " la ", " il ",
+    " le ", " el ", " en ", " in ", " e ", " y ", " et ", " un ", " una ", " che ", " que ",
+    " per ", " por ", " con ", " non ", " del ", " della", " des ", " les ", " los ", " las ",
+    " da ", " do ", " du ", " al ", " se ", " si ", " es ", " est ", " a ", " o ", " i ", " lo ",
+    " mi ", " me ", " no ", "L'", "l'", "d'", "un'", "all'", "dell'", "on", "an", "en", "in", "er",
+    "re", "ar", "or", "ri", "ra", "ro", "ta", "to", "te", "ti", "tu", "na", "ne", "ni", "no", "la",
+    "le", "li", "lo", "ma", "me", "mi", "mo", "ca", "ce", "ci", "co", "da", "de", "di", "do", "sa",
+    "se", "si", "so", "va", "ve", "vi", "pa", "pe", "pi", "po", "bo", "ll", "ss", "tt", "zz", "nn",
+    "rr", "mm", "pp", "cc", "qu", "ch", "gn", "gl", "st", "nt", "nd", "mp", "zi", "io", "ia", "ie",
+    "ue", "ua", "ui", "ei", "ai", "au", "ou", "eu", "ut", "ion", "zione", "ment", "mente", "ción",
+    "ando", "endo", "ato", "ata", "ado", "ada", "ità", "dad", "tion", "ons", "ont", "ent", "ant",
+    "are", "ere", "ire", "ar ", "er ", "ir ", "os ", "as ", "es ", "is ", "us ", "um ", "tra",
+    "pre", "pro", "per", "con", "com", "par", "ver", "nel", "sta", "ell", "ella", "gli", "sso",
+    "tto", "tta", "ano", "ino", "one", "ona", "ore", "ori", "ura", "ure", "ero", "era", "osa",
+    "oso", "ica", "ico", "ali", "ale", "ità ", "ione", "enza", "anza", "emo",
+];
+
+/// Common fragments of URLs.
+static URL: [&str; 227] = [
+    "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q", "r", "s",
+    "t", "u", "v", "w", "x", "y", "z", "0", "1", "2", "3", "4", "5", "6", "7", "8", "9", "http://",
+    "https:", "//", "www.", ".com", ".org", ".net", ".io", ".html", ".htm", ".php", ".asp", ".js",
+    ".css", ".png", ".jpg", ".gif", ".svg", ".pdf", ".xml", ".json", ".co", ".uk", ".de", ".fr",
+    ".it", ".ru", ".edu", ".gov", "/", "?", "&", "=", "#", "%", "%20", "-", "_", ".", ":", "~",
+    "+", ",", ";", "@", "index", "search", "q=", "id=", "page=", "?id=", "&id=", "?q=", "ref=",
+    "utm_", "source", "medium", "lang=", "/index", "/search", "/page", "/blog", "/news", "/wiki",
+    "/tag", "/post", "/user", "/api", "/v1/", "/v2/", "/static", "/img", "/images", "/css", "/js",
+    "/en/", "/de/", "/assets", "/login", "/about", "/home", "/watch?", "/item?", "/p/", "/r/",
+    "/u/", "/a/", "www", "com", "org", "net", "blog", "news", "mail", "img", "api", "user",
+    "login", "wiki", "tag", "post", "html", "http", "page", "home", "shop", "app", "web", "site",
+    "er", "re", "in", "on", "an", "en", "at", "es", "te", "or", "ar", "st", "ing", "ion", "ed",
+    "ti", "al", "le", "it", "ro", "ra", "co", "ma", "de", "se", "ne", "li", "la", "ch", "pro",
+    "gram", "ogle", "oo", "ss", "ll", "tt", "mm", "dd", "ee", "ic", "ac", "om", "ol", "il", "ot",
+    "ent", "ist", "ter", "con", "per", "ment", "ver", "tion", "ate", "red", "dit", "A", "B", "C",
+    "D", "E", "F", "G", "H", "I", "J", "K", "L", "M", "N", "O", "P", "Q", "R", "S", "T", "U", "V",
+    "W", "X", "Y", "Z",
+];
+
+/// Common fragments of HTML markup.
+static HTML: [&str; 248] = [
+    " ", "\n", "  ", "    ", "\t", "<", ">", "</", "/>", "\">", "=\"", "\"", "'", "=", "/", "-",
+    ".", ",", ":", ";", "#", "&", "!", "?", "(", ")", "<div", "</div>", "<div>", "<p>", "</p>",
+    "<a ", "</a>", "href=\"", "class=\"", "id=\"", "src=\"", "alt=\"", "style=\"", "type=\"",
+    "name=\"", "value=\"", "title=\"", "rel=\"", "width=\"", "height=", "target=", "<span",
+    "</span>", "<li>", "</li>", "<li", "<ul>", "</ul>", "<ol>", "</ol>", "<br>", "<br />", "<img ",
+    "<table", "</table", "<tr>", "</tr>", "<td>", "</td>", "<th>", "</th>", "<td", "<tr", "<h1>",
+    "</h1>", "<h2>", "</h2>", "<h3>", "</h3>", "<head>", "</head>", "<body>", "</body>", "<html>",
+    "</html>", "<title>", "</title", "<meta ", "<link ", "<!--", "-->", "<script", "script>",
+    "<style", "style>", "<form", "</form>", "<input ", "<button", "<label", "<nav>", "</nav>",
+    "<footer", "header", "<sectio", "section", "<strong", "strong>", "<em>", "</em>", "<b>",
+    "</b>", "<i>", "</i>", "&nbsp;", "&amp;", "&lt;", "&gt;", "&quot;", "http://", "https:",
+    "www.", ".com", ".html", ".css", ".js", ".png", ".jpg", "index", "content", "charset", "utf-8",
+    "text/", "java", "button", "center", "left", "right", "color", "font", "px", "%", "contain",
+    "wrapper", "menu", "item", "nav", "main", "row", "col", "link", "page", "logo", "icon",
+    "active", "a", "b", "c", "d", "e", "f", "g", "h", "i", "j", "k", "l", "m", "n", "o", "p", "q",
+    "r", "s", "t", "u", "v", "w", "x", "y", "z", "0", "1", "2", "3", "4", "5", "6", "7", "8", "9",
+    "th", "he", "in", "er", "an", "re", "on", "en", "at", "es", "ed", "te", "ti", "or", "st", "ar",
+    "nd", "to", "nt", "is", "of", "it", "al", "as", "ha", "ng", "co", "se", "me", "de", "the",
+    "ing", "and", "ion", "tion", "ent", "for", "the ", "e ", "s ", "t ", "d ", "y ", "A", "B", "C",
+    "D", "E", "F", "G", "H", "I", "L", "M", "N", "O", "P", "R", "S", "T", "W",
+];
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::Ordering;
+
+    use super::*;
+    use crate::codebook::STATIC_BUILDS;
+    use crate::{compress, compress_with, decompress, decompress_with, tests::TEST_STRINGS};
+
+    const BUILTINS: [BuiltinCodebook; 4] = [
+        BuiltinCodebook::English,
+        BuiltinCodebook::Latin,
+        BuiltinCodebook::Url,
+        BuiltinCodebook::Html,
+    ];
+
+    #[test]
+    fn test_roundtrip() {
+        for builtin in BUILTINS.iter() {
+            let codebook = builtin.codebook();
+            for s in TEST_STRINGS.iter() {
+                let compressed = compress_with(s.as_bytes(), codebook);
+                let decompressed = decompress_with(&compressed, codebook).unwrap();
+                assert_eq!(s.as_bytes(), &decompressed[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_url() {
+        let s = b"http://programming.reddit.com";
+        let compressed = compress_with(s, BuiltinCodebook::Url.codebook());
+        assert!(compressed.len() < compress(s).len());
+    }
+
+    #[test]
+    fn test_latin() {
+        let s = b"Nel mezzo del cammin di nostra vita, mi ritrovai in una selva oscura";
+        let compressed = compress_with(s, BuiltinCodebook::Latin.codebook());
+        assert!(compressed.len() < compress(s).len());
+    }
+
+    #[test]
+    fn test_html() {
+        let s = br#"<div class="nav"><ul><li><a href="/index.html">Home</a></li></ul></div>"#;
+        let codebook = BuiltinCodebook::Html.codebook();
+        let compressed = compress_with(s, codebook);
+        assert!(compressed.len() < compress(s).len());
+        assert_eq!(&s[..], &decompress_with(&compressed, codebook).unwrap()[..]);
+    }
+
+    #[test]
+    fn test_warm_up() {
+        warm_up();
+        // each codebook is built once, whichever test used it first
+        assert_eq!(4, STATIC_BUILDS.load(Ordering::Relaxed));
+        warm_up();
+        for s in TEST_STRINGS.iter() {
+            decompress(&compress(s.as_bytes())).unwrap();
+            for builtin in BUILTINS.iter() {
+                compress_with(s.as_bytes(), builtin.codebook());
+            }
+        }
+        assert_eq!(Codebook::default(), *BuiltinCodebook::English.codebook());
+        assert_eq!(4, STATIC_BUILDS.load(Ordering::Relaxed));
+    }
+}
@@ -0,0 +1,212 @@
+use std::result;
+
+use crate::codebook::Dictionary;
+use crate::reader::Reader;
+use crate::{
+    flush_verbatim, Codebook, CodebookError, DecompressError, Result, Step, MAX_MATCH_LEN,
+};
+
+/// Returns the code, length and case mask of the longest entry matching the
+/// start of `input` once ASCII uppercase letters are lowercased.
+fn find_cased_match(input: &[u8], codebook: &Codebook) -> Option<(u8, usize, u8)> {
+    let mut window = [0; MAX_MATCH_LEN];
+    let len = input.len().min(window.len());
+    for (w, b) in window.iter_mut().zip(input) {
+        *w = b.to_ascii_lowercase();
+    }
+
+    let (code, len) = codebook.find_match(&window[..len])?;
+    let mut mask = 0;
+    for (i, b) in input[..len].iter().enumerate() {
+        if b.is_ascii_uppercase() {
+            mask |= 1 << i;
+        }
+    }
+    Some((code, len, mask))
+}
+
+/// Returns data compressed with a codebook, restoring the case of entries that
+/// only match once uppercase ASCII letters are lowercased.
+///
+/// A cased code is the escape code of the codebook, the code of the entry and
+/// a case mask whose bit `i` uppercases byte `i` of the entry.
+///
+/// A cased code takes three bytes, so it is only used for matches of at least
+/// three bytes that are longer than the exact match.
+///
+/// # Errors
+///
+/// Returns [`CodebookError::MissingEscape`](enum.CodebookError.html) if the
+/// codebook has no escape code.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_cased, compress_with, decompress_cased, Codebook, CODEBOOK};
+///
+/// let codebook = Codebook::new(CODEBOOK.iter()).unwrap().with_escape(253).unwrap();
+/// let s = b"NOTHING IS MORE DIFFICULT";
+/// let compressed = compress_cased(s, &codebook).unwrap();
+/// assert!(compressed.len() < compress_with(s, &codebook).len());
+/// assert_eq!(s.to_vec(), decompress_cased(&compressed, &codebook).unwrap());
+/// ```
+pub fn compress_cased(input: &[u8], codebook: &Codebook) -> result::Result<Vec<u8>, CodebookError> {
+    let escape = codebook.escape().ok_or(CodebookError::MissingEscape)?;
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    let mut verbatim_start = 0;
+    let mut i = 0;
+
+    while i < input.len() {
+        let exact = codebook.find_match(&input[i..]);
+        let exact_len = exact.map_or(0, |(_, len)| len);
+        let cased = find_cased_match(&input[i..], codebook)
+            .filter(|(_, len, _)| *len >= 3 && *len > exact_len);
+
+        let len = match (cased, exact) {
+            (Some((code, len, mask)), _) => {
+                out.extend_from_slice(&flush_verbatim(&input[verbatim_start..i]));
+                out.extend_from_slice(&[escape, code, mask]);
+                len
+            }
+            (None, Some((code, len))) => {
+                out.extend_from_slice(&flush_verbatim(&input[verbatim_start..i]));
+                out.push(code);
+                len
+            }
+            (None, None) => {
+                i += 1;
+                continue;
+            }
+        };
+        i += len;
+        verbatim_start = i;
+    }
+    out.extend_from_slice(&flush_verbatim(&input[verbatim_start..]));
+    Ok(out)
+}
+
+/// Returns data decompressed from the format of
+/// [`compress_cased`](fn.compress_cased.html).
+///
+/// # Errors
+///
+/// If the compressed data is invalid, or a case mask has bits beyond its
+/// entry or on bytes that are not lowercase ASCII letters, then an error is
+/// returned [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_cased(input: &[u8], codebook: &Codebook) -> Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        let step = reader.read_step()?;
+        if step != Step::Code(codebook.escape().ok_or(DecompressError)?) {
+            codebook.expand(step, &mut out)?;
+            continue;
+        }
+
+        let code = reader.read_u8()?;
+        let mask = reader.read_u8()?;
+        let entry = codebook.entry(code).ok_or(DecompressError)?;
+        if mask as usize >> entry.len() != 0 {
+            return Err(DecompressError);
+        }
+        for (i, b) in entry.iter().enumerate() {
+            if mask & (1 << i) == 0 {
+                out.push(*b);
+            } else if b.is_ascii_lowercase() {
+                out.push(b.to_ascii_uppercase());
+            } else {
+                return Err(DecompressError);
+            }
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{escape_codebook, Rng, TEST_STRINGS};
+    use crate::{compress_with, CODEBOOK};
+
+    #[test]
+    fn test_roundtrip() {
+        let codebook = escape_codebook();
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_cased(s.as_bytes(), &codebook).unwrap();
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_cased(&compressed, &codebook).unwrap()[..]
+            );
+        }
+
+        let mut rng = Rng::new(126);
+        for _ in 0..1000 {
+            let mut input = rng.bytes(300);
+            for b in input.iter_mut().step_by(3) {
+                *b = b.to_ascii_uppercase();
+            }
+            let compressed = compress_cased(&input, &codebook).unwrap();
+            assert_eq!(input, decompress_cased(&compressed, &codebook).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_mixed_case() {
+        let codebook = escape_codebook();
+        for s in [
+            "McDonald",
+            "iPhone",
+            "McDonald's iPhone",
+            "eBay and YouTube",
+            "THE END",
+        ]
+        .iter()
+        {
+            let compressed = compress_cased(s.as_bytes(), &codebook).unwrap();
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_cased(&compressed, &codebook).unwrap()[..]
+            );
+        }
+
+        // "the" with the first and last letters uppercased
+        let the = codebook.lookup(b"the").unwrap();
+        assert_eq!(
+            vec![253, the, 0b101],
+            compress_cased(b"ThE", &codebook).unwrap()
+        );
+        assert_eq!(
+            compress_with(b"McDonald", &codebook).len(),
+            compress_cased(b"McDonald", &codebook).unwrap().len()
+        );
+        assert!(
+            compress_cased(b"THE END", &codebook).unwrap().len()
+                < compress_with(b"THE END", &codebook).len()
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let codebook = escape_codebook();
+        let the = codebook.lookup(b"the").unwrap();
+        let space = codebook.lookup(b" ").unwrap();
+        assert!(decompress_cased(&[253, the], &codebook).is_err());
+        // "the" has three bytes, bit 3 is out of range
+        assert!(decompress_cased(&[253, the, 0b1000], &codebook).is_err());
+        // " " is not a letter
+        assert!(decompress_cased(&[253, space, 1], &codebook).is_err());
+        // the escape code itself has no entry
+        assert!(decompress_cased(&[253, 253, 0], &codebook).is_err());
+        assert_eq!(
+            b" ".to_vec(),
+            decompress_cased(&[253, space, 0], &codebook).unwrap()
+        );
+
+        let plain = Codebook::new(CODEBOOK.iter()).unwrap();
+        assert_eq!(
+            Err(CodebookError::MissingEscape),
+            compress_cased(b"the", &plain)
+        );
+    }
+}
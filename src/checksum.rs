@@ -0,0 +1,384 @@
+use std::error::Error;
+use std::fmt;
+use std::result;
+
+use crate::{compress, decompress};
+
+/// Largest number of input bytes in a block.
+const MAX_BLOCK_SIZE: usize = 1 << 24;
+
+/// Largest compressed length of a block: each input byte takes at most two.
+const MAX_DATA_LEN: usize = 2 * MAX_BLOCK_SIZE;
+
+/// Length of the header of a block: its length and checksum.
+const HEADER_LEN: usize = 8;
+
+/// CRC-32 (IEEE) lookup table.
+static CRC_TABLE: [u32; 256] = crc_table();
+
+const fn crc_table() -> [u32; 256] {
+    let mut table = [0; 256];
+    let mut i = 0;
+    while i < 256 {
+        let mut c = i as u32;
+        let mut k = 0;
+        while k < 8 {
+            c = if c & 1 == 1 {
+                0xedb8_8320 ^ (c >> 1)
+            } else {
+                c >> 1
+            };
+            k += 1;
+        }
+        table[i] = c;
+        i += 1;
+    }
+    table
+}
+
+/// Returns the CRC-32 state after the bytes, starting from `state`, so that
+/// the checksum of consecutive slices needs no copy of them.
+fn crc32_update(state: u32, bytes: &[u8]) -> u32 {
+    bytes.iter().fold(state, |c, b| {
+        CRC_TABLE[((c ^ u32::from(*b)) & 0xff) as usize] ^ (c >> 8)
+    })
+}
+
+/// Returns the checksum of a block, covering its length and compressed data.
+fn block_crc(len: &[u8], data: &[u8]) -> u32 {
+    !crc32_update(crc32_update(!0, len), data)
+}
+
+fn write_block(data: &[u8], out: &mut Vec<u8>) {
+    let len = (data.len() as u32).to_le_bytes();
+    out.extend_from_slice(&len);
+    out.extend_from_slice(&block_crc(&len, data).to_le_bytes());
+    out.extend_from_slice(data);
+}
+
+/// The error type for [`ChecksummedDecoder`](struct.ChecksummedDecoder.html).
+///
+/// Identifies the first block that failed its checksum or did not decompress,
+/// counting from zero. The end of the stream counts as one more block, so a
+/// stream cut short reports the block it was cut in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChecksumError {
+    block: usize,
+}
+
+impl ChecksumError {
+    /// Returns the index of the corrupt block.
+    pub fn block(&self) -> usize {
+        self.block
+    }
+}
+
+impl fmt::Display for ChecksumError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "block {} is corrupt", self.block)
+    }
+}
+
+impl Error for ChecksumError {}
+
+/// A streaming compressor writing blocks protected by a checksum.
+///
+/// The input is split into blocks of `block_size` bytes, each compressed on
+/// its own and written as:
+///
+/// 1. the compressed length, a 4-byte little-endian integer;
+/// 2. the CRC-32 of the compressed length and the compressed data together,
+///    a 4-byte little-endian integer;
+/// 3. the compressed data.
+///
+/// An empty block ends the stream. Read it back with
+/// [`ChecksummedDecoder`](struct.ChecksummedDecoder.html), which verifies each
+/// block before decompressing it.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{ChecksummedDecoder, ChecksummedEncoder};
+///
+/// let mut encoder = ChecksummedEncoder::new(16);
+/// let mut stream = Vec::new();
+/// encoder.push(b"this is a small string", &mut stream);
+/// encoder.finish(&mut stream);
+///
+/// let mut decoder = ChecksummedDecoder::new();
+/// let mut out = Vec::new();
+/// decoder.push(&stream, &mut out).unwrap();
+/// decoder.finish().unwrap();
+/// assert_eq!(b"this is a small string".to_vec(), out);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ChecksummedEncoder {
+    block_size: usize,
+    pending: Vec<u8>,
+}
+
+impl ChecksummedEncoder {
+    /// Creates a new encoder of blocks of `block_size` input bytes.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` is zero or larger than 16 MiB.
+    pub fn new(block_size: usize) -> ChecksummedEncoder {
+        assert!(
+            block_size > 0 && block_size <= MAX_BLOCK_SIZE,
+            "block size must be between 1 and 16 MiB"
+        );
+        ChecksummedEncoder {
+            block_size,
+            pending: Vec::with_capacity(block_size),
+        }
+    }
+
+    /// Compresses more input, appending the blocks it completes to `out`.
+    pub fn push(&mut self, mut input: &[u8], out: &mut Vec<u8>) {
+        while !input.is_empty() {
+            let n = input.len().min(self.block_size - self.pending.len());
+            self.pending.extend_from_slice(&input[..n]);
+            input = &input[n..];
+            if self.pending.len() == self.block_size {
+                write_block(&compress(&self.pending), out);
+                self.pending.clear();
+            }
+        }
+    }
+
+    /// Compresses the rest of the input and ends the stream, appending the
+    /// last blocks to `out`.
+    pub fn finish(self, out: &mut Vec<u8>) {
+        if !self.pending.is_empty() {
+            write_block(&compress(&self.pending), out);
+        }
+        write_block(&[], out);
+    }
+}
+
+/// A streaming decompressor of blocks protected by a checksum.
+///
+/// Compressed data can be pushed in pieces of any size. Each block is only
+/// decompressed once it is complete and its checksum matches, so the output
+/// holds verified data only. See
+/// [`ChecksummedEncoder`](struct.ChecksummedEncoder.html).
+#[derive(Debug, Clone, Default)]
+pub struct ChecksummedDecoder {
+    pending: Vec<u8>,
+    block: usize,
+    ended: bool,
+}
+
+impl ChecksummedDecoder {
+    /// Creates a new decoder.
+    pub fn new() -> ChecksummedDecoder {
+        ChecksummedDecoder::default()
+    }
+
+    /// Returns the number of blocks decoded so far, not counting the end of
+    /// the stream.
+    pub fn blocks(&self) -> usize {
+        self.block
+    }
+
+    /// Decompresses more input, appending the output of the blocks it
+    /// completes to `out`.
+    ///
+    /// # Errors
+    ///
+    /// If a block fails its checksum or does not decompress, or data follows
+    /// the end of the stream, then an error is returned
+    /// [`ChecksumError`](struct.ChecksumError.html) with the index of the
+    /// block. The blocks before it have been appended to `out`. A block whose
+    /// length was corrupted into a larger one waits for more data, and is
+    /// reported by [`finish`](#method.finish).
+    pub fn push(&mut self, input: &[u8], out: &mut Vec<u8>) -> result::Result<(), ChecksumError> {
+        self.pending.extend_from_slice(input);
+        let error = ChecksumError { block: self.block };
+        if self.ended {
+            return if self.pending.is_empty() {
+                Ok(())
+            } else {
+                Err(error)
+            };
+        }
+
+        let mut pos = 0;
+        let result = loop {
+            let header = match self.pending.get(pos..pos + HEADER_LEN) {
+                Some(header) => header,
+                None => break Ok(()),
+            };
+            let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+            let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+            let error = ChecksumError { block: self.block };
+            if len > MAX_DATA_LEN {
+                break Err(error);
+            }
+            let data = match self.pending.get(pos + HEADER_LEN..pos + HEADER_LEN + len) {
+                Some(data) => data,
+                None => break Ok(()),
+            };
+            if block_crc(&header[..4], data) != crc {
+                break Err(error);
+            }
+            pos += HEADER_LEN + len;
+
+            if len == 0 {
+                self.ended = true;
+                break if pos == self.pending.len() {
+                    Ok(())
+                } else {
+                    Err(error)
+                };
+            }
+            match decompress(data) {
+                Ok(block) => out.extend(block),
+                Err(_) => break Err(error),
+            }
+            self.block += 1;
+        };
+        self.pending.drain(..pos);
+        result
+    }
+
+    /// Checks that the stream has ended.
+    ///
+    /// # Errors
+    ///
+    /// If the end of the stream has not been pushed, then an error is returned
+    /// [`ChecksumError`](struct.ChecksumError.html) with the index of the
+    /// block the stream was cut in.
+    pub fn finish(self) -> result::Result<(), ChecksumError> {
+        if self.ended && self.pending.is_empty() {
+            Ok(())
+        } else {
+            Err(ChecksumError { block: self.block })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Rng, TEST_STRINGS};
+
+    fn encode(input: &[u8], block_size: usize) -> Vec<u8> {
+        let mut encoder = ChecksummedEncoder::new(block_size);
+        let mut stream = Vec::new();
+        encoder.push(input, &mut stream);
+        encoder.finish(&mut stream);
+        stream
+    }
+
+    /// Returns the CRC-32 of the bytes, the checksum of zlib and PNG.
+    fn crc32(bytes: &[u8]) -> u32 {
+        !crc32_update(!0, bytes)
+    }
+
+    #[test]
+    fn test_crc32() {
+        assert_eq!(0, crc32(b""));
+        assert_eq!(0xcbf4_3926, crc32(b"123456789"));
+        assert_eq!(
+            0x414f_a339,
+            crc32(b"The quick brown fox jumps over the lazy dog")
+        );
+        assert_eq!(crc32(b"123456789"), block_crc(b"1234", b"56789"));
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let text = TEST_STRINGS.concat();
+        let mut rng = Rng::new(153);
+        for block_size in [1, 7, 64, 1000].iter() {
+            let stream = encode(text.as_bytes(), *block_size);
+            for size in [1, 5, 100, stream.len()].iter() {
+                let mut decoder = ChecksummedDecoder::new();
+                let mut out = Vec::new();
+                for chunk in stream.chunks(*size) {
+                    decoder.push(chunk, &mut out).unwrap();
+                }
+                assert_eq!(text.len().div_ceil(*block_size), decoder.blocks());
+                decoder.finish().unwrap();
+                assert_eq!(text.as_bytes(), &out[..]);
+            }
+
+            let input = rng.bytes(3000);
+            let stream = encode(&input, *block_size);
+            let mut decoder = ChecksummedDecoder::new();
+            let mut out = Vec::new();
+            decoder.push(&stream, &mut out).unwrap();
+            decoder.finish().unwrap();
+            assert_eq!(input, out);
+        }
+
+        assert_eq!(HEADER_LEN, encode(b"", 10).len());
+    }
+
+    #[test]
+    fn test_corrupt_block() {
+        let text = TEST_STRINGS.concat();
+        let stream = encode(text.as_bytes(), 100);
+        let mut starts = vec![0];
+        let mut pos = 0;
+        while pos < stream.len() {
+            let len = u32::from_le_bytes([
+                stream[pos],
+                stream[pos + 1],
+                stream[pos + 2],
+                stream[pos + 3],
+            ]);
+            pos += HEADER_LEN + len as usize;
+            starts.push(pos);
+        }
+
+        // flip a bit in the header and in the data of the blocks in turn
+        for block in 0..starts.len() - 1 {
+            for offset in [1, 6, HEADER_LEN].iter() {
+                let at = starts[block] + offset;
+                if at >= starts[block + 1] {
+                    continue;
+                }
+                let mut corrupt = stream.clone();
+                corrupt[at] ^= 0x10;
+
+                let mut decoder = ChecksummedDecoder::new();
+                let mut out = Vec::new();
+                // a longer length waits for more data, until the end
+                let err = match decoder.push(&corrupt, &mut out) {
+                    Ok(()) => decoder.finish().unwrap_err(),
+                    Err(err) => err,
+                };
+                assert_eq!(block, err.block());
+                assert_eq!(&text.as_bytes()[..(block * 100).min(text.len())], &out[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_truncated() {
+        let stream = encode(b"the end of the stream", 8);
+        for n in 0..stream.len() {
+            let mut decoder = ChecksummedDecoder::new();
+            decoder.push(&stream[..n], &mut Vec::new()).unwrap();
+            let blocks = decoder.blocks();
+            assert_eq!(blocks, decoder.finish().unwrap_err().block());
+        }
+
+        let mut decoder = ChecksummedDecoder::new();
+        decoder.push(&stream, &mut Vec::new()).unwrap();
+        assert_eq!(3, decoder.push(b"x", &mut Vec::new()).unwrap_err().block());
+        assert_eq!(
+            "block 3 is corrupt",
+            decoder.finish().unwrap_err().to_string()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "block size must be between 1 and 16 MiB")]
+    fn test_zero_block_size() {
+        ChecksummedEncoder::new(0);
+    }
+}
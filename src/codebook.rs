@@ -0,0 +1,1123 @@
+use std::cmp::Ordering;
+#[cfg(not(feature = "no-hashmap"))]
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+#[cfg(test)]
+use std::sync::atomic::{self, AtomicUsize};
+use std::sync::Arc;
+
+use crate::{DecompressError, Step, CODEBOOK, MAX_MATCH_LEN};
+
+include!(concat!(env!("OUT_DIR"), "/default_table.rs"));
+
+lazy_static! {
+    pub(crate) static ref DEFAULT_CODEBOOK: Codebook = build_static(&CODEBOOK);
+}
+
+/// Number of lazily built codebooks built so far.
+#[cfg(test)]
+pub(crate) static STATIC_BUILDS: AtomicUsize = AtomicUsize::new(0);
+
+/// Builds the codebook of a lazy static.
+pub(crate) fn build_static(entries: &[&str]) -> Codebook {
+    #[cfg(test)]
+    STATIC_BUILDS.fetch_add(1, atomic::Ordering::Relaxed);
+    Codebook::new(entries.iter()).unwrap()
+}
+
+/// Maximum number of entries in a codebook.
+///
+/// Codes 254 and 255 are reserved as verbatim markers.
+pub(crate) const MAX_ENTRIES: usize = crate::CODEBOOK_SIZE;
+
+/// Lookup of codebook entries, shared by the default and custom codebooks.
+pub(crate) trait Dictionary {
+    /// Returns the code of an entry.
+    fn lookup(&self, entry: &[u8]) -> Option<u8>;
+
+    /// Returns the entry for a code.
+    fn entry(&self, code: u8) -> Option<&[u8]>;
+
+    /// Returns whether some entry starts with `byte`; if not, no lookup can
+    /// match at that position.
+    fn can_start(&self, byte: u8) -> bool;
+
+    /// Returns the length of the longest entry.
+    fn max_len(&self) -> usize;
+
+    /// Appends the bytes a step stands for to `out`.
+    fn expand(&self, step: Step, out: &mut Vec<u8>) -> crate::Result<()> {
+        match step {
+            Step::Code(code) => match self.entry(code) {
+                Some(v) => out.extend_from_slice(v),
+                None => return Err(DecompressError),
+            },
+            Step::Verbatim(bytes) => out.extend_from_slice(bytes),
+        }
+        Ok(())
+    }
+
+    /// Returns the code and length of the longest entry at the start of
+    /// `input`.
+    fn find_match(&self, input: &[u8]) -> Option<(u8, usize)> {
+        if !self.can_start(*input.first()?) {
+            return None;
+        }
+
+        let max_len = input.len().min(self.max_len()).min(MAX_MATCH_LEN);
+        for i in (1..=max_len).rev() {
+            if let Some(v) = self.lookup(&input[..i]) {
+                return Some((v, i));
+            }
+        }
+        None
+    }
+}
+
+/// A set of byte values, one bit each.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+struct ByteSet([u64; 4]);
+
+impl ByteSet {
+    /// Returns the set of first bytes of the entries, leaving out the escape.
+    fn starts(entries: &[Vec<u8>], escape: Option<u8>) -> ByteSet {
+        let mut set = ByteSet::default();
+        for (i, entry) in entries.iter().enumerate() {
+            if escape != Some(i as u8) {
+                set.insert(entry[0]);
+            }
+        }
+        set
+    }
+
+    fn insert(&mut self, byte: u8) {
+        self.0[byte as usize / 64] |= 1 << (byte % 64);
+    }
+
+    fn contains(&self, byte: u8) -> bool {
+        self.0[byte as usize / 64] & (1 << (byte % 64)) != 0
+    }
+}
+
+// Must match `hash` in build.rs.
+#[cfg(not(feature = "no-hashmap"))]
+fn hash(seed: u32, bytes: &[u8]) -> usize {
+    let mut h = seed ^ 0x811c_9dc5;
+    for b in bytes {
+        h ^= *b as u32;
+        h = h.wrapping_mul(0x0100_0193);
+    }
+    (h ^ (h >> 16)) as usize & (DEFAULT_TABLE_SIZE - 1)
+}
+
+/// The default codebook, backed by [`CODEBOOK`] and lookup tables generated
+/// at build time: direct tables for entries of one and two bytes, the most
+/// frequent matches, and for longer ones a perfect hash table. With the
+/// `no-hashmap` feature only entries of one byte have a direct table, and a
+/// sorted array serves the others.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct DefaultCodebook;
+
+/// Marks a missing entry in the direct tables.
+const NO_CODE: u8 = 255;
+
+impl DefaultCodebook {
+    /// Looks up an entry of any length in the hash table.
+    #[cfg(not(feature = "no-hashmap"))]
+    fn lookup_long(entry: &[u8]) -> Option<u8> {
+        let code = DEFAULT_TABLE[hash(DEFAULT_SEED, entry)];
+        match CODEBOOK.get(code as usize) {
+            Some(v) if v.as_bytes() == entry => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Looks up an entry of any length in the sorted array.
+    #[cfg(feature = "no-hashmap")]
+    fn lookup_long(entry: &[u8]) -> Option<u8> {
+        DEFAULT_SORTED
+            .binary_search_by(|(e, _)| e.cmp(&entry))
+            .ok()
+            .map(|i| DEFAULT_SORTED[i].1)
+    }
+}
+
+impl Dictionary for DefaultCodebook {
+    fn lookup(&self, entry: &[u8]) -> Option<u8> {
+        let code = match *entry {
+            [a] => DEFAULT_SINGLES[a as usize],
+            #[cfg(not(feature = "no-hashmap"))]
+            [a, b] => DEFAULT_PAIRS[(a as usize) << 8 | b as usize],
+            _ => return DefaultCodebook::lookup_long(entry),
+        };
+        if code == NO_CODE {
+            None
+        } else {
+            Some(code)
+        }
+    }
+
+    fn entry(&self, code: u8) -> Option<&[u8]> {
+        CODEBOOK.get(code as usize).map(|v| v.as_bytes())
+    }
+
+    fn can_start(&self, byte: u8) -> bool {
+        DEFAULT_STARTS.contains(byte)
+    }
+
+    fn max_len(&self) -> usize {
+        MAX_MATCH_LEN
+    }
+}
+
+/// Reverse lookup from entries to codes, the first code wins for duplicates.
+#[cfg(not(feature = "no-hashmap"))]
+#[derive(Clone)]
+struct Index(HashMap<Vec<u8>, u8>);
+
+#[cfg(not(feature = "no-hashmap"))]
+impl Index {
+    fn new(entries: &[Vec<u8>], escape: Option<u8>) -> Index {
+        let mut map: HashMap<Vec<u8>, u8> = HashMap::with_capacity(entries.len());
+        for (i, entry) in entries.iter().enumerate() {
+            if escape != Some(i as u8) {
+                map.entry(entry.clone()).or_insert(i as u8);
+            }
+        }
+        Index(map)
+    }
+
+    fn get(&self, entry: &[u8]) -> Option<u8> {
+        self.0.get(entry).copied()
+    }
+}
+
+/// Reverse lookup from entries to codes, the first code wins for duplicates.
+#[cfg(feature = "no-hashmap")]
+#[derive(Clone)]
+struct Index(Vec<(Vec<u8>, u8)>);
+
+#[cfg(feature = "no-hashmap")]
+impl Index {
+    fn new(entries: &[Vec<u8>], escape: Option<u8>) -> Index {
+        let mut sorted: Vec<(Vec<u8>, u8)> = entries
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| escape != Some(*i as u8))
+            .map(|(i, e)| (e.clone(), i as u8))
+            .collect();
+        sorted.sort();
+        sorted.dedup_by(|a, b| a.0 == b.0);
+        Index(sorted)
+    }
+
+    fn get(&self, entry: &[u8]) -> Option<u8> {
+        self.0
+            .binary_search_by(|(e, _)| e.as_slice().cmp(entry))
+            .ok()
+            .map(|i| self.0[i].1)
+    }
+}
+
+/// The error type for codebook construction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CodebookError {
+    /// More entries were given than the format allows, 254 in general.
+    TooManyEntries,
+    /// An entry is empty and could never be matched.
+    EmptyEntry,
+    /// The escape code is not the code of an entry.
+    InvalidEscape,
+    /// The format needs an escape code and the codebook has none.
+    MissingEscape,
+    /// Serialized codebook data is truncated or has trailing bytes.
+    Malformed,
+    /// The number of frequencies differs from the number of entries.
+    InvalidFrequencies,
+    /// The code is not the code of an entry.
+    InvalidCode,
+    /// An entry is longer than
+    /// [`MAX_MATCH_LEN`](constant.MAX_MATCH_LEN.html) and could never be
+    /// matched.
+    EntryTooLong,
+}
+
+impl fmt::Display for CodebookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            CodebookError::TooManyEntries => write!(f, "codebook has too many entries"),
+            CodebookError::EmptyEntry => write!(f, "codebook has an empty entry"),
+            CodebookError::InvalidEscape => write!(f, "escape code is out of range"),
+            CodebookError::MissingEscape => write!(f, "codebook has no escape code"),
+            CodebookError::Malformed => write!(f, "codebook data is malformed"),
+            CodebookError::InvalidFrequencies => {
+                write!(f, "frequencies do not match the entries")
+            }
+            CodebookError::InvalidCode => write!(f, "code is out of range"),
+            CodebookError::EntryTooLong => {
+                write!(
+                    f,
+                    "codebook has an entry longer than {} bytes",
+                    MAX_MATCH_LEN
+                )
+            }
+        }
+    }
+}
+
+impl Error for CodebookError {}
+
+/// A dictionary of byte strings used to compress and decompress data.
+///
+/// The code of an entry is its index. If the same entry appears more than once,
+/// the first occurrence is used for compression.
+///
+/// Compression is deterministic: it depends only on the entries and their
+/// order, so a codebook rebuilt from the same entries, e.g. after being stored
+/// and loaded again, always produces byte-identical output.
+///
+/// Cloning a codebook is cheap: clones share its entries and lookup index.
+///
+/// Codebooks compare and order by their entries in code order, then by their
+/// escape code and their frequencies, and the `Debug` output lists the entries
+/// in code order with non-printable bytes escaped.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_with, decompress_with, Codebook};
+///
+/// let codebook = Codebook::new(["foo", "bar", " "]).unwrap();
+/// let compressed = compress_with(b"foo bar", &codebook);
+/// assert_eq!(vec![0, 2, 1], compressed);
+/// assert_eq!(b"foo bar".to_vec(), decompress_with(&compressed, &codebook).unwrap());
+/// ```
+#[derive(Clone)]
+pub struct Codebook {
+    entries: Arc<[Vec<u8>]>,
+    index: Arc<Index>,
+    starts: ByteSet,
+    max_len: usize,
+    escape: Option<u8>,
+    frequencies: Option<Arc<[u64]>>,
+}
+
+impl Codebook {
+    /// Creates a codebook from a list of entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CodebookError`](enum.CodebookError.html) if there are more than
+    /// 254 entries, or any entry is empty or longer than
+    /// [`MAX_MATCH_LEN`](constant.MAX_MATCH_LEN.html) bytes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::{Codebook, CodebookError};
+    ///
+    /// let codebook = Codebook::new(["foo", "bar"]).unwrap();
+    /// assert_eq!(2, codebook.len());
+    /// assert_eq!(Err(CodebookError::EmptyEntry), Codebook::new(["foo", ""]));
+    /// ```
+    pub fn new<I, E>(entries: I) -> Result<Codebook, CodebookError>
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<[u8]>,
+    {
+        let entries: Vec<Vec<u8>> = entries.into_iter().map(|e| e.as_ref().to_vec()).collect();
+        if entries.len() > MAX_ENTRIES {
+            return Err(CodebookError::TooManyEntries);
+        }
+
+        if entries.iter().any(|e| e.is_empty()) {
+            return Err(CodebookError::EmptyEntry);
+        }
+        if entries.iter().any(|e| e.len() > MAX_MATCH_LEN) {
+            return Err(CodebookError::EntryTooLong);
+        }
+
+        Ok(Codebook::build(entries.into(), None))
+    }
+
+    /// Builds the lookup index and metadata, leaving out the escape.
+    fn build(entries: Arc<[Vec<u8>]>, escape: Option<u8>) -> Codebook {
+        let index = Arc::new(Index::new(&entries, escape));
+        let starts = ByteSet::starts(&entries, escape);
+        let max_len = entries
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| escape != Some(*i as u8))
+            .map(|(_, e)| e.len())
+            .max()
+            .unwrap_or(0);
+        Codebook {
+            entries,
+            index,
+            starts,
+            max_len,
+            escape,
+            frequencies: None,
+        }
+    }
+
+    /// Returns the codebook with the slot of `code` repurposed as an escape.
+    ///
+    /// The compressor never emits the escape code, and
+    /// [`decompress_to_escape`](fn.decompress_to_escape.html) stops when it
+    /// reads it, so compressed data can be embedded in an outer format that
+    /// uses the escape as a boundary. Other decompress functions reject the
+    /// escape code as invalid data.
+    ///
+    /// Some formats use the escape code to introduce a token of their own,
+    /// and store the other codes and verbatim runs as in
+    /// [`compress_with`](fn.compress_with.html): raw blocks in
+    /// [`compress_raw_blocks`](fn.compress_raw_blocks.html), run-length tokens
+    /// in [`compress_rle`](fn.compress_rle.html), back-references in
+    /// [`compress_backref`](fn.compress_backref.html) and cased codes in
+    /// [`compress_cased`](fn.compress_cased.html).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CodebookError::InvalidEscape`](enum.CodebookError.html) if
+    /// `code` is not the code of an entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::{compress_with, decompress_to_escape, Codebook};
+    ///
+    /// let codebook = Codebook::new(["foo", "bar", "|"]).unwrap().with_escape(2).unwrap();
+    /// let mut buf = compress_with(b"foo|bar", &codebook);
+    /// buf.push(2);
+    /// buf.extend_from_slice(b"outer data");
+    ///
+    /// let (value, consumed) = decompress_to_escape(&buf, &codebook).unwrap();
+    /// assert_eq!(b"foo|bar".to_vec(), value);
+    /// assert_eq!(b"outer data", &buf[consumed.unwrap()..]);
+    /// ```
+    pub fn with_escape(self, code: u8) -> Result<Codebook, CodebookError> {
+        if code as usize >= self.entries.len() {
+            return Err(CodebookError::InvalidEscape);
+        }
+        let mut codebook = Codebook::build(self.entries, Some(code));
+        codebook.frequencies = self.frequencies;
+        Ok(codebook)
+    }
+
+    /// Returns the codebook with a frequency for each entry, in code order.
+    ///
+    /// Frequencies are how often each code is expected in compressed data. They
+    /// do not change [`compress_with`](fn.compress_with.html), but give the
+    /// most frequent codes the shortest bit codes in
+    /// [`compress_bits_with`](fn.compress_bits_with.html).
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CodebookError::InvalidFrequencies`](enum.CodebookError.html)
+    /// if there is not exactly one frequency per entry.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::Codebook;
+    ///
+    /// let codebook = Codebook::new(["foo", "bar"]).unwrap();
+    /// let codebook = codebook.with_frequencies(&[90, 10]).unwrap();
+    /// assert_eq!(Some(&[90, 10][..]), codebook.frequencies());
+    /// ```
+    pub fn with_frequencies(mut self, frequencies: &[u64]) -> Result<Codebook, CodebookError> {
+        if frequencies.len() != self.entries.len() {
+            return Err(CodebookError::InvalidFrequencies);
+        }
+        self.frequencies = Some(frequencies.into());
+        Ok(self)
+    }
+
+    /// Replaces the entry of `code`, rebuilding the lookup index so that
+    /// compression matches the new entry from then on.
+    ///
+    /// Entries can only be changed through this method, so the index is never
+    /// stale. Clones made before the change share the old entries and keep
+    /// them. The escape code and frequencies are kept as they are.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CodebookError`](enum.CodebookError.html) if `code` is not the
+    /// code of an entry or the new entry is empty or too long, leaving the
+    /// codebook unchanged.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::{compress_with, Codebook};
+    ///
+    /// let mut codebook = Codebook::new(["foo", "bar"]).unwrap();
+    /// codebook.set_entry(1, "baz").unwrap();
+    /// assert_eq!(vec![1], compress_with(b"baz", &codebook));
+    /// assert_eq!(vec![255, 2, b'b', b'a', b'r'], compress_with(b"bar", &codebook));
+    /// ```
+    pub fn set_entry<E: AsRef<[u8]>>(&mut self, code: u8, entry: E) -> Result<(), CodebookError> {
+        if code as usize >= self.entries.len() {
+            return Err(CodebookError::InvalidCode);
+        }
+        if entry.as_ref().is_empty() {
+            return Err(CodebookError::EmptyEntry);
+        }
+        if entry.as_ref().len() > MAX_MATCH_LEN {
+            return Err(CodebookError::EntryTooLong);
+        }
+        let mut entries = self.entries.to_vec();
+        entries[code as usize] = entry.as_ref().to_vec();
+        let frequencies = self.frequencies.take();
+        *self = Codebook::build(entries.into(), self.escape);
+        self.frequencies = frequencies;
+        Ok(())
+    }
+
+    /// Returns the frequencies of the entries, if any.
+    pub fn frequencies(&self) -> Option<&[u64]> {
+        self.frequencies.as_deref()
+    }
+
+    /// Returns the escape code, if any.
+    pub fn escape(&self) -> Option<u8> {
+        self.escape
+    }
+
+    /// Returns the length of the longest entry, or 0 if there are none.
+    ///
+    /// The escape entry, if any, is left out.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::{BuiltinCodebook, Codebook};
+    ///
+    /// assert_eq!(7, BuiltinCodebook::English.codebook().max_entry_len());
+    /// assert_eq!(6, Codebook::new(["foo", "foobar"]).unwrap().max_entry_len());
+    /// ```
+    pub fn max_entry_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// Returns `true` if some entry starts with `byte`.
+    ///
+    /// The escape entry, if any, is left out. Compression takes no lookups at a
+    /// byte no entry starts with.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::Codebook;
+    ///
+    /// let codebook = Codebook::new(["foo", "bar"]).unwrap();
+    /// assert!(codebook.can_start_with(b'b'));
+    /// assert!(!codebook.can_start_with(b'o'));
+    /// ```
+    pub fn can_start_with(&self, byte: u8) -> bool {
+        self.starts.contains(byte)
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the codebook has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entry for a code, or `None` if the code is out of range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::BuiltinCodebook;
+    ///
+    /// let codebook = BuiltinCodebook::English.codebook();
+    /// assert_eq!(Some(&b"the"[..]), codebook.get(1));
+    /// assert_eq!(None, codebook.get(254));
+    /// ```
+    pub fn get(&self, code: u8) -> Option<&[u8]> {
+        self.entries.get(code as usize).map(|e| e.as_slice())
+    }
+
+    /// Returns an iterator over the entries in code order.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::Codebook;
+    ///
+    /// let codebook = Codebook::new(["foo", "bar"]).unwrap();
+    /// let entries: Vec<&[u8]> = codebook.iter().collect();
+    /// assert_eq!(vec![&b"foo"[..], b"bar"], entries);
+    /// ```
+    pub fn iter(&self) -> impl Iterator<Item = &[u8]> {
+        self.entries.iter().map(|e| e.as_slice())
+    }
+}
+
+impl Default for Codebook {
+    /// Returns the default English codebook, the entries of
+    /// [`CODEBOOK`](static.CODEBOOK.html).
+    ///
+    /// This is cheap: the entries and lookup index are built once and shared
+    /// by every default codebook, like any clone of a codebook.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::{compress, compress_with, Codebook};
+    ///
+    /// assert_eq!(compress(b"the end"), compress_with(b"the end", &Codebook::default()));
+    /// ```
+    fn default() -> Codebook {
+        DEFAULT_CODEBOOK.clone()
+    }
+}
+
+impl PartialEq for Codebook {
+    fn eq(&self, other: &Codebook) -> bool {
+        self.entries == other.entries
+            && self.escape == other.escape
+            && self.frequencies == other.frequencies
+    }
+}
+
+impl Eq for Codebook {}
+
+/// Returns the entries of a codebook serialized for other tools.
+///
+/// The format is one byte holding the number of entries, then for each entry
+/// in code order one byte holding its length followed by its bytes. The
+/// escape code is not part of it. Read it back with
+/// [`import_codebook`](fn.import_codebook.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{export_codebook, Codebook};
+///
+/// let codebook = Codebook::new(["foo", "ba"]).unwrap();
+/// assert_eq!(b"\x02\x03foo\x02ba".to_vec(), export_codebook(&codebook));
+/// ```
+pub fn export_codebook(codebook: &Codebook) -> Vec<u8> {
+    let mut out = Vec::with_capacity(1 + codebook.iter().map(|e| e.len() + 1).sum::<usize>());
+    out.push(codebook.len() as u8);
+    for entry in codebook.iter() {
+        debug_assert!(entry.len() <= MAX_MATCH_LEN);
+        out.push(entry.len() as u8);
+        out.extend_from_slice(entry);
+    }
+    out
+}
+
+/// Returns a codebook from entries serialized by
+/// [`export_codebook`](fn.export_codebook.html).
+///
+/// # Errors
+///
+/// If the data is truncated or has trailing bytes, then
+/// [`CodebookError::Malformed`](enum.CodebookError.html) is returned; if it
+/// holds more entries than a codebook can or an empty one, then the error of
+/// [`Codebook::new`](struct.Codebook.html#method.new) is returned.
+pub fn import_codebook(input: &[u8]) -> Result<Codebook, CodebookError> {
+    let (&count, mut rest) = input.split_first().ok_or(CodebookError::Malformed)?;
+    let mut entries = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        let (&len, tail) = rest.split_first().ok_or(CodebookError::Malformed)?;
+        if tail.len() < len as usize {
+            return Err(CodebookError::Malformed);
+        }
+        let (entry, tail) = tail.split_at(len as usize);
+        entries.push(entry);
+        rest = tail;
+    }
+    if !rest.is_empty() {
+        return Err(CodebookError::Malformed);
+    }
+    Codebook::new(entries)
+}
+
+impl PartialOrd for Codebook {
+    fn partial_cmp(&self, other: &Codebook) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Codebook {
+    fn cmp(&self, other: &Codebook) -> Ordering {
+        (&self.entries, self.escape, &self.frequencies).cmp(&(
+            &other.entries,
+            other.escape,
+            &other.frequencies,
+        ))
+    }
+}
+
+impl Hash for Codebook {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.entries.hash(state);
+        self.escape.hash(state);
+        self.frequencies.hash(state);
+    }
+}
+
+/// Formats bytes as an escaped string literal.
+pub(crate) struct Escaped<'a>(pub(crate) &'a [u8]);
+
+impl<'a> fmt::Debug for Escaped<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "\"{}\"", self.0.escape_ascii())
+    }
+}
+
+impl fmt::Debug for Codebook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Codebook")?;
+        f.debug_list()
+            .entries(self.entries.iter().map(|e| Escaped(e)))
+            .finish()?;
+        match self.escape {
+            Some(code) => write!(f, " (escape {})", code),
+            None => Ok(()),
+        }
+    }
+}
+
+/// Creates a [`Codebook`](struct.Codebook.html) from string or byte string
+/// literals.
+///
+/// The entries are checked at compile time: more than 254 entries, or an empty
+/// entry or one longer than [`MAX_MATCH_LEN`](constant.MAX_MATCH_LEN.html),
+/// fail the build instead of returning an error from
+/// [`Codebook::new`](struct.Codebook.html#method.new). Entries must therefore
+/// be constant expressions.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{codebook, compress_with};
+///
+/// let codebook = codebook!["foo", "bar", b"\xff"];
+/// assert_eq!(3, codebook.len());
+/// assert_eq!(vec![0, 1, 2], compress_with(b"foobar\xff", &codebook));
+/// ```
+///
+/// ```compile_fail
+/// let codebook = smaz::codebook!["foo", ""];
+/// ```
+///
+/// ```compile_fail
+/// let codebook = smaz::codebook!["foo", "too long"];
+/// ```
+#[macro_export]
+macro_rules! codebook {
+    (@unit $entry:expr) => {
+        ()
+    };
+    ($($entry:expr),* $(,)?) => {{
+        const LEN: usize = <[()]>::len(&[$($crate::codebook!(@unit $entry)),*]);
+        const _: () = {
+            assert!(LEN <= $crate::CODEBOOK_SIZE, "codebook has too many entries");
+            $(assert!(!$entry.is_empty(), "codebook has an empty entry");)*
+            $(assert!($entry.len() <= $crate::MAX_MATCH_LEN, "codebook entry is too long");)*
+        };
+        let entries: [&[u8]; LEN] = [$(::std::convert::AsRef::<[u8]>::as_ref($entry)),*];
+        $crate::Codebook::new(entries).unwrap()
+    }};
+}
+
+impl Dictionary for Codebook {
+    fn lookup(&self, entry: &[u8]) -> Option<u8> {
+        self.index.get(entry)
+    }
+
+    fn entry(&self, code: u8) -> Option<&[u8]> {
+        if self.escape == Some(code) {
+            return None;
+        }
+        self.get(code)
+    }
+
+    fn can_start(&self, byte: u8) -> bool {
+        self.can_start_with(byte)
+    }
+
+    fn max_len(&self) -> usize {
+        self.max_len
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::builtin::BuiltinCodebook;
+    use crate::tests::{Rng, TEST_STRINGS};
+    use crate::{compress_with, decompress_with};
+
+    /// Asserts that a codebook rebuilt from its entries compresses the same.
+    fn assert_reload_eq(codebook: &Codebook) {
+        let reloaded = Codebook::new(codebook.iter()).unwrap();
+        for s in TEST_STRINGS.iter() {
+            assert_eq!(
+                compress_with(s.as_bytes(), codebook),
+                compress_with(s.as_bytes(), &reloaded)
+            );
+        }
+    }
+
+    #[test]
+    fn test_reload() {
+        assert_reload_eq(&DEFAULT_CODEBOOK);
+        assert_reload_eq(&Codebook::new(["s", "e", " ", "is", "s", "e", "is"]).unwrap());
+    }
+
+    #[test]
+    fn test_duplicate_entries() {
+        let codebook = Codebook::new(["a", "b", "a", "b"]).unwrap();
+        assert_eq!(vec![0, 1, 1, 0], compress_with(b"abba", &codebook));
+    }
+
+    #[test]
+    fn test_new() {
+        let codebook = Codebook::new(["a", "bc", "a"]).unwrap();
+        assert_eq!(3, codebook.len());
+        assert_eq!(Some(&b"bc"[..]), codebook.get(1));
+        assert_eq!(None, codebook.get(3));
+        assert_eq!(Some((0, 1)), codebook.find_match(b"a"));
+
+        assert_eq!(
+            CodebookError::EmptyEntry,
+            Codebook::new(["a", ""]).unwrap_err()
+        );
+        let entries: Vec<String> = (0..255).map(|i| i.to_string()).collect();
+        assert_eq!(
+            CodebookError::TooManyEntries,
+            Codebook::new(&entries).unwrap_err()
+        );
+    }
+
+    #[test]
+    fn test_default() {
+        assert_eq!(CODEBOOK.len(), DEFAULT_CODEBOOK.len());
+        for (i, entry) in CODEBOOK.iter().enumerate() {
+            assert_eq!(
+                Some((i as u8, entry.len())),
+                DEFAULT_CODEBOOK.find_match(entry.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn test_default_table() {
+        let mut map: HashMap<Vec<u8>, u8> = HashMap::new();
+        for (i, code) in CODEBOOK.iter().enumerate() {
+            map.insert(code.to_string().into_bytes(), i as u8);
+        }
+
+        for entry in CODEBOOK.iter() {
+            let entry = entry.as_bytes();
+            assert_eq!(map.get(entry).copied(), DefaultCodebook.lookup(entry));
+        }
+        for entry in [&b""[..], b"x ", b"thee", b"http:/", b"\xff", b".comm"].iter() {
+            assert_eq!(None, DefaultCodebook.lookup(entry));
+        }
+    }
+
+    #[test]
+    fn test_debug() {
+        let debug = format!("{:?}", *DEFAULT_CODEBOOK);
+        assert!(debug.starts_with(r#"Codebook[" ", "the", "e", "t", "a", "of", "o", "and", "#));
+        assert!(debug.contains(r#""he ", "to", "\r\n", "l", "#));
+        assert!(debug.contains(r#""\n\r\n", "\r\n\r", "=\"", "#));
+        assert!(debug.ends_with(r#""e c", "men", ".com"]"#));
+
+        let codebook = Codebook::new([&b"\x00\xff"[..], b"a"]).unwrap();
+        assert_eq!(r#"Codebook["\x00\xff", "a"]"#, format!("{:?}", codebook));
+    }
+
+    #[test]
+    fn test_short_tables() {
+        for a in 0..=255u8 {
+            assert_eq!(
+                DefaultCodebook::lookup_long(&[a]),
+                DefaultCodebook.lookup(&[a])
+            );
+            for b in 0..=255u8 {
+                let entry = [a, b];
+                assert_eq!(
+                    DefaultCodebook::lookup_long(&entry),
+                    DefaultCodebook.lookup(&entry)
+                );
+            }
+        }
+
+        /// The default codebook without the direct tables.
+        struct Long;
+
+        impl Dictionary for Long {
+            fn lookup(&self, entry: &[u8]) -> Option<u8> {
+                DefaultCodebook::lookup_long(entry)
+            }
+
+            fn entry(&self, code: u8) -> Option<&[u8]> {
+                DefaultCodebook.entry(code)
+            }
+
+            fn can_start(&self, byte: u8) -> bool {
+                DefaultCodebook.can_start(byte)
+            }
+
+            fn max_len(&self) -> usize {
+                DefaultCodebook.max_len()
+            }
+        }
+
+        let mut rng = Rng::new(155);
+        for _ in 0..2000 {
+            let input = rng.bytes(100);
+            assert_eq!(crate::encode(&input, &Long), crate::compress(&input));
+        }
+    }
+
+    #[test]
+    fn test_ord() {
+        let a = Codebook::new(["a", "b"]).unwrap();
+        let b = Codebook::new(["a", "c"]).unwrap();
+        assert!(a < b);
+        assert_eq!(a, Codebook::new(["a", "b"]).unwrap());
+        assert_ne!(a, Codebook::new(["b", "a"]).unwrap());
+    }
+
+    #[test]
+    fn test_escape() {
+        let codebook = Codebook::new(["a", "b", "a"])
+            .unwrap()
+            .with_escape(0)
+            .unwrap();
+        assert_eq!(Some(0), codebook.escape());
+        assert_eq!(Some((2, 1)), codebook.find_match(b"a"));
+        assert_eq!(None, codebook.entry(0));
+        assert_eq!(
+            r#"Codebook["a", "b", "a"] (escape 0)"#,
+            format!("{:?}", codebook)
+        );
+        assert_ne!(codebook, Codebook::new(["a", "b", "a"]).unwrap());
+
+        assert_eq!(
+            CodebookError::InvalidEscape,
+            Codebook::new(["a"]).unwrap().with_escape(1).unwrap_err()
+        );
+    }
+
+    /// Returns the longest match by probing every length, without the first
+    /// byte check.
+    fn find_match_probing<D: Dictionary>(codebook: &D, input: &[u8]) -> Option<(u8, usize)> {
+        (1..=input.len().min(MAX_MATCH_LEN))
+            .rev()
+            .find_map(|i| codebook.lookup(&input[..i]).map(|code| (code, i)))
+    }
+
+    #[test]
+    fn test_starts() {
+        let entries: Vec<Vec<u8>> = CODEBOOK.iter().map(|e| e.as_bytes().to_vec()).collect();
+        assert_eq!(ByteSet::starts(&entries, None), DEFAULT_STARTS);
+        assert!(DefaultCodebook.can_start(b't'));
+        assert!(!DefaultCodebook.can_start(b'\xff'));
+
+        let codebook = Codebook::new(["foo", "bar"])
+            .unwrap()
+            .with_escape(1)
+            .unwrap();
+        assert!(codebook.can_start(b'f'));
+        assert!(!codebook.can_start(b'b'));
+    }
+
+    #[test]
+    fn test_find_match_starts() {
+        let mut rng = Rng::new(129);
+        for _ in 0..1000 {
+            let input = rng.bytes(16);
+            for i in 0..input.len() {
+                let input = &input[i..];
+                assert_eq!(
+                    find_match_probing(&DefaultCodebook, input),
+                    DefaultCodebook.find_match(input)
+                );
+                for builtin in [BuiltinCodebook::English, BuiltinCodebook::Html].iter() {
+                    let codebook = builtin.codebook();
+                    assert_eq!(
+                        find_match_probing(codebook, input),
+                        codebook.find_match(input)
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_metadata() {
+        let codebook = &*DEFAULT_CODEBOOK;
+        assert_eq!(7, codebook.max_entry_len());
+        assert_eq!(DefaultCodebook.max_len(), codebook.max_entry_len());
+        for b in 0..=255 {
+            assert_eq!(DefaultCodebook.can_start(b), codebook.can_start_with(b));
+        }
+        assert!(codebook.can_start_with(b' '));
+        assert!(codebook.can_start_with(b'<'));
+        assert!(!codebook.can_start_with(b'Q'));
+        assert!(!codebook.can_start_with(0));
+
+        let codebook = Codebook::new(["a", "bcd", "efghij"]).unwrap();
+        assert_eq!(6, codebook.max_entry_len());
+        let starts: Vec<u8> = (0..=255).filter(|b| codebook.can_start_with(*b)).collect();
+        assert_eq!(b"abe".to_vec(), starts);
+
+        let codebook = codebook.with_escape(2).unwrap();
+        assert_eq!(3, codebook.max_entry_len());
+        assert!(!codebook.can_start_with(b'e'));
+        assert_eq!(
+            0,
+            Codebook::new(Vec::<&str>::new()).unwrap().max_entry_len()
+        );
+    }
+
+    #[test]
+    fn test_macro() {
+        let codebook = crate::codebook!["foo", b"bar", " ", "\r\n",];
+        assert_eq!(
+            Codebook::new(["foo", "bar", " ", "\r\n"]).unwrap(),
+            codebook
+        );
+        assert_eq!(4, codebook.len());
+
+        let input = b"foo bar\r\nbaz";
+        let compressed = compress_with(input, &codebook);
+        assert_eq!(&[0, 2, 1, 3][..], &compressed[..4]);
+        assert_eq!(
+            input.to_vec(),
+            crate::decompress_with(&compressed, &codebook).unwrap()
+        );
+
+        assert!(crate::codebook![].is_empty());
+    }
+
+    #[test]
+    fn test_export() {
+        let exported = export_codebook(&DEFAULT_CODEBOOK);
+        assert_eq!(254, exported[0]);
+        assert_eq!(&[1, b' ', 3, b't', b'h', b'e'], &exported[1..7]);
+        assert_eq!(*DEFAULT_CODEBOOK, import_codebook(&exported).unwrap());
+
+        let codebook = Codebook::new([&b"foo"[..], b"\0\xff", b"a"]).unwrap();
+        let exported = export_codebook(&codebook);
+        assert_eq!(codebook, import_codebook(&exported).unwrap());
+        assert_eq!(
+            vec![0],
+            export_codebook(&Codebook::new(Vec::<&str>::new()).unwrap())
+        );
+
+        for n in 0..exported.len() {
+            assert_eq!(
+                Err(CodebookError::Malformed),
+                import_codebook(&exported[..n])
+            );
+        }
+        let mut trailing = exported.clone();
+        trailing.push(0);
+        assert_eq!(Err(CodebookError::Malformed), import_codebook(&trailing));
+        assert_eq!(
+            Err(CodebookError::EmptyEntry),
+            import_codebook(&[2, 1, b'a', 0])
+        );
+        let mut full = vec![255];
+        for _ in 0..255 {
+            full.extend_from_slice(&[1, b'a']);
+        }
+        assert_eq!(Err(CodebookError::TooManyEntries), import_codebook(&full));
+    }
+
+    #[test]
+    fn test_default_codebook() {
+        let codebook = Codebook::default();
+        for s in TEST_STRINGS.iter() {
+            assert_eq!(
+                crate::compress(s.as_bytes()),
+                compress_with(s.as_bytes(), &codebook)
+            );
+        }
+        assert_eq!(Codebook::new(CODEBOOK.iter()).unwrap(), codebook);
+        assert!(Arc::ptr_eq(&codebook.entries, &Codebook::default().entries));
+        assert!(Arc::ptr_eq(&codebook.index, &DEFAULT_CODEBOOK.index));
+    }
+
+    #[test]
+    fn test_set_entry() {
+        let mut codebook = Codebook::new(["the", " ", "cat"]).unwrap();
+        let before = codebook.clone();
+        assert_eq!(vec![0, 1, 2], compress_with(b"the cat", &codebook));
+
+        codebook.set_entry(2, "dog").unwrap();
+        assert_eq!(vec![0, 1, 2], compress_with(b"the dog", &codebook));
+        assert_eq!(
+            vec![0, 1, 255, 2, b'c', b'a', b't'],
+            compress_with(b"the cat", &codebook)
+        );
+        assert_eq!(
+            b"the dog".to_vec(),
+            decompress_with(&[0, 1, 2], &codebook).unwrap()
+        );
+        assert!(!codebook.can_start_with(b'c'));
+        assert!(codebook.can_start_with(b'd'));
+        // clones keep the old entries
+        assert_eq!(vec![0, 1, 2], compress_with(b"the cat", &before));
+
+        // a longer entry raises the longest match
+        codebook.set_entry(1, " big ").unwrap();
+        assert_eq!(5, codebook.max_entry_len());
+        assert_eq!(vec![0, 1, 2], compress_with(b"the big dog", &codebook));
+        assert_eq!(codebook, Codebook::new(["the", " big ", "dog"]).unwrap());
+
+        assert_eq!(Err(CodebookError::InvalidCode), codebook.set_entry(3, "x"));
+        assert_eq!(Err(CodebookError::EmptyEntry), codebook.set_entry(0, ""));
+        assert_eq!(codebook, Codebook::new(["the", " big ", "dog"]).unwrap());
+
+        // the escape and frequencies stay
+        let mut escaped = Codebook::new(["a", "b", "|"])
+            .unwrap()
+            .with_escape(2)
+            .unwrap()
+            .with_frequencies(&[1, 2, 3])
+            .unwrap();
+        escaped.set_entry(0, "c").unwrap();
+        assert_eq!(Some(2), escaped.escape());
+        assert_eq!(Some(&[1, 2, 3][..]), escaped.frequencies());
+        assert_eq!(vec![0, 1], compress_with(b"cb", &escaped));
+    }
+
+    #[test]
+    fn test_entry_too_long() {
+        assert_eq!(
+            Err(CodebookError::EntryTooLong),
+            Codebook::new(["foo", "12345678"])
+        );
+        let longest = Codebook::new(["foo", "1234567"]).unwrap();
+        assert_eq!(crate::MAX_MATCH_LEN, longest.max_entry_len());
+        assert_eq!(vec![1], compress_with(b"1234567", &longest));
+
+        let mut codebook = Codebook::new(["foo"]).unwrap();
+        assert_eq!(
+            Err(CodebookError::EntryTooLong),
+            codebook.set_entry(0, "12345678")
+        );
+        assert!(CODEBOOK.iter().all(|e| e.len() <= crate::MAX_MATCH_LEN));
+    }
+}
@@ -0,0 +1,384 @@
+use crate::codebook::{DefaultCodebook, Dictionary};
+use crate::{flush_verbatim, MAX_MATCH_LEN};
+
+/// Cost of one more verbatim byte by run state: no run, a run of one byte,
+/// a longer run. A second byte turns a `254` marker into a `255` and a length.
+const LITERAL_COST: [usize; 3] = [2, 2, 1];
+
+/// A configurable compressor for the default codebook.
+///
+/// With the default settings the output is that of
+/// [`compress`](fn.compress.html). See
+/// [`greedy_lookahead`](#method.greedy_lookahead) to spend more time on a
+/// smaller output, and [`tie_break`](#method.tie_break) and
+/// [`delay_flush`](#method.delay_flush) to keep verbatim runs going.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompress, Compressor};
+///
+/// let s = b"this is an example of what works very well with smaz";
+/// let compressed = Compressor::new().greedy_lookahead(2).compress(s);
+/// assert!(compressed.len() <= compress(s).len());
+/// assert_eq!(s.to_vec(), decompress(&compressed).unwrap());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Compressor {
+    lookahead: usize,
+    tie_break: TieBreak,
+    delay_flush: bool,
+}
+
+/// What [`Compressor`](struct.Compressor.html) does when a code costs as much
+/// as a verbatim byte.
+///
+/// The only such tie is a match of a single byte while a verbatim run of 2 to
+/// 255 bytes is open: the code takes one byte, and so does adding the byte to
+/// the run. Taking the code ends the run, so a verbatim byte right after it
+/// starts a new run with its own marker, while adding the byte to the run
+/// never costs more. Runs of one byte are no tie, as growing a `254` marker to
+/// a run takes two more bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum TieBreak {
+    /// Take the code, as [`compress`](fn.compress.html) does.
+    #[default]
+    Code,
+    /// Add the byte to the verbatim run. The output is never longer than with
+    /// `Code`, and shorter when the run goes on after the byte.
+    Verbatim,
+}
+
+/// One decision of the matcher.
+#[derive(Clone, Copy)]
+enum Action {
+    Code(u8, usize),
+    Literal,
+}
+
+impl Action {
+    fn len(self) -> usize {
+        match self {
+            Action::Code(_, len) => len,
+            Action::Literal => 1,
+        }
+    }
+}
+
+impl Compressor {
+    /// Creates a compressor with the default settings.
+    pub fn new() -> Compressor {
+        Compressor::default()
+    }
+
+    /// Sets how far the matcher looks ahead before taking a match.
+    ///
+    /// With `0`, the default, the longest match is always taken. With a depth
+    /// of `n`, each decision is the first of the cheapest way to encode the
+    /// next `n + 1` match lengths of input, which may take a shorter match or
+    /// a verbatim byte so that a better match follows. The output is never
+    /// longer than with a smaller depth: the shortest output of all depths up
+    /// to `n` is kept, so compression takes about `n + 1` times as long, each
+    /// pass longer as `n` grows.
+    pub fn greedy_lookahead(mut self, depth: usize) -> Compressor {
+        self.lookahead = depth;
+        self
+    }
+
+    /// Sets how to break ties between a code and a verbatim byte of the same
+    /// cost. The default is [`TieBreak::Code`](enum.TieBreak.html).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::{compress, Compressor, TieBreak};
+    ///
+    /// // the spaces are codes, which split the digits into three runs
+    /// let s = b"10 20 30";
+    /// let compressed = Compressor::new().tie_break(TieBreak::Verbatim).compress(s);
+    /// assert_eq!(14, compress(s).len());
+    /// assert_eq!(10, compressed.len());
+    /// ```
+    pub fn tie_break(mut self, tie_break: TieBreak) -> Compressor {
+        self.tie_break = tie_break;
+        self
+    }
+
+    /// Sets whether a verbatim run may take one more byte so that a longer
+    /// match starts after it. Off by default.
+    ///
+    /// While a run of 2 to 255 bytes is open, one more byte costs one byte of
+    /// output, as much as a code. When the longest match at the next position
+    /// reaches further than the match found, and the match found would end
+    /// where no entry covers the rest of that reach, taking the byte and the
+    /// next match costs two bytes where the match found needs at least three
+    /// for the same input. A single byte of lookahead is used; see
+    /// [`greedy_lookahead`](#method.greedy_lookahead) for a wider search.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::{compress, decompress, Compressor};
+    ///
+    /// let s = b"92 http://";
+    /// let compressed = Compressor::new().delay_flush(true).compress(s);
+    /// assert!(compressed.len() < compress(s).len());
+    /// assert_eq!(s.to_vec(), decompress(&compressed).unwrap());
+    /// ```
+    pub fn delay_flush(mut self, delay_flush: bool) -> Compressor {
+        self.delay_flush = delay_flush;
+        self
+    }
+
+    /// Returns the input compressed with these settings.
+    pub fn compress(&self, input: &[u8]) -> Vec<u8> {
+        let mut best = match (self.tie_break, self.delay_flush) {
+            (TieBreak::Code, false) => crate::compress(input),
+            (tie_break, delay_flush) => encode_greedy(input, tie_break, delay_flush),
+        };
+        for depth in 1..=self.lookahead {
+            let out = encode_lookahead(input, (depth + 1) * MAX_MATCH_LEN);
+            if out.len() < best.len() {
+                best = out;
+            }
+        }
+        best
+    }
+}
+
+/// Returns the matches of entries at the start of the input.
+fn matches(input: &[u8]) -> impl Iterator<Item = (u8, usize)> + '_ {
+    let can_start = input.first().is_some_and(|b| DefaultCodebook.can_start(*b));
+    (1..=input.len().min(MAX_MATCH_LEN))
+        .filter(move |_| can_start)
+        .filter_map(move |len| {
+            DefaultCodebook
+                .lookup(&input[..len])
+                .map(|code| (code, len))
+        })
+}
+
+/// Keeps the cheaper of a known way to reach a position and a new one.
+fn relax(slot: &mut Option<(usize, Action)>, cost: usize, first: Action) {
+    if slot.is_none_or(|(c, _)| cost < c) {
+        *slot = Some((cost, first));
+    }
+}
+
+/// Returns the first action of the cheapest encoding of `window` bytes of the
+/// input, given the matches at each position, starting in verbatim run state
+/// `state`.
+fn first_action(matches: &[Vec<(u8, usize)>], window: usize, state: usize) -> Action {
+    let end = matches.len().min(window);
+    // cost and first action to reach each position in each run state
+    let mut best: Vec<[Option<(usize, Action)>; 3]> = vec![[None; 3]; end + 1];
+    for pos in 0..end {
+        for s in 0..3 {
+            let (cost, first) = match best[pos][s] {
+                Some(v) => v,
+                None if pos == 0 && s == state => (0, Action::Literal),
+                None => continue,
+            };
+            let first_or = |action| if pos == 0 { action } else { first };
+
+            relax(
+                &mut best[pos + 1][(s + 1).min(2)],
+                cost + LITERAL_COST[s],
+                first_or(Action::Literal),
+            );
+            for &(code, len) in matches[pos].iter().filter(|(_, len)| pos + len <= end) {
+                relax(
+                    &mut best[pos + len][0],
+                    cost + 1,
+                    first_or(Action::Code(code, len)),
+                );
+            }
+        }
+    }
+    best[end]
+        .iter()
+        .flatten()
+        .min_by_key(|(cost, _)| *cost)
+        .map_or(Action::Literal, |(_, first)| *first)
+}
+
+/// Returns the length of the longest match at the start of the input, `0` if
+/// there is none.
+fn longest(input: &[u8]) -> usize {
+    matches(input).last().map_or(0, |(_, len)| len)
+}
+
+/// Returns the input compressed greedily, except for matches a verbatim run
+/// open with 2 to 255 bytes takes one more byte over: single-byte matches
+/// with `TieBreak::Verbatim`, and matches a longer one follows with
+/// `delay_flush`.
+fn encode_greedy(input: &[u8], tie_break: TieBreak, delay_flush: bool) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    let mut verbatim: Vec<u8> = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let open = (2..256).contains(&(verbatim.len() % 256));
+        let delay = |len: usize| {
+            let next = longest(&input[pos + 1..]);
+            next + 1 > len && longest(&input[pos + len..]) < next + 1 - len
+        };
+        match matches(&input[pos..]).last() {
+            Some((_, 1)) if open && tie_break == TieBreak::Verbatim => {}
+            Some((_, len)) if open && delay_flush && delay(len) => {}
+            Some((code, len)) => {
+                out.extend(flush_verbatim(&verbatim));
+                verbatim.clear();
+                out.push(code);
+                pos += len;
+                continue;
+            }
+            None => {}
+        }
+        verbatim.push(input[pos]);
+        pos += 1;
+    }
+    out.extend(flush_verbatim(&verbatim));
+    out
+}
+
+fn encode_lookahead(input: &[u8], window: usize) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2);
+    let mut verbatim: Vec<u8> = Vec::new();
+    let matches: Vec<Vec<(u8, usize)>> = (0..input.len())
+        .map(|pos| matches(&input[pos..]).collect())
+        .collect();
+    let mut pos = 0;
+    while pos < input.len() {
+        let state = verbatim.len().min(2);
+        let action = first_action(&matches[pos..], window, state);
+        match action {
+            Action::Code(code, _) => {
+                out.extend(flush_verbatim(&verbatim));
+                verbatim.clear();
+                out.push(code);
+            }
+            Action::Literal => verbatim.push(input[pos]),
+        }
+        pos += action.len();
+    }
+    out.extend(flush_verbatim(&verbatim));
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Rng, TEST_STRINGS};
+    use crate::{compress, decompress};
+
+    #[test]
+    fn test_default() {
+        for s in TEST_STRINGS.iter() {
+            assert_eq!(
+                compress(s.as_bytes()),
+                Compressor::new().compress(s.as_bytes())
+            );
+        }
+    }
+
+    #[test]
+    fn test_lookahead() {
+        let mut totals = [0; 4];
+        for s in TEST_STRINGS.iter() {
+            let mut previous = usize::MAX;
+            for (depth, total) in totals.iter_mut().enumerate() {
+                let compressed = Compressor::new()
+                    .greedy_lookahead(depth)
+                    .compress(s.as_bytes());
+                assert_eq!(s.as_bytes(), &decompress(&compressed).unwrap()[..]);
+                assert!(compressed.len() <= previous);
+                previous = compressed.len();
+                *total += compressed.len();
+            }
+        }
+        assert!(totals[2] < totals[0], "{:?}", totals);
+
+        let mut rng = Rng::new(162);
+        for _ in 0..300 {
+            let input = rng.bytes(300);
+            let compressed = Compressor::new().greedy_lookahead(1).compress(&input);
+            assert_eq!(input, decompress(&compressed).unwrap());
+            assert!(compressed.len() <= compress(&input).len());
+        }
+    }
+
+    #[test]
+    fn test_encode_lookahead() {
+        // every window decodes, whether or not it is kept
+        let mut rng = Rng::new(162);
+        for window in [1, 2, 7, 30].iter() {
+            for _ in 0..200 {
+                let input = rng.bytes(600);
+                assert_eq!(
+                    input,
+                    decompress(&encode_lookahead(&input, *window)).unwrap()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_tie_break() {
+        let verbatim = Compressor::new().tie_break(TieBreak::Verbatim);
+        let mut saved = 0;
+        for s in TEST_STRINGS.iter() {
+            let compressed = verbatim.compress(s.as_bytes());
+            assert_eq!(s.as_bytes(), &decompress(&compressed).unwrap()[..]);
+            saved += compress(s.as_bytes()).len() - compressed.len();
+        }
+        assert!(saved > 0);
+
+        let mut rng = Rng::new(175);
+        for _ in 0..500 {
+            let input = rng.bytes(600);
+            let compressed = verbatim.compress(&input);
+            assert_eq!(input, decompress(&compressed).unwrap());
+            assert!(compressed.len() <= compress(&input).len());
+            let deeper = verbatim.greedy_lookahead(1).compress(&input);
+            assert!(deeper.len() <= compressed.len());
+        }
+
+        // "1" alone is no tie, " " after the run "10" is
+        assert_eq!(compress(b"1 "), verbatim.compress(b"1 "));
+        assert_eq!(vec![255, 2, b'1', b'0', b' '], verbatim.compress(b"10 "));
+        // a run of 256 bytes is full, so the code is taken
+        let mut full = vec![b'1'; 256];
+        full.push(b' ');
+        assert_eq!(compress(&full), verbatim.compress(&full));
+        assert_eq!(TieBreak::Code, TieBreak::default());
+    }
+
+    #[test]
+    fn test_delay_flush() {
+        let delayed = Compressor::new().delay_flush(true);
+        // greedily " " ends the run "92", and "http://" no longer matches
+        // after it; the run takes the space instead
+        let s = b"92 http://";
+        assert_eq!(
+            [&[255, 2, b'9', b'2', b' '][..], &compress(b"http://")].concat(),
+            delayed.compress(s)
+        );
+        assert!(delayed.compress(s).len() < compress(s).len());
+
+        let mut rng = Rng::new(186);
+        for _ in 0..500 {
+            let input = rng.bytes(600);
+            let compressed = delayed.compress(&input);
+            assert_eq!(input, decompress(&compressed).unwrap());
+            assert!(compressed.len() <= compress(&input).len());
+            let both = delayed.tie_break(TieBreak::Verbatim).compress(&input);
+            assert_eq!(input, decompress(&both).unwrap());
+        }
+        for s in TEST_STRINGS.iter() {
+            let compressed = delayed.compress(s.as_bytes());
+            assert_eq!(s.as_bytes(), &decompress(&compressed).unwrap()[..]);
+        }
+        // no run is open before the match
+        assert_eq!(compress(b"http://"), delayed.compress(b"http://"));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{CODEBOOK_SIZE, MAX_MATCH_LEN};
+
+const ENTRIES: [&str; CODEBOOK_SIZE] = include!("codebook.in");
+
+/// Returns the code and length of the longest entry matching at `pos`, by
+/// comparing against every entry, as no lookup table is usable in a constant
+/// expression.
+const fn find_match(input: &[u8], pos: usize) -> Option<(u8, usize)> {
+    let mut len = input.len() - pos;
+    if len > MAX_MATCH_LEN {
+        len = MAX_MATCH_LEN;
+    }
+    while len > 0 {
+        let mut code = 0;
+        while code < CODEBOOK_SIZE {
+            let entry = ENTRIES[code].as_bytes();
+            if entry.len() == len {
+                let mut i = 0;
+                while i < len && entry[i] == input[pos + i] {
+                    i += 1;
+                }
+                if i == len {
+                    return Some((code as u8, len));
+                }
+            }
+            code += 1;
+        }
+        len -= 1;
+    }
+    None
+}
+
+/// Writes the verbatim bytes `input[start..end]` at `len` as runs of at most
+/// 256 bytes, and returns the new length.
+const fn write_verbatim(
+    input: &[u8],
+    start: usize,
+    end: usize,
+    out: &mut [u8],
+    mut len: usize,
+) -> usize {
+    let mut run = start;
+    while run < end {
+        let mut n = end - run;
+        if n > 256 {
+            n = 256;
+        }
+        if n > 1 {
+            len = write(out, len, 255);
+            len = write(out, len, (n - 1) as u8);
+        } else {
+            len = write(out, len, 254);
+        }
+        let mut i = 0;
+        while i < n {
+            len = write(out, len, input[run + i]);
+            i += 1;
+        }
+        run += n;
+    }
+    len
+}
+
+/// Writes a byte at `len` if the buffer has room for it, and returns the new
+/// length either way, so that an empty buffer counts the output.
+const fn write(out: &mut [u8], len: usize, byte: u8) -> usize {
+    if len < out.len() {
+        out[len] = byte;
+    }
+    len + 1
+}
+
+/// Compresses into `out` as [`compress`](fn.compress.html) does, and returns
+/// the length of the output.
+const fn encode(input: &[u8], out: &mut [u8]) -> usize {
+    let mut len = 0;
+    let mut verbatim = 0;
+    let mut pos = 0;
+    while pos < input.len() {
+        match find_match(input, pos) {
+            Some((code, n)) => {
+                len = write_verbatim(input, verbatim, pos, out, len);
+                len = write(out, len, code);
+                pos += n;
+                verbatim = pos;
+            }
+            None => pos += 1,
+        }
+    }
+    write_verbatim(input, verbatim, pos, out, len)
+}
+
+/// Returns the length of the output of [`compress`](fn.compress.html), in a
+/// constant expression.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compressed_len_const};
+///
+/// const LEN: usize = compressed_len_const(b"the end");
+/// assert_eq!(compress(b"the end").len(), LEN);
+/// ```
+pub const fn compressed_len_const(input: &[u8]) -> usize {
+    encode(input, &mut [])
+}
+
+/// Returns the output of [`compress`](fn.compress.html) as an array, in a
+/// constant expression.
+///
+/// Matching compares against every entry of the codebook, which is slow but
+/// only costs compile time. The length of the array is given by
+/// [`compressed_len_const`](fn.compressed_len_const.html), as
+/// [`compress_const!`](macro.compress_const.html) does.
+///
+/// # Panics
+///
+/// Panics if `N` is not the length of the output, which fails the build in a
+/// constant expression.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_array};
+///
+/// const COMPRESSED: [u8; 3] = compress_array(b"the end");
+/// assert_eq!(compress(b"the end"), COMPRESSED);
+/// ```
+pub const fn compress_array<const N: usize>(input: &[u8]) -> [u8; N] {
+    let mut out = [0; N];
+    assert!(
+        encode(input, &mut out) == N,
+        "array length is not the compressed length"
+    );
+    out
+}
+
+/// Compresses a string at compile time into a byte array.
+///
+/// The array holds the output of [`compress`](fn.compress.html) for the
+/// string, which must be a constant expression, so strings embedded in a
+/// binary, such as messages of firmware, take no more room than their
+/// compressed form and need no compression at runtime. A reference to the
+/// array can be a `&'static [u8]` constant, as in the examples.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_const, decompress};
+///
+/// const GREETING: &[u8] = &compress_const!("the end of the world");
+/// static MESSAGES: [&[u8]; 2] = [
+///     &compress_const!("not found"),
+///     &compress_const!("permission denied"),
+/// ];
+///
+/// assert!(GREETING.len() < "the end of the world".len());
+/// assert_eq!(b"the end of the world".to_vec(), decompress(GREETING).unwrap());
+/// assert_eq!(b"not found".to_vec(), decompress(MESSAGES[0]).unwrap());
+/// ```
+#[macro_export]
+macro_rules! compress_const {
+    ($input:expr) => {{
+        const INPUT: &[u8] = <str>::as_bytes($input);
+        const LEN: usize = $crate::compressed_len_const(INPUT);
+        const COMPRESSED: [u8; LEN] = $crate::compress_array(INPUT);
+        COMPRESSED
+    }};
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{inputs, TEST_STRINGS};
+    use crate::{compress, decompress};
+
+    const EMBEDDED: [&[u8]; 3] = [
+        &compress_const!(""),
+        &compress_const!("the end"),
+        &compress_const!("Smaz is a simple compression library"),
+    ];
+
+    #[test]
+    fn test_compress_const() {
+        for (compressed, s) in EMBEDDED.iter().zip(["", "the end", TEST_STRINGS[5]]) {
+            assert_eq!(compress(s.as_bytes()), *compressed);
+            assert_eq!(s.as_bytes(), &decompress(compressed).unwrap()[..]);
+        }
+    }
+
+    #[test]
+    fn test_encode() {
+        let mut inputs = inputs(198);
+        inputs.push(vec![0x80; 513]);
+        for input in &inputs {
+            let expected = compress(input);
+            assert_eq!(expected.len(), compressed_len_const(input));
+            let mut out = vec![0; expected.len()];
+            assert_eq!(expected.len(), encode(input, &mut out));
+            assert_eq!(expected, out);
+        }
+    }
+}
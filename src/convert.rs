@@ -0,0 +1,224 @@
+use std::ffi::{CStr, CString};
+use std::result;
+use std::string::FromUtf16Error;
+
+use crate::codebook::{DefaultCodebook, Dictionary};
+use crate::reader::Reader;
+use crate::{compress, decompress, DecompressError, Result};
+
+/// Returns the compressed bytes of a C string, without its terminating NUL.
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::CStr;
+///
+/// use smaz::{compress, compress_cstr};
+///
+/// let s = CStr::from_bytes_with_nul(b"the end\0").unwrap();
+/// assert_eq!(compress(b"the end"), compress_cstr(s));
+/// ```
+pub fn compress_cstr(s: &CStr) -> Vec<u8> {
+    compress(s.to_bytes())
+}
+
+/// Returns decompressed data as a C string.
+///
+/// # Errors
+///
+/// If the compressed data is invalid or the decompressed data contains a NUL
+/// byte, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+///
+/// # Examples
+///
+/// ```
+/// use std::ffi::CString;
+///
+/// use smaz::{compress_cstr, decompress_cstring};
+///
+/// let s = CString::new("the end").unwrap();
+/// assert_eq!(s, decompress_cstring(&compress_cstr(&s)).unwrap());
+/// ```
+pub fn decompress_cstring(input: &[u8]) -> Result<CString> {
+    CString::new(decompress(input)?).map_err(|_| DecompressError)
+}
+
+/// Returns the first `max_chars` characters of decompressed UTF-8 text, or all
+/// of it if it is shorter.
+///
+/// Decompression stops as soon as the character after the last one kept
+/// starts, so a short prefix of a long text is cheap, and the data after it is
+/// neither decoded nor checked. A character split across codes or verbatim
+/// runs is kept whole or not at all.
+///
+/// # Errors
+///
+/// If the compressed data read is invalid, or the characters kept are not
+/// UTF-8, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompress_truncated};
+///
+/// let compressed = compress("caf\u{e9} cr\u{e8}me".as_bytes());
+/// assert_eq!("caf\u{e9}", decompress_truncated(&compressed, 4).unwrap());
+/// assert_eq!("caf\u{e9} cr\u{e8}me", decompress_truncated(&compressed, 100).unwrap());
+/// ```
+pub fn decompress_truncated(input: &[u8], max_chars: usize) -> Result<String> {
+    let mut reader = Reader::new(input);
+    let mut out = Vec::new();
+    let (mut chars, mut end) = (0, 0);
+    'decode: while !reader.is_empty() {
+        DefaultCodebook.expand(reader.read_step()?, &mut out)?;
+        while end < out.len() {
+            // every byte but a continuation byte starts a character
+            if out[end] & 0xc0 != 0x80 {
+                if chars == max_chars {
+                    break 'decode;
+                }
+                chars += 1;
+            }
+            end += 1;
+        }
+    }
+    out.truncate(end);
+    String::from_utf8(out).map_err(|_| DecompressError)
+}
+
+/// Returns the compressed bytes of UTF-16 text, transcoded to UTF-8 first.
+///
+/// UTF-16 of mostly ASCII text has a zero byte in every other position and
+/// matches no codebook entry, while its UTF-8 form compresses as any text.
+/// Read it back with [`decompress_utf16`](fn.decompress_utf16.html).
+///
+/// # Errors
+///
+/// If the text has an unpaired surrogate, then an error is returned
+/// [`FromUtf16Error`](https://doc.rust-lang.org/std/string/struct.FromUtf16Error.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_utf16, decompress_utf16};
+///
+/// let units: Vec<u16> = "the end".encode_utf16().collect();
+/// let compressed = compress_utf16(&units).unwrap();
+/// assert_eq!(compress(b"the end"), compressed);
+/// assert_eq!(units, decompress_utf16(&compressed).unwrap());
+/// assert!(compress_utf16(&[0xd800]).is_err());
+/// ```
+pub fn compress_utf16(units: &[u16]) -> result::Result<Vec<u8>, FromUtf16Error> {
+    Ok(compress(String::from_utf16(units)?.as_bytes()))
+}
+
+/// Returns decompressed data as UTF-16 text.
+///
+/// # Errors
+///
+/// If the compressed data is invalid or the decompressed data is not UTF-8,
+/// then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_utf16(input: &[u8]) -> Result<Vec<u16>> {
+    let s = String::from_utf8(decompress(input)?).map_err(|_| DecompressError)?;
+    Ok(s.encode_utf16().collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TEST_STRINGS;
+
+    #[test]
+    fn test_cstr() {
+        for s in TEST_STRINGS.iter() {
+            let c = CString::new(*s).unwrap();
+            let compressed = compress_cstr(&c);
+            assert_eq!(compress(s.as_bytes()), compressed);
+            let decompressed = decompress_cstring(&compressed).unwrap();
+            assert_eq!(c, decompressed);
+            assert_eq!(s.len() + 1, decompressed.as_bytes_with_nul().len());
+        }
+
+        let c = CString::new(&b"\x01\xff\xfe high bytes"[..]).unwrap();
+        assert_eq!(c, decompress_cstring(&compress_cstr(&c)).unwrap());
+    }
+
+    #[test]
+    fn test_cstring_interior_nul() {
+        assert!(decompress_cstring(&compress(b"foo\0bar")).is_err());
+    }
+
+    #[test]
+    fn test_utf16() {
+        let astral = "\u{1f980} crab, \u{10348} gothic, \u{1d11e} clef";
+        let bmp = "caf\u{e9} \u{3b1}\u{3b2}\u{3b3} \u{65e5}\u{672c}";
+        for s in TEST_STRINGS.iter().chain([&astral, &bmp]) {
+            let units: Vec<u16> = s.encode_utf16().collect();
+            let compressed = compress_utf16(&units).unwrap();
+            assert_eq!(compress(s.as_bytes()), compressed);
+            assert_eq!(units, decompress_utf16(&compressed).unwrap());
+        }
+        // the crab is a surrogate pair
+        let units: Vec<u16> = astral.encode_utf16().collect();
+        assert_eq!([0xd83e, 0xdd80], units[..2]);
+
+        // half as long as the UTF-16 bytes compressed
+        let units: Vec<u16> = TEST_STRINGS[1].encode_utf16().collect();
+        let bytes: Vec<u8> = units.iter().flat_map(|u| u.to_le_bytes()).collect();
+        assert!(compress_utf16(&units).unwrap().len() * 2 < compress(&bytes).len());
+    }
+
+    #[test]
+    fn test_utf16_invalid() {
+        // unpaired and reversed surrogates
+        for units in [&[0xd83e][..], &[0xdd80, 0xd83e], &[0x61, 0xdc00, 0x62]] {
+            assert!(compress_utf16(units).is_err());
+        }
+        assert!(decompress_utf16(&compress(b"\xc3\x28")).is_err());
+        assert!(decompress_utf16(&[254]).is_err());
+        assert!(decompress_utf16(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_truncated() {
+        let long = TEST_STRINGS.join(" \u{1f980} caf\u{e9} \u{65e5}\u{672c}\u{8a9e} ");
+        for s in TEST_STRINGS.iter().chain([&long.as_str()]) {
+            let compressed = compress(s.as_bytes());
+            let len = s.chars().count();
+            for n in 0..len + 3 {
+                let truncated = decompress_truncated(&compressed, n).unwrap();
+                assert_eq!(n.min(len), truncated.chars().count());
+                assert_eq!(s.chars().take(n).collect::<String>(), truncated);
+            }
+        }
+
+        // the crab takes four bytes in a verbatim run
+        let compressed = compress("the \u{1f980}".as_bytes());
+        assert_eq!("the ", decompress_truncated(&compressed, 4).unwrap());
+        assert_eq!(
+            "the \u{1f980}",
+            decompress_truncated(&compressed, 5).unwrap()
+        );
+        assert_eq!("", decompress_truncated(&[], 5).unwrap());
+    }
+
+    #[test]
+    fn test_truncated_invalid() {
+        // data after the characters kept is not read
+        let mut compressed = compress(b"the end");
+        compressed.push(254);
+        assert!(decompress(&compressed).is_err());
+        assert_eq!("the", decompress_truncated(&compressed, 3).unwrap());
+        assert!(decompress_truncated(&compressed, 7).is_err());
+
+        // a split character, a lone continuation byte, and invalid data
+        let crab = "\u{1f980}".as_bytes();
+        assert!(decompress_truncated(&compress(&crab[..3]), 1).is_err());
+        assert!(decompress_truncated(&compress(&crab[1..]), 1).is_err());
+        assert!(decompress_truncated(&compress(b"\xff the end"), 3).is_err());
+        assert!(decompress_truncated(&[255, 5, b'a'], 1).is_err());
+    }
+}
@@ -0,0 +1,97 @@
+use crate::codebook::{DefaultCodebook, Dictionary};
+use crate::reader::Reader;
+use crate::Result;
+
+/// A decompressor reusing its output buffer between calls.
+///
+/// Each call to [`decompress`](#method.decompress) overwrites the output of
+/// the previous one, so decoding many records allocates only when a record is
+/// longer than any before it.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, Decompressor};
+///
+/// let records = [compress(b"the end"), compress(b"foobar")];
+/// let mut decompressor = Decompressor::new();
+/// for (record, expected) in records.iter().zip([&b"the end"[..], b"foobar"]) {
+///     assert_eq!(expected, decompressor.decompress(record).unwrap());
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Decompressor {
+    buf: Vec<u8>,
+}
+
+impl Decompressor {
+    /// Creates a decompressor with an empty buffer.
+    pub fn new() -> Decompressor {
+        Decompressor::default()
+    }
+
+    /// Creates a decompressor with room for `capacity` bytes of output.
+    pub fn with_capacity(capacity: usize) -> Decompressor {
+        Decompressor {
+            buf: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of bytes of output the buffer holds without
+    /// reallocating.
+    pub fn capacity(&self) -> usize {
+        self.buf.capacity()
+    }
+
+    /// Returns the decompressed data of the input, borrowed from the buffer of
+    /// the decompressor.
+    ///
+    /// The slice borrows the decompressor mutably, so it must be dropped, or
+    /// copied, before the next call. The input is decompressed as by
+    /// [`decompress`](fn.decompress.html).
+    ///
+    /// # Errors
+    ///
+    /// If the compressed data is invalid or encoded incorrectly, then an error
+    /// is returned [`DecompressError`](struct.DecompressError.html).
+    pub fn decompress(&mut self, input: &[u8]) -> Result<&[u8]> {
+        self.buf.clear();
+        let mut reader = Reader::new(input);
+        while !reader.is_empty() {
+            DefaultCodebook.expand(reader.read_step()?, &mut self.buf)?;
+        }
+        Ok(&self.buf)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TEST_STRINGS;
+    use crate::{compress, decompress};
+
+    #[test]
+    fn test_decompressor() {
+        assert_eq!(0, Decompressor::new().capacity());
+
+        let longest = TEST_STRINGS.iter().map(|s| s.len()).max().unwrap();
+        let mut decompressor = Decompressor::with_capacity(longest);
+        let capacity = decompressor.capacity();
+        let ptr = decompressor.buf.as_ptr();
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress(s.as_bytes());
+            assert_eq!(s.as_bytes(), decompressor.decompress(&compressed).unwrap());
+        }
+        assert_eq!(capacity, decompressor.capacity());
+        assert_eq!(ptr, decompressor.buf.as_ptr());
+
+        // an error leaves the decompressor usable
+        assert!(decompressor.decompress(&[0, 255, 9]).is_err());
+        let compressed = compress(b"the end");
+        assert_eq!(
+            decompress(&compressed).unwrap(),
+            decompressor.decompress(&compressed).unwrap()
+        );
+        assert!(decompressor.decompress(b"").unwrap().is_empty());
+    }
+}
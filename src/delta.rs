@@ -0,0 +1,141 @@
+use crate::reader::{write_varint, Reader};
+use crate::{compress, decompress, DecompressError, Result};
+
+/// Shortest match copied from the base, shorter ones are cheaper as literals.
+const MIN_COPY: usize = 4;
+
+/// Returns the offset and length of the longest run of `base` that `target`
+/// starts with.
+fn longest_match(base: &[u8], target: &[u8]) -> (usize, usize) {
+    let mut best = (0, 0);
+    for offset in 0..base.len() {
+        let len = base[offset..]
+            .iter()
+            .zip(target)
+            .take_while(|(a, b)| a == b)
+            .count();
+        if len > best.1 {
+            best = (offset, len);
+        }
+    }
+    best
+}
+
+fn push_record(out: &mut Vec<u8>, literal: &[u8], copy: (usize, usize)) {
+    let compressed = compress(literal);
+    write_varint(out, compressed.len());
+    out.extend_from_slice(&compressed);
+    write_varint(out, copy.1);
+    if copy.1 > 0 {
+        write_varint(out, copy.0);
+    }
+}
+
+/// Returns `target` compressed against `base`.
+///
+/// Runs of at least 4 bytes that also occur in `base` are stored as
+/// references into it, and the remaining bytes are compressed as usual, so a
+/// target similar to the base compresses to a few bytes. The delta is a
+/// sequence of records, each made of:
+///
+/// - the length of a literal as an unsigned LEB128 varint, followed by the
+///   literal compressed with [`compress`](fn.compress.html);
+/// - the length of a copy from `base` as a varint, followed by the offset of
+///   the copy in `base` as a varint if the length is not 0.
+///
+/// Finding matches takes time proportional to the product of both lengths, so
+/// this is meant for short strings.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_delta, decompress_delta};
+///
+/// let base = b"user:1234:profile:name";
+/// let target = b"user:1234:profile:mail";
+/// let delta = compress_delta(base, target);
+/// assert!(delta.len() < 8);
+/// assert_eq!(target.to_vec(), decompress_delta(base, &delta).unwrap());
+/// ```
+pub fn compress_delta(base: &[u8], target: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut literal_start = 0;
+    let mut i = 0;
+    while i < target.len() {
+        let copy = longest_match(base, &target[i..]);
+        if copy.1 >= MIN_COPY {
+            push_record(&mut out, &target[literal_start..i], copy);
+            i += copy.1;
+            literal_start = i;
+        } else {
+            i += 1;
+        }
+    }
+    if literal_start < target.len() || out.is_empty() {
+        push_record(&mut out, &target[literal_start..], (0, 0));
+    }
+    out
+}
+
+/// Returns the target reconstructed from `base` and a delta produced by
+/// [`compress_delta`](fn.compress_delta.html).
+///
+/// # Errors
+///
+/// If the delta is invalid or references bytes outside of `base`, then an
+/// error is returned [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_delta(base: &[u8], delta: &[u8]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    let mut reader = Reader::new(delta);
+    while !reader.is_empty() {
+        let len = reader.read_varint()?;
+        out.append(&mut decompress(reader.read_slice(len)?)?);
+
+        let len = reader.read_varint()?;
+        if len > 0 {
+            let offset = reader.read_varint()?;
+            let end = offset.checked_add(len).ok_or(DecompressError)?;
+            out.extend_from_slice(base.get(offset..end).ok_or(DecompressError)?);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TEST_STRINGS;
+
+    #[test]
+    fn test_roundtrip() {
+        for base in TEST_STRINGS.iter() {
+            for target in TEST_STRINGS.iter() {
+                let delta = compress_delta(base.as_bytes(), target.as_bytes());
+                let decompressed = decompress_delta(base.as_bytes(), &delta).unwrap();
+                assert_eq!(target.as_bytes(), &decompressed[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_similar() {
+        let base = b"Nel mezzo del cammin di nostra vita, mi ritrovai in una selva oscura";
+        let target = b"Nel mezzo del cammin di vostra vita, mi ritrovai in una selva oscura!";
+        let delta = compress_delta(base, target);
+        assert!(delta.len() < 12);
+        assert!(delta.len() * 3 < compress(target).len());
+        assert_eq!(target.to_vec(), decompress_delta(base, &delta).unwrap());
+
+        assert_eq!(3, compress_delta(base, base).len());
+        assert_eq!(vec![0, 0], compress_delta(base, b""));
+        assert_eq!(b"".to_vec(), decompress_delta(base, &[0, 0]).unwrap());
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(decompress_delta(b"abc", &[0, 4, 0]).is_err());
+        assert!(decompress_delta(b"abc", &[0, 1, 3]).is_err());
+        assert!(decompress_delta(b"abc", &[2, 1]).is_err());
+        assert!(decompress_delta(b"abc", &[0, 0x80]).is_err());
+    }
+}
@@ -0,0 +1,250 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::result;
+
+use crate::codebook::{CodebookError, Escaped};
+use crate::reader::{write_varint, Reader};
+use crate::{flush_verbatim, DecompressError, Result, Step, MAX_MATCH_LEN};
+
+/// Code introducing an extended index.
+const EXTENDED: u8 = 253;
+
+/// A dictionary of any number of byte strings, for
+/// [`compress_extended`](fn.compress_extended.html).
+///
+/// Like [`Codebook`](struct.Codebook.html), the index of an entry is its
+/// position and the first occurrence of a duplicate entry is used for
+/// compression, but there is no limit of 254 entries.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::ExtendedCodebook;
+///
+/// let words: Vec<String> = (0..500).map(|i| format!("w{}", i)).collect();
+/// let codebook = ExtendedCodebook::new(&words).unwrap();
+/// assert_eq!(500, codebook.len());
+/// assert_eq!(Some(&b"w300"[..]), codebook.get(300));
+/// ```
+#[derive(Clone, PartialEq, Eq)]
+pub struct ExtendedCodebook {
+    entries: Vec<Vec<u8>>,
+    index: BTreeMap<Vec<u8>, usize>,
+}
+
+impl ExtendedCodebook {
+    /// Creates a dictionary from a list of entries.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`CodebookError`](enum.CodebookError.html) if any entry is
+    /// empty or longer than [`MAX_MATCH_LEN`](constant.MAX_MATCH_LEN.html)
+    /// bytes.
+    pub fn new<I, E>(entries: I) -> result::Result<ExtendedCodebook, CodebookError>
+    where
+        I: IntoIterator<Item = E>,
+        E: AsRef<[u8]>,
+    {
+        let entries: Vec<Vec<u8>> = entries.into_iter().map(|e| e.as_ref().to_vec()).collect();
+        if entries.iter().any(|e| e.is_empty()) {
+            return Err(CodebookError::EmptyEntry);
+        }
+        if entries.iter().any(|e| e.len() > MAX_MATCH_LEN) {
+            return Err(CodebookError::EntryTooLong);
+        }
+
+        let mut index = BTreeMap::new();
+        for (i, entry) in entries.iter().enumerate() {
+            index.entry(entry.clone()).or_insert(i);
+        }
+        Ok(ExtendedCodebook { entries, index })
+    }
+
+    /// Returns the number of entries.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the dictionary has no entries.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Returns the entry at an index, or `None` if the index is out of range.
+    pub fn get(&self, index: usize) -> Option<&[u8]> {
+        self.entries.get(index).map(|v| v.as_slice())
+    }
+
+    /// Returns the index and length of the longest entry at the start of
+    /// `input`.
+    fn find_match(&self, input: &[u8]) -> Option<(usize, usize)> {
+        (1..=input.len().min(MAX_MATCH_LEN))
+            .rev()
+            .find_map(|i| self.index.get(&input[..i]).map(|index| (*index, i)))
+    }
+}
+
+impl fmt::Debug for ExtendedCodebook {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("ExtendedCodebook")?;
+        f.debug_list()
+            .entries(self.entries.iter().map(|e| Escaped(e)))
+            .finish()
+    }
+}
+
+/// Returns data compressed with a dictionary of any size.
+///
+/// Indices 0 to 252 are written as a single code, like
+/// [`compress_with`](fn.compress_with.html). Code `253` introduces a larger
+/// index: it is followed by the index minus 253 as an unsigned LEB128 varint,
+/// so indices up to 380 take two bytes and each further 7 bits one more byte.
+/// Codes `254` and `255` are the usual verbatim markers.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_extended, decompress_extended, ExtendedCodebook};
+///
+/// let words: Vec<String> = (0..500).map(|i| format!("<{}>", i)).collect();
+/// let codebook = ExtendedCodebook::new(&words).unwrap();
+/// let compressed = compress_extended(b"<7><300>", &codebook);
+/// assert_eq!(vec![7, 253, 47], compressed);
+/// assert_eq!(b"<7><300>".to_vec(), decompress_extended(&compressed, &codebook).unwrap());
+/// ```
+pub fn compress_extended(input: &[u8], codebook: &ExtendedCodebook) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    let mut verbatim_start = 0;
+    let mut i = 0;
+
+    while i < input.len() {
+        let (index, len) = match codebook.find_match(&input[i..]) {
+            Some(v) => v,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        out.extend_from_slice(&flush_verbatim(&input[verbatim_start..i]));
+        if index < EXTENDED as usize {
+            out.push(index as u8);
+        } else {
+            out.push(EXTENDED);
+            write_varint(&mut out, index - EXTENDED as usize);
+        }
+        i += len;
+        verbatim_start = i;
+    }
+    out.extend_from_slice(&flush_verbatim(&input[verbatim_start..]));
+    out
+}
+
+/// Returns data decompressed from the format of
+/// [`compress_extended`](fn.compress_extended.html).
+///
+/// # Errors
+///
+/// If the compressed data is invalid or uses an index missing from the
+/// dictionary, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_extended(input: &[u8], codebook: &ExtendedCodebook) -> Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        let index = match reader.read_step()? {
+            Step::Code(EXTENDED) => reader
+                .read_varint()?
+                .checked_add(EXTENDED as usize)
+                .ok_or(DecompressError)?,
+            Step::Code(code) => code as usize,
+            Step::Verbatim(bytes) => {
+                out.extend_from_slice(bytes);
+                continue;
+            }
+        };
+        out.extend_from_slice(codebook.get(index).ok_or(DecompressError)?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Rng, TEST_STRINGS};
+    use crate::{compress, CODEBOOK};
+
+    /// Returns the default entries followed by several hundred more.
+    fn codebook() -> ExtendedCodebook {
+        let mut entries: Vec<Vec<u8>> = CODEBOOK[..253]
+            .iter()
+            .map(|e| e.as_bytes().to_vec())
+            .collect();
+        entries.extend((0..20000).map(|i| format!("#{}", i).into_bytes()));
+        ExtendedCodebook::new(&entries).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let codebook = codebook();
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_extended(s.as_bytes(), &codebook);
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_extended(&compressed, &codebook).unwrap()[..]
+            );
+        }
+
+        let mut rng = Rng::new(145);
+        for _ in 0..500 {
+            let mut input = rng.bytes(200);
+            input.extend(format!("#{} ", rng.next_u64() % 30000).into_bytes());
+            let compressed = compress_extended(&input, &codebook);
+            assert_eq!(input, decompress_extended(&compressed, &codebook).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_indices() {
+        let codebook = codebook();
+        // "#0" is index 253, "#127" 380, "#128" 381
+        assert_eq!(vec![253, 0], compress_extended(b"#0", &codebook));
+        assert_eq!(vec![253, 127], compress_extended(b"#127", &codebook));
+        assert_eq!(vec![253, 0x80, 1], compress_extended(b"#128", &codebook));
+        assert_eq!(
+            vec![253, 0x9f, 0x9c, 1],
+            compress_extended(b"#19999", &codebook)
+        );
+        assert_eq!(
+            compress(b"the end"),
+            compress_extended(b"the end", &codebook)
+        );
+
+        assert!(decompress_extended(&[253], &codebook).is_err());
+        assert!(decompress_extended(&[253, 0x80], &codebook).is_err());
+        assert!(decompress_extended(&[253, 0xa0, 0x9c, 1], &codebook).is_err());
+        assert!(decompress_extended(
+            &[253, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01],
+            &codebook
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(
+            Err(CodebookError::EmptyEntry),
+            ExtendedCodebook::new(["a", ""])
+        );
+        let codebook = ExtendedCodebook::new(["a", "b", "a"]).unwrap();
+        assert_eq!(3, codebook.len());
+        assert_eq!(vec![0, 1, 0], compress_extended(b"aba", &codebook));
+        assert_eq!(
+            r#"ExtendedCodebook["a", "b", "a"]"#,
+            format!("{:?}", codebook)
+        );
+        assert!(ExtendedCodebook::new(Vec::<&str>::new())
+            .unwrap()
+            .is_empty());
+    }
+}
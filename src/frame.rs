@@ -0,0 +1,1062 @@
+use std::iter;
+
+use crate::codebook::{DefaultCodebook, Dictionary};
+use crate::reader::{write_varint, Reader};
+use crate::{compress, decompress, max_decompressed_len, DecompressError, Result, Steps};
+
+/// Returns compressed data with a trailing sentinel byte appended to the input
+/// before compression.
+///
+/// Use [`decompress_sentinel`](fn.decompress_sentinel.html) to check for the
+/// sentinel on decompression, which detects most truncated buffers.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_sentinel, decompress_sentinel};
+///
+/// let compressed = compress_sentinel(b"the end", 0);
+/// assert_eq!(b"the end".to_vec(), decompress_sentinel(&compressed, 0).unwrap());
+/// ```
+pub fn compress_sentinel(input: &[u8], sentinel: u8) -> Vec<u8> {
+    let mut data = Vec::with_capacity(input.len() + 1);
+    data.extend_from_slice(input);
+    data.push(sentinel);
+    compress(&data)
+}
+
+/// Returns decompressed data with the trailing sentinel byte removed.
+///
+/// # Errors
+///
+/// If the compressed data is invalid or the decompressed data does not end
+/// with the sentinel, e.g. because the buffer was truncated, then an error is
+/// returned [`DecompressError`](struct.DecompressError.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_sentinel, decompress_sentinel};
+///
+/// let compressed = compress_sentinel(b"foobar", 0);
+/// assert!(decompress_sentinel(&compressed[..compressed.len() - 2], 0).is_err());
+/// ```
+pub fn decompress_sentinel(input: &[u8], sentinel: u8) -> Result<Vec<u8>> {
+    let mut out = decompress(input)?;
+    match out.pop() {
+        Some(v) if v == sentinel => Ok(out),
+        _ => Err(DecompressError),
+    }
+}
+
+/// Returns the input split into blocks of `block_size` bytes, each compressed
+/// independently.
+///
+/// Any block can be decompressed on its own with
+/// [`decompress`](fn.decompress.html); the block holding byte `n` of the input
+/// is `n / block_size`.
+///
+/// # Panics
+///
+/// Panics if `block_size` is 0.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_blocks, decompress, decompress_blocks};
+///
+/// let blocks = compress_blocks(b"the end of the story", 8);
+/// assert_eq!(3, blocks.len());
+/// assert_eq!(b"of the s".to_vec(), decompress(&blocks[1]).unwrap());
+/// assert_eq!(b"the end of the story".to_vec(), decompress_blocks(&blocks).unwrap());
+/// ```
+pub fn compress_blocks(input: &[u8], block_size: usize) -> Vec<Vec<u8>> {
+    input.chunks(block_size).map(compress).collect()
+}
+
+/// Returns the concatenated decompressed data of all blocks.
+///
+/// # Errors
+///
+/// If any block is invalid, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_blocks<T: AsRef<[u8]>>(blocks: &[T]) -> Result<Vec<u8>> {
+    let mut out = Vec::new();
+    for block in blocks {
+        out.append(&mut decompress(block.as_ref())?);
+    }
+    Ok(out)
+}
+
+/// Returns the decompressed data of each buffer, or an error for each invalid
+/// one.
+///
+/// Unlike [`decompress_blocks`](fn.decompress_blocks.html), an invalid buffer
+/// does not stop the others from being decompressed.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompress_batch};
+///
+/// let results = decompress_batch(&[compress(b"foo"), vec![254], compress(b"bar")]);
+/// assert_eq!(b"foo".to_vec(), *results[0].as_ref().unwrap());
+/// assert!(results[1].is_err());
+/// assert_eq!(b"bar".to_vec(), *results[2].as_ref().unwrap());
+/// ```
+pub fn decompress_batch<T: AsRef<[u8]>>(inputs: &[T]) -> Vec<Result<Vec<u8>>> {
+    inputs.iter().map(|v| decompress(v.as_ref())).collect()
+}
+
+/// Returns a self-delimiting frame: the length of the input as an unsigned
+/// LEB128 varint followed by the compressed input.
+///
+/// Frames can be concatenated and read back one by one with
+/// [`unframe`](fn.unframe.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{frame, unframe};
+///
+/// let mut buf = frame(b"foo");
+/// buf.extend(frame(b"bar"));
+/// let (first, used) = unframe(&buf).unwrap();
+/// assert_eq!(b"foo".to_vec(), first);
+/// assert_eq!(b"bar".to_vec(), unframe(&buf[used..]).unwrap().0);
+/// ```
+pub fn frame(input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(input.len() / 2 + 1);
+    write_varint(&mut out, input.len());
+    for step in Steps::new(input, &DefaultCodebook) {
+        step.write_to(&mut out);
+    }
+    out
+}
+
+/// Returns the data of the frame at the start of the input and the number of
+/// bytes the frame takes.
+///
+/// # Errors
+///
+/// If the frame is truncated or invalid, or its data does not have the length
+/// stored in the frame, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn unframe(input: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut reader = Reader::new(input);
+    let len = reader.read_varint()?;
+    let mut out = Vec::with_capacity(len.min(max_decompressed_len(input.len())));
+    while out.len() < len {
+        DefaultCodebook.expand(reader.read_step()?, &mut out)?;
+    }
+    if out.len() != len {
+        return Err(DecompressError);
+    }
+    Ok((out, reader.position()))
+}
+
+/// Returns a column of nullable values packed into a single buffer.
+///
+/// The buffer holds the number of values as an unsigned LEB128 varint, then a
+/// bitmap of one bit per value, least significant bit first, set for `Some`
+/// values and padded with zero bits to a whole byte. Each `Some` value follows
+/// in order as the varint length of its compressed data and the compressed
+/// data itself; `None` values take no further space.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_nullable, decompress_nullable};
+///
+/// let values = [Some(&b"foo"[..]), None, Some(&b""[..])];
+/// let packed = compress_nullable(&values);
+/// let unpacked = decompress_nullable(&packed).unwrap();
+/// assert_eq!(vec![Some(b"foo".to_vec()), None, Some(Vec::new())], unpacked);
+/// ```
+pub fn compress_nullable(values: &[Option<&[u8]>]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, values.len());
+
+    let mut bitmap = vec![0; values.len().div_ceil(8)];
+    for (i, _) in values.iter().enumerate().filter(|(_, v)| v.is_some()) {
+        bitmap[i / 8] |= 1 << (i % 8);
+    }
+    out.extend_from_slice(&bitmap);
+
+    for value in values.iter().flatten() {
+        let compressed = compress(value);
+        write_varint(&mut out, compressed.len());
+        out.extend_from_slice(&compressed);
+    }
+    out
+}
+
+/// Returns the column of nullable values packed by
+/// [`compress_nullable`](fn.compress_nullable.html).
+///
+/// # Errors
+///
+/// If the buffer is truncated, has trailing bytes or padding bits set, or any
+/// value is invalid, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_nullable(input: &[u8]) -> Result<Vec<Option<Vec<u8>>>> {
+    let mut reader = Reader::new(input);
+    let len = reader.read_varint()?;
+    let bitmap = reader.read_slice(len.div_ceil(8))?;
+    if len % 8 != 0 && bitmap[len / 8] >> (len % 8) != 0 {
+        return Err(DecompressError);
+    }
+
+    let mut out = Vec::with_capacity(len);
+    for i in 0..len {
+        if bitmap[i / 8] & (1 << (i % 8)) == 0 {
+            out.push(None);
+            continue;
+        }
+        let body_len = reader.read_varint()?;
+        out.push(Some(decompress(reader.read_slice(body_len)?)?));
+    }
+    if !reader.is_empty() {
+        return Err(DecompressError);
+    }
+    Ok(out)
+}
+
+/// Returns a block of strings, each front-coded against the previous one and
+/// compressed.
+///
+/// The block starts with the number of strings as an unsigned LEB128 varint.
+/// Each string follows as the varint length of the prefix it shares with the
+/// previous string, the varint length of the compressed rest, and the
+/// compressed rest itself. Any order works, but sorted strings share the
+/// longest prefixes.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_sorted, decompress_sorted};
+///
+/// let keys = [&b"apple"[..], b"applet", b"application"];
+/// let block = compress_sorted(&keys);
+/// assert_eq!(keys.to_vec(), decompress_sorted(&block).unwrap());
+/// ```
+pub fn compress_sorted(strings: &[&[u8]]) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, strings.len());
+
+    let mut previous: &[u8] = &[];
+    for s in strings {
+        let shared = previous
+            .iter()
+            .zip(s.iter())
+            .take_while(|(a, b)| a == b)
+            .count();
+        let compressed = compress(&s[shared..]);
+        write_varint(&mut out, shared);
+        write_varint(&mut out, compressed.len());
+        out.extend_from_slice(&compressed);
+        previous = s;
+    }
+    out
+}
+
+/// Returns the strings of a block written by
+/// [`compress_sorted`](fn.compress_sorted.html).
+///
+/// # Errors
+///
+/// If the block is truncated or has trailing bytes, a prefix is longer than the
+/// previous string, or any string is invalid, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_sorted(input: &[u8]) -> Result<Vec<Vec<u8>>> {
+    let mut reader = Reader::new(input);
+    let len = reader.read_varint()?;
+
+    let mut out: Vec<Vec<u8>> = Vec::with_capacity(len.min(input.len()));
+    for _ in 0..len {
+        let shared = reader.read_varint()?;
+        let body_len = reader.read_varint()?;
+        let previous = out.last().map_or(&[][..], |v| &v[..]);
+        let mut s = previous.get(..shared).ok_or(DecompressError)?.to_vec();
+        s.extend(decompress(reader.read_slice(body_len)?)?);
+        out.push(s);
+    }
+    if !reader.is_empty() {
+        return Err(DecompressError);
+    }
+    Ok(out)
+}
+
+/// How [`compress_or_store`](fn.compress_or_store.html) stored its input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageKind {
+    /// Compressed with the default codebook.
+    Smaz,
+    /// The input as is.
+    Raw,
+}
+
+/// Returns the compressed input if it is shorter than the input, or else the
+/// input as is, along with which of the two it is.
+///
+/// The kind is not stored in the bytes; keep it alongside them and pass it to
+/// [`decompress_stored`](fn.decompress_stored.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_or_store, decompress_stored, StorageKind};
+///
+/// let (kind, bytes) = compress_or_store(b"the end");
+/// assert_eq!(StorageKind::Smaz, kind);
+/// assert_eq!(b"the end".to_vec(), decompress_stored(kind, &bytes).unwrap());
+///
+/// assert_eq!(StorageKind::Raw, compress_or_store(b"XQZ").0);
+/// ```
+pub fn compress_or_store(input: &[u8]) -> (StorageKind, Vec<u8>) {
+    let compressed = compress(input);
+    if compressed.len() < input.len() {
+        (StorageKind::Smaz, compressed)
+    } else {
+        (StorageKind::Raw, input.to_vec())
+    }
+}
+
+/// Returns `true` and the compressed input if compression saves at least the
+/// fraction `min_savings` of its length, or else `false` and the input as is.
+///
+/// The savings are `1 - compressed / input` in bytes, so `0.25` asks for data
+/// at most three quarters the length of the input, and `0.0` for data no
+/// longer than it. Empty input is returned as is. Like
+/// [`compress_or_store`](fn.compress_or_store.html), the flag is not stored in
+/// the bytes.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_if_beneficial, decompress};
+///
+/// let (compressed, bytes) = compress_if_beneficial(b"the end", 0.5);
+/// assert!(compressed);
+/// assert_eq!(b"the end".to_vec(), decompress(&bytes).unwrap());
+///
+/// assert_eq!((false, b"XQZ".to_vec()), compress_if_beneficial(b"XQZ", 0.5));
+/// ```
+pub fn compress_if_beneficial(input: &[u8], min_savings: f64) -> (bool, Vec<u8>) {
+    let compressed = compress(input);
+    let savings = 1.0 - compressed.len() as f64 / input.len() as f64;
+    if !input.is_empty() && savings >= min_savings {
+        (true, compressed)
+    } else {
+        (false, input.to_vec())
+    }
+}
+
+/// Returns the original bytes stored by
+/// [`compress_or_store`](fn.compress_or_store.html) as `kind`.
+///
+/// # Errors
+///
+/// If `kind` is [`StorageKind::Smaz`](enum.StorageKind.html) and the
+/// compressed data is invalid, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_stored(kind: StorageKind, bytes: &[u8]) -> Result<Vec<u8>> {
+    match kind {
+        StorageKind::Smaz => decompress(bytes),
+        StorageKind::Raw => Ok(bytes.to_vec()),
+    }
+}
+
+/// Returns a compressed record: the line followed by a newline.
+///
+/// Records can be concatenated and read back one by one with
+/// [`decompress_one`](fn.decompress_one.html), which stops at the newline.
+///
+/// # Panics
+///
+/// Panics if the line contains a newline.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_line, decompress_one};
+///
+/// let mut buf = compress_line(b"foo");
+/// buf.extend(compress_line(b"bar"));
+/// let (first, used) = decompress_one(&buf).unwrap();
+/// assert_eq!(b"foo".to_vec(), first);
+/// assert_eq!(b"bar".to_vec(), decompress_one(&buf[used..]).unwrap().0);
+/// ```
+pub fn compress_line(line: &[u8]) -> Vec<u8> {
+    assert!(!line.contains(&b'\n'), "line contains a newline");
+    let mut data = Vec::with_capacity(line.len() + 1);
+    data.extend_from_slice(line);
+    data.push(b'\n');
+    compress(&data)
+}
+
+/// Returns the line of the record at the start of the input, without its
+/// newline, and the number of bytes the record takes.
+///
+/// # Errors
+///
+/// If the record is truncated or invalid, or its newline is not at the end of
+/// a code or verbatim run, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_one(input: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut reader = Reader::new(input);
+    let mut out = Vec::new();
+    loop {
+        let start = out.len();
+        DefaultCodebook.expand(reader.read_step()?, &mut out)?;
+        match out[start..].iter().position(|b| *b == b'\n') {
+            Some(i) if start + i + 1 == out.len() => break,
+            Some(_) => return Err(DecompressError),
+            None => {}
+        }
+    }
+    out.pop();
+    Ok((out, reader.position()))
+}
+
+/// Returns compressed data padded to a whole number of blocks of `block`
+/// bytes.
+///
+/// The output is the length of the compressed data as an unsigned LEB128
+/// varint, the compressed data, then zero bytes up to the next multiple of
+/// `block`. [`decompress_padded`](fn.decompress_padded.html) reads the length
+/// and ignores the padding, so the output fits fixed-size slots as is.
+///
+/// # Panics
+///
+/// Panics if `block` is zero.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_padded, decompress_padded};
+///
+/// let padded = compress_padded(b"the end", 16);
+/// assert_eq!(16, padded.len());
+/// assert_eq!(b"the end".to_vec(), decompress_padded(&padded).unwrap());
+/// ```
+pub fn compress_padded(input: &[u8], block: usize) -> Vec<u8> {
+    assert!(block > 0, "block size is zero");
+    let compressed = compress(input);
+    let mut out = Vec::with_capacity(compressed.len() + block);
+    write_varint(&mut out, compressed.len());
+    out.extend(compressed);
+    out.resize(out.len().div_ceil(block) * block, 0);
+    out
+}
+
+/// Returns data decompressed from the output of
+/// [`compress_padded`](fn.compress_padded.html), ignoring the padding.
+///
+/// The bytes after the compressed data are not read, whatever the block size
+/// was.
+///
+/// # Errors
+///
+/// If the length is truncated or longer than the input, or the compressed
+/// data is invalid, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_padded(input: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(input);
+    let n = reader.read_varint()?;
+    decompress(reader.read_slice(n)?)
+}
+
+/// The end-of-stream marker of [`compress_framed`](fn.compress_framed.html),
+/// a verbatim run of a single byte cut short.
+const EOS: [u8; 2] = [255, 0];
+
+/// Returns compressed data followed by an end-of-stream marker, so a decoder
+/// knows where the data ends without being given its length.
+///
+/// The data is identical to the output of [`compress`](fn.compress.html) and
+/// the marker is the two bytes `255 0`. Read as a verbatim run, it would hold
+/// a single byte, which [`compress`](fn.compress.html) always writes as `254`
+/// and the byte instead, so the marker never starts a token of the data. Data
+/// cut at any point before the end of the marker either ends inside a token or
+/// lacks the marker, and is reported as truncated.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_framed, decompress_framed};
+///
+/// let framed = compress_framed(b"the end");
+/// assert_eq!([&compress(b"the end")[..], &[255, 0]].concat(), framed);
+/// assert_eq!((b"the end".to_vec(), framed.len()), decompress_framed(&framed).unwrap());
+/// assert!(decompress_framed(&framed[..framed.len() - 1]).is_err());
+/// ```
+pub fn compress_framed(input: &[u8]) -> Vec<u8> {
+    let mut out = compress(input);
+    out.extend_from_slice(&EOS);
+    out
+}
+
+/// Returns the data of the stream at the start of the input, up to its
+/// end-of-stream marker, and the number of bytes the stream takes with the
+/// marker.
+///
+/// Bytes after the marker are not read.
+///
+/// # Errors
+///
+/// If the stream is truncated or lacks its end-of-stream marker, or its data
+/// is invalid, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_framed(input: &[u8]) -> Result<(Vec<u8>, usize)> {
+    let mut reader = Reader::new(input);
+    let mut out = Vec::new();
+    while !input[reader.position()..].starts_with(&EOS) {
+        DefaultCodebook.expand(reader.read_step()?, &mut out)?;
+    }
+    Ok((out, reader.position() + EOS.len()))
+}
+
+/// Returns compressed data in which no byte equals `delimiter`, so records can
+/// be split on it.
+///
+/// The data is compressed with [`compress`](fn.compress.html) and then
+/// escaped. The escape byte is the delimiter with its high bit flipped, and
+/// each delimiter or escape byte in the compressed data is written as the
+/// escape byte followed by the byte with bit 6 flipped. Neither of the two
+/// can equal the delimiter, and each escaped byte makes the output one byte
+/// longer.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_avoiding, decompress_avoiding};
+///
+/// // code 0 is " ", which compresses the spaces here
+/// assert!(compress(b"10 20 30").contains(&0));
+/// let compressed = compress_avoiding(b"10 20 30", 0);
+/// assert!(!compressed.contains(&0));
+/// assert_eq!(b"10 20 30".to_vec(), decompress_avoiding(&compressed, 0).unwrap());
+/// ```
+pub fn compress_avoiding(input: &[u8], delimiter: u8) -> Vec<u8> {
+    let escape = delimiter ^ 0x80;
+    let compressed = compress(input);
+    let mut out = Vec::with_capacity(compressed.len());
+    for b in compressed {
+        if b == delimiter || b == escape {
+            out.push(escape);
+            out.push(b ^ 0x40);
+        } else {
+            out.push(b);
+        }
+    }
+    out
+}
+
+/// Returns data decompressed from the escaped format of
+/// [`compress_avoiding`](fn.compress_avoiding.html).
+///
+/// # Errors
+///
+/// If the data contains the delimiter, an escape byte is not followed by an
+/// escaped delimiter or escape byte, or the unescaped data is invalid, then an
+/// error is returned [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_avoiding(input: &[u8], delimiter: u8) -> Result<Vec<u8>> {
+    let escape = delimiter ^ 0x80;
+    let mut compressed = Vec::with_capacity(input.len());
+    let mut bytes = input.iter();
+    while let Some(&b) = bytes.next() {
+        match b {
+            _ if b == delimiter => return Err(DecompressError),
+            _ if b == escape => {
+                let unescaped = bytes.next().ok_or(DecompressError)? ^ 0x40;
+                if unescaped != delimiter && unescaped != escape {
+                    return Err(DecompressError);
+                }
+                compressed.push(unescaped);
+            }
+            _ => compressed.push(b),
+        }
+    }
+    decompress(&compressed)
+}
+
+/// Returns the strings compressed into one blob with an offset table, so any
+/// string can be decompressed with [`get`](fn.get.html) without reading the
+/// others, as when the blob is memory-mapped.
+///
+/// The blob starts with the number of strings as a 32-bit little-endian
+/// integer, followed by one more 32-bit little-endian offset than there are
+/// strings. String `i` is compressed from offset `i` up to offset `i + 1`,
+/// counted from the start of the blob, and the last offset is the blob length.
+///
+/// # Panics
+///
+/// Panics if the blob would be larger than 4 GiB.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{get, pack};
+///
+/// let packed = pack(&[b"foo", b"the end", b"bar"]);
+/// assert_eq!(b"the end".to_vec(), get(&packed, 1).unwrap());
+/// assert!(get(&packed, 3).is_err());
+/// ```
+pub fn pack(strings: &[&[u8]]) -> Vec<u8> {
+    let table = 4 * (strings.len() + 2);
+    let mut bodies = Vec::new();
+    let mut offsets = Vec::with_capacity(strings.len() + 1);
+    offsets.push(table);
+    for s in strings {
+        bodies.extend(compress(s));
+        offsets.push(table + bodies.len());
+    }
+
+    let mut out = Vec::with_capacity(table + bodies.len());
+    for value in iter::once(strings.len()).chain(offsets) {
+        let value = u32::try_from(value).expect("packed data is larger than 4 GiB");
+        out.extend_from_slice(&value.to_le_bytes());
+    }
+    out.extend(bodies);
+    out
+}
+
+/// Returns the string at `index` of a blob written by
+/// [`pack`](fn.pack.html).
+///
+/// Only the header, two offsets and the string itself are read.
+///
+/// # Errors
+///
+/// If the index is out of range, the header or offsets are truncated or out of
+/// bounds, or the string is invalid, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn get(packed: &[u8], index: usize) -> Result<Vec<u8>> {
+    // the count comes from the header, so offsets may overflow on 32-bit
+    let read_u32 = |i: usize| -> Result<usize> {
+        let range = i
+            .checked_mul(4)
+            .and_then(|o| Some(o..o.checked_add(4)?))
+            .ok_or(DecompressError)?;
+        let bytes = packed.get(range).ok_or(DecompressError)?;
+        Ok(u32::from_le_bytes(bytes.try_into().unwrap()) as usize)
+    };
+    if index >= read_u32(0)? {
+        return Err(DecompressError);
+    }
+    // index is below the count, so only index + 2 may overflow
+    let last = index.checked_add(2).ok_or(DecompressError)?;
+    let (start, end) = (read_u32(index + 1)?, read_u32(last)?);
+    decompress(packed.get(start..end).ok_or(DecompressError)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{inputs, TEST_STRINGS};
+
+    #[test]
+    fn test_sentinel() {
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_sentinel(s.as_bytes(), 0);
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_sentinel(&compressed, 0).unwrap()[..]
+            );
+            assert!(decompress_sentinel(&compressed, 1).is_err());
+        }
+    }
+
+    #[test]
+    fn test_sentinel_truncated() {
+        let compressed = compress_sentinel(b"the end", 0);
+        for i in 0..compressed.len() {
+            assert!(decompress_sentinel(&compressed[..i], 0).is_err());
+        }
+    }
+
+    #[test]
+    fn test_blocks() {
+        let input = TEST_STRINGS.join("\n");
+        for block_size in [1, 7, 64, 10000].iter() {
+            let blocks = compress_blocks(input.as_bytes(), *block_size);
+            assert_eq!(input.as_bytes(), &decompress_blocks(&blocks).unwrap()[..]);
+            for (block, chunk) in blocks.iter().zip(input.as_bytes().chunks(*block_size)) {
+                assert_eq!(chunk, &decompress(block).unwrap()[..]);
+            }
+        }
+        assert!(compress_blocks(b"", 8).is_empty());
+    }
+
+    #[test]
+    fn test_frame() {
+        let mut buf = Vec::new();
+        for s in TEST_STRINGS.iter() {
+            buf.append(&mut frame(s.as_bytes()));
+        }
+
+        let mut used = 0;
+        for s in TEST_STRINGS.iter() {
+            let (value, len) = unframe(&buf[used..]).unwrap();
+            assert_eq!(s.as_bytes(), &value[..]);
+            used += len;
+        }
+        assert_eq!(buf.len(), used);
+
+        assert_eq!(vec![0], frame(b""));
+        assert_eq!((Vec::new(), 1), unframe(&[0, 1, 2]).unwrap());
+    }
+
+    #[test]
+    fn test_unframe_invalid() {
+        let buf = frame(b"this is a small string");
+        for i in 0..buf.len() {
+            assert!(unframe(&buf[..i]).is_err());
+        }
+        // "the" is three bytes, longer than the stored length.
+        assert!(unframe(&[2, 1]).is_err());
+    }
+
+    fn assert_nullable_roundtrip(values: &[Option<&[u8]>]) {
+        let unpacked = decompress_nullable(&compress_nullable(values)).unwrap();
+        let unpacked: Vec<Option<&[u8]>> = unpacked.iter().map(|v| v.as_deref()).collect();
+        assert_eq!(values, &unpacked[..]);
+    }
+
+    #[test]
+    fn test_nullable() {
+        let some: Vec<Option<&[u8]>> = TEST_STRINGS.iter().map(|s| Some(s.as_bytes())).collect();
+        let mixed: Vec<Option<&[u8]>> = TEST_STRINGS
+            .iter()
+            .enumerate()
+            .map(|(i, s)| if i % 3 == 0 { None } else { Some(s.as_bytes()) })
+            .collect();
+
+        assert_nullable_roundtrip(&[]);
+        assert_nullable_roundtrip(&[None; 9]);
+        assert_nullable_roundtrip(&some);
+        assert_nullable_roundtrip(&mixed);
+        assert_nullable_roundtrip(&mixed[..7]);
+
+        assert_eq!(vec![0], compress_nullable(&[]));
+        assert_eq!(vec![9, 0, 0], compress_nullable(&[None; 9]));
+    }
+
+    #[test]
+    fn test_nullable_invalid() {
+        let packed = compress_nullable(&[Some(b"foo"), None, Some(b"bar")]);
+        for i in 0..packed.len() {
+            assert!(decompress_nullable(&packed[..i]).is_err());
+        }
+        let mut trailing = packed.clone();
+        trailing.push(0);
+        assert!(decompress_nullable(&trailing).is_err());
+        // bit 3 is padding for three values
+        assert!(decompress_nullable(&[3, 0b1000]).is_err());
+    }
+
+    #[test]
+    fn test_batch() {
+        let mut inputs: Vec<Vec<u8>> = TEST_STRINGS
+            .iter()
+            .map(|s| compress(s.as_bytes()))
+            .collect();
+        let malformed = [3, 7, 10];
+        for i in malformed.iter() {
+            inputs[*i].push(255);
+        }
+        inputs.push(vec![254]);
+
+        let results = decompress_batch(&inputs);
+        assert_eq!(inputs.len(), results.len());
+        for (i, result) in results.iter().enumerate() {
+            match TEST_STRINGS.get(i) {
+                Some(s) if !malformed.contains(&i) => {
+                    assert_eq!(s.as_bytes(), &result.as_ref().unwrap()[..])
+                }
+                _ => assert!(result.is_err()),
+            }
+        }
+
+        let slices: Vec<&[u8]> = inputs.iter().map(|v| &v[..]).collect();
+        assert_eq!(results.len(), decompress_batch(&slices).len());
+        assert!(decompress_batch::<&[u8]>(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_lines() {
+        let mut buf = Vec::new();
+        for s in TEST_STRINGS.iter() {
+            buf.extend(compress_line(s.as_bytes()));
+        }
+        for line in [&b"foo\r"[..], b"\r", b"\xff\xfe", b""].iter() {
+            buf.extend(compress_line(line));
+        }
+
+        let mut lines = Vec::new();
+        let mut used = 0;
+        while used < buf.len() {
+            let (line, len) = decompress_one(&buf[used..]).unwrap();
+            lines.push(line);
+            used += len;
+        }
+        let expected: Vec<&[u8]> = TEST_STRINGS
+            .iter()
+            .map(|s| s.as_bytes())
+            .chain([&b"foo\r"[..], b"\r", b"\xff\xfe", b""].iter().copied())
+            .collect();
+        assert_eq!(expected, lines);
+    }
+
+    #[test]
+    fn test_lines_invalid() {
+        let record = compress_line(b"the end");
+        for i in 0..record.len() {
+            assert!(decompress_one(&record[..i]).is_err());
+        }
+        assert!(decompress_one(&[255, 1, b'\n', b'b']).is_err());
+        assert_eq!(
+            (b"a".to_vec(), 4),
+            decompress_one(&[254, b'a', 254, b'\n', 254, b'b']).unwrap()
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "line contains a newline")]
+    fn test_compress_line_newline() {
+        compress_line(b"foo\nbar");
+    }
+
+    #[test]
+    fn test_sorted() {
+        let keys = [
+            &b"apple"[..],
+            b"applet",
+            b"application",
+            b"apply",
+            b"b",
+            b"",
+        ];
+        let block = compress_sorted(&keys);
+        assert_eq!(keys.to_vec(), decompress_sorted(&block).unwrap());
+        let separate: usize = keys.iter().map(|k| compress(k).len() + 1).sum();
+        assert!(block.len() < separate);
+
+        let urls: Vec<String> = (0..100)
+            .map(|i| format!("http://example.com/items/{:03}", i))
+            .collect();
+        let urls: Vec<&[u8]> = urls.iter().map(|u| u.as_bytes()).collect();
+        let block = compress_sorted(&urls);
+        assert_eq!(urls, decompress_sorted(&block).unwrap());
+        let separate: usize = urls.iter().map(|k| compress(k).len() + 1).sum();
+        assert!(block.len() * 3 < separate);
+
+        assert_eq!(vec![0], compress_sorted(&[]));
+        assert!(decompress_sorted(&[]).is_err());
+        assert!(decompress_sorted(&block[..block.len() - 1]).is_err());
+        // a prefix longer than the previous string
+        assert!(decompress_sorted(&[2, 0, 1, 0, 4, 0]).is_err());
+        assert!(decompress_sorted(&[1, 0, 0, 0]).is_err());
+        assert_eq!(
+            vec![b"ab".to_vec(), b"a".to_vec()],
+            decompress_sorted(&[2, 0, 4, 255, 1, b'a', b'b', 1, 0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_compress_or_store() {
+        for s in TEST_STRINGS.iter() {
+            let (kind, bytes) = compress_or_store(s.as_bytes());
+            let expected = if s.is_empty() || *s == "not-a-g00d-Exampl333" {
+                StorageKind::Raw
+            } else {
+                StorageKind::Smaz
+            };
+            assert_eq!(expected, kind, "{:?}", s);
+            assert!(bytes.len() <= s.len());
+            assert_eq!(s.as_bytes(), &decompress_stored(kind, &bytes).unwrap()[..]);
+        }
+
+        // raw bytes are never decoded, even if they look like invalid data
+        assert_eq!(
+            vec![255],
+            decompress_stored(StorageKind::Raw, &[255]).unwrap()
+        );
+        assert!(decompress_stored(StorageKind::Smaz, &[255]).is_err());
+    }
+
+    #[test]
+    fn test_compress_if_beneficial() {
+        // 7 bytes for 19, and 20 bytes for 22
+        let good = b"this is the end of ";
+        let poor = b"1000 numbers 2000 will";
+        assert_eq!(7, compress(good).len());
+        assert_eq!(20, compress(poor).len());
+
+        assert_eq!((true, compress(good)), compress_if_beneficial(good, 0.5));
+        assert_eq!((false, poor.to_vec()), compress_if_beneficial(poor, 0.5));
+        // 9% saved
+        assert_eq!((true, compress(poor)), compress_if_beneficial(poor, 0.09));
+        assert!(!compress_if_beneficial(poor, 0.1).0);
+        assert!(compress_if_beneficial(good, 0.0).0);
+        assert!(!compress_if_beneficial(good, 1.0).0);
+        assert_eq!((false, Vec::new()), compress_if_beneficial(b"", 0.0));
+
+        for s in TEST_STRINGS.iter() {
+            let (compressed, bytes) = compress_if_beneficial(s.as_bytes(), 0.2);
+            if compressed {
+                assert!(bytes.len() * 5 <= s.len() * 4);
+                assert_eq!(s.as_bytes(), &decompress(&bytes).unwrap()[..]);
+            } else {
+                assert_eq!(s.as_bytes(), &bytes[..]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pack() {
+        let strings: Vec<&[u8]> = TEST_STRINGS.iter().map(|s| s.as_bytes()).collect();
+        let packed = pack(&strings);
+        for i in [5, 0, 15, 1, 9, 5] {
+            assert_eq!(strings[i], &get(&packed, i).unwrap()[..]);
+        }
+        assert_eq!(16, u32::from_le_bytes(packed[..4].try_into().unwrap()));
+        assert_eq!(
+            packed.len(),
+            u32::from_le_bytes(packed[68..72].try_into().unwrap()) as usize
+        );
+        assert!(get(&packed, 16).is_err());
+        assert!(get(&packed, usize::MAX).is_err());
+
+        let empty = pack(&[]);
+        assert_eq!(vec![0, 0, 0, 0, 8, 0, 0, 0], empty);
+        assert!(get(&empty, 0).is_err());
+    }
+
+    #[test]
+    fn test_pack_invalid() {
+        let packed = pack(&[b"foo", b"bar"]);
+        // truncated header, offsets and bodies
+        for len in [0, 3, 8, 11, packed.len() - 1] {
+            assert!(get(&packed[..len], 1).is_err());
+        }
+        // offsets out of order
+        let mut swapped = packed.clone();
+        swapped[4..8].copy_from_slice(&packed[8..12]);
+        swapped[8..12].copy_from_slice(&packed[4..8]);
+        assert!(get(&swapped, 0).is_err());
+        assert!(get(&packed[..packed.len() - 1], 0).is_ok());
+        // a count near u32::MAX, with offsets far past the end
+        let mut huge = packed.clone();
+        huge[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        for index in [2, u32::MAX as usize - 2, u32::MAX as usize - 1] {
+            assert!(get(&huge, index).is_err());
+        }
+    }
+
+    #[test]
+    fn test_avoiding() {
+        let inputs = inputs(170);
+        for delimiter in [0, b'\n', 254, 255, 0x80] {
+            let mut escaped = 0;
+            for input in &inputs {
+                let compressed = compress_avoiding(input, delimiter);
+                assert!(!compressed.contains(&delimiter));
+                assert_eq!(*input, decompress_avoiding(&compressed, delimiter).unwrap());
+                escaped += compressed.len() - compress(input).len();
+            }
+            assert!(escaped > 0, "delimiter {} never escaped", delimiter);
+        }
+    }
+
+    #[test]
+    fn test_avoiding_invalid() {
+        // the delimiter itself, a dangling escape and an unknown escape
+        assert!(decompress_avoiding(&[0], 0).is_err());
+        assert!(decompress_avoiding(&[0x80], 0).is_err());
+        assert!(decompress_avoiding(&[0x80, 0x41], 0).is_err());
+        assert!(decompress_avoiding(&[254], 0).is_err());
+        assert_eq!(
+            b" ".to_vec(),
+            decompress_avoiding(&[0x80, 0x40], 0).unwrap()
+        );
+        assert_eq!(
+            vec![0x80],
+            decompress_avoiding(&[254, 0x80, 0xc0], 0).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_framed() {
+        let mut inputs = inputs(182);
+        inputs.push(vec![255, 0, 255, 0]);
+        inputs.push(Vec::new());
+
+        for input in &inputs {
+            let framed = compress_framed(input);
+            assert_eq!(
+                (input.clone(), framed.len()),
+                decompress_framed(&framed).unwrap()
+            );
+            // every cut before the end of the marker is truncated
+            for i in 0..framed.len() {
+                assert!(decompress_framed(&framed[..i]).is_err());
+            }
+            // bytes after the marker are left to the caller
+            let mut buf = framed.clone();
+            buf.extend(compress_framed(b"next"));
+            let (data, used) = decompress_framed(&buf).unwrap();
+            assert_eq!((input, framed.len()), (&data, used));
+            assert_eq!(b"next".to_vec(), decompress_framed(&buf[used..]).unwrap().0);
+        }
+    }
+
+    #[test]
+    fn test_framed_invalid() {
+        assert!(decompress_framed(&[]).is_err());
+        assert!(decompress_framed(&[255]).is_err());
+        assert!(decompress_framed(&[0, 254]).is_err());
+        assert_eq!((Vec::new(), 2), decompress_framed(&EOS).unwrap());
+        // a run of one byte written with 255 is only the marker
+        assert_eq!((Vec::new(), 2), decompress_framed(&[255, 0, b'a']).unwrap());
+    }
+
+    #[test]
+    fn test_padded() {
+        let inputs = inputs(192);
+
+        for input in &inputs {
+            for block in [1, 2, 7, 16, 64, 512, 4096] {
+                let padded = compress_padded(input, block);
+                assert_eq!(0, padded.len() % block);
+                // never a whole block of padding
+                let compressed = compress(input).len();
+                assert!(padded.len() < compressed + 2 + block);
+                assert_eq!(*input, decompress_padded(&padded).unwrap());
+            }
+        }
+        assert_eq!(vec![0; 8], compress_padded(b"", 8));
+        assert_eq!(vec![1, 1, 0, 0], compress_padded(b"the", 4));
+
+        // padding is ignored, whatever it holds
+        let mut padded = compress_padded(b"the", 4);
+        padded[2..].fill(0xff);
+        assert_eq!(b"the".to_vec(), decompress_padded(&padded).unwrap());
+        assert_eq!(b"the".to_vec(), decompress_padded(&padded[..2]).unwrap());
+    }
+
+    #[test]
+    fn test_padded_invalid() {
+        assert!(decompress_padded(&[]).is_err());
+        assert!(decompress_padded(&[0x80]).is_err());
+        assert!(decompress_padded(&[5, 1, 0, 0]).is_err());
+        assert!(decompress_padded(&[1, 254, 0, 0]).is_err());
+    }
+
+    #[test]
+    #[should_panic(expected = "block size is zero")]
+    fn test_padded_zero_block() {
+        compress_padded(b"the", 0);
+    }
+}
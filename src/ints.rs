@@ -0,0 +1,197 @@
+use crate::reader::{write_varint, Reader};
+use crate::{compress, decompress, DecompressError, Result};
+
+/// Returns the value of a run of digits stored as a number, or `None` if the
+/// run has a leading zero or is too large and is kept as text.
+fn parse(digits: &[u8]) -> Option<usize> {
+    if digits.len() > 1 && digits[0] == b'0' {
+        return None;
+    }
+    let value = digits.iter().try_fold(0usize, |v, d| {
+        v.checked_mul(10)?.checked_add((d - b'0') as usize)
+    })?;
+    if value > isize::MAX as usize {
+        return None;
+    }
+    Some(value)
+}
+
+/// Returns the length of the run of ASCII digits at the start of `input`.
+fn digit_run(input: &[u8]) -> usize {
+    input.iter().take_while(|b| b.is_ascii_digit()).count()
+}
+
+fn zigzag(delta: isize) -> usize {
+    (delta << 1 ^ delta >> (isize::BITS - 1)) as usize
+}
+
+fn unzigzag(value: usize) -> isize {
+    (value >> 1) as isize ^ -((value & 1) as isize)
+}
+
+/// Returns text compressed with its integers delta-encoded apart from the
+/// text around them.
+///
+/// Every run of ASCII digits without a leading zero and no larger than
+/// `isize::MAX` is taken out of the text; other runs, such as `007`, stay in
+/// the text as they are. The output starts with the number of integers as an
+/// unsigned LEB128 varint, then for each integer the number of text bytes
+/// since the previous one as a varint and its difference from the previous
+/// integer, starting from zero, zigzag-encoded as a varint so that small
+/// differences of either sign take one byte. The remaining text follows,
+/// compressed with [`compress`](fn.compress.html).
+///
+/// Sequences of nearby or evenly spaced integers compress much better than
+/// with [`compress`](fn.compress.html), which stores digits verbatim.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_ints, decompress_ints};
+///
+/// let s = b"1000 numbers 2000 will 10 20 30";
+/// let compressed = compress_ints(s);
+/// assert!(compressed.len() < compress(s).len());
+/// assert_eq!(s.to_vec(), decompress_ints(&compressed).unwrap());
+/// ```
+pub fn compress_ints(text: &[u8]) -> Vec<u8> {
+    let mut scaffold = Vec::with_capacity(text.len());
+    let mut deltas = Vec::new();
+    let mut count = 0;
+    let mut previous = 0;
+    let mut last = 0;
+
+    let mut i = 0;
+    while i < text.len() {
+        let len = digit_run(&text[i..]);
+        if len == 0 {
+            scaffold.push(text[i]);
+            i += 1;
+            continue;
+        }
+        let digits = &text[i..i + len];
+        match parse(digits) {
+            Some(value) => {
+                write_varint(&mut deltas, scaffold.len() - last);
+                last = scaffold.len();
+                write_varint(&mut deltas, zigzag(value as isize - previous as isize));
+                previous = value;
+                count += 1;
+            }
+            None => scaffold.extend_from_slice(digits),
+        }
+        i += len;
+    }
+
+    let mut out = Vec::with_capacity(deltas.len() + scaffold.len());
+    write_varint(&mut out, count);
+    out.extend(deltas);
+    out.extend(compress(&scaffold));
+    out
+}
+
+/// Returns text decompressed from the format of
+/// [`compress_ints`](fn.compress_ints.html), with its integers put back.
+///
+/// # Errors
+///
+/// If the data is truncated or invalid, or an integer or its position is out
+/// of range, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_ints(input: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(input);
+    let count = reader.read_varint()?;
+    let mut ints = Vec::with_capacity(count.min(input.len()));
+    let (mut position, mut previous): (usize, isize) = (0, 0);
+    for _ in 0..count {
+        position = position
+            .checked_add(reader.read_varint()?)
+            .ok_or(DecompressError)?;
+        previous = previous
+            .checked_add(unzigzag(reader.read_varint()?))
+            .filter(|v| *v >= 0)
+            .ok_or(DecompressError)?;
+        ints.push((position, previous as usize));
+    }
+    let scaffold = decompress(&input[reader.position()..])?;
+
+    let mut out = Vec::with_capacity(scaffold.len() + 4 * count);
+    let mut last = 0;
+    for (position, value) in ints {
+        out.extend_from_slice(scaffold.get(last..position).ok_or(DecompressError)?);
+        out.extend_from_slice(value.to_string().as_bytes());
+        last = position;
+    }
+    out.extend_from_slice(&scaffold[last..]);
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Rng, TEST_STRINGS};
+
+    #[test]
+    fn test_roundtrip() {
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_ints(s.as_bytes());
+            assert_eq!(s.as_bytes(), &decompress_ints(&compressed).unwrap()[..]);
+        }
+
+        let mut rng = Rng::new(172);
+        for _ in 0..500 {
+            let mut input = rng.bytes(200);
+            for b in input.iter_mut() {
+                if *b < 0x80 && rng.next_u64() % 3 == 0 {
+                    *b = b'0' + (*b % 10);
+                }
+            }
+            let compressed = compress_ints(&input);
+            assert_eq!(input, decompress_ints(&compressed).unwrap());
+        }
+
+        let edge: &[&[u8]] = &[
+            b"0",
+            b"00",
+            b"007 0 7 0",
+            b"9223372036854775807 0",
+            b"9223372036854775808 99999999999999999999999",
+            b"5 3 1 0 1 3 5",
+        ];
+        for s in edge {
+            assert_eq!(*s, &decompress_ints(&compress_ints(s)).unwrap()[..]);
+        }
+    }
+
+    #[test]
+    fn test_ratio() {
+        let s = b"1000 numbers 2000 will 10 20 30";
+        let ints = compress_ints(s).len();
+        let plain = compress(s).len();
+        assert!(ints * 4 < plain * 3, "{} bytes vs {} bytes", ints, plain);
+
+        // "007" stays in the text, only 5 and 6 are integers
+        assert_eq!(
+            [&[2, 4, 10, 1, 2][..], &compress(b"007  ")[..]].concat(),
+            compress_ints(b"007 5 6")
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let compressed = compress_ints(b"10 20 30");
+        for i in 0..7 {
+            assert!(decompress_ints(&compressed[..i]).is_err());
+        }
+        // a position past the end of the text
+        assert!(decompress_ints(&[1, 1, 2]).is_err());
+        assert!(decompress_ints(&[2, 0, 2, 2, 2, 254, b'a']).is_err());
+        // an integer below zero
+        assert!(decompress_ints(&[1, 0, 1]).is_err());
+        assert_eq!(b"1".to_vec(), decompress_ints(&[1, 0, 2]).unwrap());
+        assert_eq!(
+            b"a1a".to_vec(),
+            decompress_ints(&[1, 1, 2, 255, 1, b'a', b'a']).unwrap()
+        );
+    }
+}
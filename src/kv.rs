@@ -0,0 +1,155 @@
+use crate::reader::{write_varint, Reader};
+use crate::{compress_with, decompress_with, Codebook, DecompressError, Result};
+
+/// Returns key-value pairs compressed into a single buffer, the keys with one
+/// codebook and the values with another.
+///
+/// Field names and field values have little in common, so a codebook of the
+/// field names compresses the keys to one or two bytes each, while the values
+/// keep a codebook suited to them. The buffer holds the number of pairs as an
+/// unsigned LEB128 varint, then for each pair the varint length of the
+/// compressed key, the compressed key, the varint length of the compressed
+/// value and the compressed value.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_kv, decompress_kv, BuiltinCodebook, Codebook};
+///
+/// let keys = Codebook::new(["name", "email", "url"]).unwrap();
+/// let values = BuiltinCodebook::Url.codebook();
+/// let pairs = [(&b"name"[..], &b"smaz"[..]), (b"url", b"https://github.com")];
+/// let compressed = compress_kv(&pairs, &keys, values);
+/// let decompressed = decompress_kv(&compressed, &keys, values).unwrap();
+/// assert_eq!(pairs.len(), decompressed.len());
+/// assert_eq!((b"url".to_vec(), b"https://github.com".to_vec()), decompressed[1]);
+/// ```
+pub fn compress_kv(pairs: &[(&[u8], &[u8])], key_cb: &Codebook, val_cb: &Codebook) -> Vec<u8> {
+    let mut out = Vec::new();
+    write_varint(&mut out, pairs.len());
+    for (key, value) in pairs {
+        for (data, codebook) in [(key, key_cb), (value, val_cb)] {
+            let compressed = compress_with(data, codebook);
+            write_varint(&mut out, compressed.len());
+            out.extend(compressed);
+        }
+    }
+    out
+}
+
+/// Returns the key-value pairs of a buffer written by
+/// [`compress_kv`](fn.compress_kv.html) with the same codebooks.
+///
+/// # Errors
+///
+/// If the buffer is truncated or has trailing bytes, or a key or value is
+/// invalid, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_kv(
+    input: &[u8],
+    key_cb: &Codebook,
+    val_cb: &Codebook,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>> {
+    let mut reader = Reader::new(input);
+    let count = reader.read_varint()?;
+    let mut pairs = Vec::with_capacity(count.min(input.len()));
+    for _ in 0..count {
+        let n = reader.read_varint()?;
+        let key = decompress_with(reader.read_slice(n)?, key_cb)?;
+        let n = reader.read_varint()?;
+        let value = decompress_with(reader.read_slice(n)?, val_cb)?;
+        pairs.push((key, value));
+    }
+    if !reader.is_empty() {
+        return Err(DecompressError);
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TEST_STRINGS;
+    use crate::{compress, CodebookBuilder};
+
+    const FIELDS: [&str; 5] = ["id", "name", "email", "active", "tags"];
+
+    fn records() -> Vec<Vec<(Vec<u8>, Vec<u8>)>> {
+        TEST_STRINGS
+            .iter()
+            .enumerate()
+            .map(|(i, s)| {
+                FIELDS
+                    .iter()
+                    .zip([
+                        i.to_string(),
+                        format!("\"{}\"", s),
+                        format!("\"user{}@example.com\"", i),
+                        (i % 2 == 0).to_string(),
+                        "[\"a\",\"b\"]".to_string(),
+                    ])
+                    .map(|(k, v)| (k.as_bytes().to_vec(), v.into_bytes()))
+                    .collect()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_kv() {
+        let records = records();
+        let keys = Codebook::new(FIELDS.iter()).unwrap();
+        let values = CodebookBuilder::new()
+            .add(records.iter().flatten().map(|(_, v)| v))
+            .build();
+        let (mut kv, mut plain) = (0, 0);
+        for record in records {
+            let pairs: Vec<(&[u8], &[u8])> = record.iter().map(|(k, v)| (&k[..], &v[..])).collect();
+            let compressed = compress_kv(&pairs, &keys, &values);
+            assert_eq!(record, decompress_kv(&compressed, &keys, &values).unwrap());
+            // each key takes a single code and its length
+            assert!(pairs
+                .iter()
+                .all(|(k, _)| compress_with(k, &keys).len() == 1));
+
+            // the same record as JSON with the default codebook
+            let json: Vec<u8> = pairs
+                .iter()
+                .flat_map(|(k, v)| [&b"\""[..], k, b"\":", v, b","])
+                .flatten()
+                .copied()
+                .collect();
+            kv += compressed.len();
+            plain += compress(&json).len();
+        }
+        assert!(kv * 3 < plain * 2, "{} bytes vs {} bytes", kv, plain);
+    }
+
+    #[test]
+    fn test_kv_codebooks() {
+        // keys and values are each read with their own codebook
+        let keys = Codebook::new(["key"]).unwrap();
+        let values = Codebook::new(["value"]).unwrap();
+        let compressed = compress_kv(&[(b"key", b"value")], &keys, &values);
+        assert_eq!(vec![1, 1, 0, 1, 0], compressed);
+        assert_eq!(
+            vec![(b"value".to_vec(), b"key".to_vec())],
+            decompress_kv(&compressed, &values, &keys).unwrap()
+        );
+
+        assert_eq!(vec![0], compress_kv(&[], &keys, &values));
+        assert!(decompress_kv(&[0], &keys, &values).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_kv_invalid() {
+        let keys = Codebook::default();
+        let compressed = compress_kv(&[(b"the", b"end"), (b"", b"")], &keys, &keys);
+        for i in 0..compressed.len() {
+            assert!(decompress_kv(&compressed[..i], &keys, &keys).is_err());
+        }
+        let mut longer = compressed.clone();
+        longer.push(0);
+        assert!(decompress_kv(&longer, &keys, &keys).is_err());
+        assert!(decompress_kv(&[1, 1, 254], &keys, &keys).is_err());
+    }
+}
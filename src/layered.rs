@@ -0,0 +1,231 @@
+use std::result;
+
+use crate::codebook::Dictionary;
+use crate::reader::Reader;
+use crate::{
+    flush_verbatim, Codebook, CodebookError, DecompressError, Result, Step, CODEBOOK_SIZE,
+};
+
+/// Returns the code tagging entries of `layer`, for layers after the first.
+fn tag(layer: usize) -> u8 {
+    (CODEBOOK_SIZE - layer) as u8
+}
+
+/// Returns the layer a code of the first codebook tags, if any.
+fn layer_of(code: u8, layers: &[&Codebook]) -> Option<usize> {
+    let layer = CODEBOOK_SIZE - code as usize;
+    if layer >= 1 && layer < layers.len() {
+        Some(layer)
+    } else {
+        None
+    }
+}
+
+/// Returns data compressed with several codebooks applied in priority order.
+///
+/// At each position the longest match across all codebooks is taken, the
+/// earlier codebook winning ties. Codes of the first codebook are written as
+/// usual. A code of codebook `k` for `k >= 1` is written after the tag
+/// `254 - k`, a code the first codebook must leave unused: either one past its
+/// entries or its escape code, see
+/// [`Codebook::with_escape`](struct.Codebook.html#method.with_escape).
+/// Verbatim runs are as in [`compress_with`](fn.compress_with.html).
+///
+/// # Errors
+///
+/// Returns [`CodebookError::TooManyEntries`](enum.CodebookError.html) if
+/// there are more than 254 codebooks, which leaves no code for a tag, or if
+/// the first codebook uses the code of a tag.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_layered, decompress_layered, Codebook, CODEBOOK};
+///
+/// let global = Codebook::new(CODEBOOK.iter()).unwrap().with_escape(253).unwrap();
+/// let tenant = Codebook::new(["acme", "widget"]).unwrap();
+/// let layers = [&global, &tenant];
+///
+/// assert_eq!(vec![253, 0], compress_layered(b"acme", &layers).unwrap());
+///
+/// let compressed = compress_layered(b"the acme widget", &layers).unwrap();
+/// assert_eq!(b"the acme widget".to_vec(), decompress_layered(&compressed, &layers).unwrap());
+/// ```
+pub fn compress_layered(
+    input: &[u8],
+    layers: &[&Codebook],
+) -> result::Result<Vec<u8>, CodebookError> {
+    if layers.len() > CODEBOOK_SIZE {
+        return Err(CodebookError::TooManyEntries);
+    }
+    if let Some(first) = layers.first() {
+        for layer in 1..layers.len() {
+            let tag = tag(layer);
+            if (tag as usize) < first.len() && first.escape() != Some(tag) {
+                return Err(CodebookError::TooManyEntries);
+            }
+        }
+    }
+
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    let mut verbatim_start = 0;
+    let mut i = 0;
+
+    while i < input.len() {
+        let mut best: Option<(usize, u8, usize)> = None;
+        for (layer, codebook) in layers.iter().enumerate() {
+            if let Some((code, len)) = codebook.find_match(&input[i..]) {
+                if best.is_none_or(|(_, _, best_len)| len > best_len) {
+                    best = Some((layer, code, len));
+                }
+            }
+        }
+
+        let (layer, code, len) = match best {
+            Some(v) => v,
+            None => {
+                i += 1;
+                continue;
+            }
+        };
+        out.extend_from_slice(&flush_verbatim(&input[verbatim_start..i]));
+        if layer > 0 {
+            out.push(tag(layer));
+        }
+        out.push(code);
+        i += len;
+        verbatim_start = i;
+    }
+    out.extend_from_slice(&flush_verbatim(&input[verbatim_start..]));
+    Ok(out)
+}
+
+/// Returns data decompressed from the format of
+/// [`compress_layered`](fn.compress_layered.html), with the same codebooks in
+/// the same order.
+///
+/// # Errors
+///
+/// If the compressed data is invalid, or uses a code missing from its
+/// codebook, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_layered(input: &[u8], layers: &[&Codebook]) -> Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        let step = reader.read_step()?;
+        match step {
+            Step::Code(code) => match layer_of(code, layers) {
+                Some(layer) => {
+                    let code = reader.read_u8()?;
+                    layers[layer].expand(Step::Code(code), &mut out)?;
+                }
+                None => layers
+                    .first()
+                    .ok_or(DecompressError)?
+                    .expand(step, &mut out)?,
+            },
+            Step::Verbatim(bytes) => out.extend_from_slice(bytes),
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Rng, TEST_STRINGS};
+    use crate::{compress, CODEBOOK};
+
+    #[test]
+    fn test_layered() {
+        let global = Codebook::new(&CODEBOOK[..250]).unwrap();
+        let tenant = Codebook::new(["acme", "widget", "sku-"]).unwrap();
+        let other = Codebook::new(["zorg", "qux"]).unwrap();
+        let layers = [&global, &tenant, &other];
+
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_layered(s.as_bytes(), &layers).unwrap();
+            assert!(compressed.len() <= compress(s.as_bytes()).len() + 2);
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_layered(&compressed, &layers).unwrap()[..]
+            );
+        }
+
+        let s = b"acme widget sku-42 for zorg";
+        let compressed = compress_layered(s, &layers).unwrap();
+        assert!(compressed.len() < compress_layered(s, &[&global]).unwrap().len());
+        assert_eq!(
+            s.to_vec(),
+            decompress_layered(&compressed, &layers).unwrap()
+        );
+        assert_eq!(
+            vec![253, 0, 253, 1],
+            compress_layered(b"acmewidget", &layers).unwrap()
+        );
+        assert_eq!(vec![252, 1], compress_layered(b"qux", &layers).unwrap());
+
+        let mut rng = Rng::new(131);
+        for _ in 0..500 {
+            let input = rng.bytes(200);
+            let compressed = compress_layered(&input, &layers).unwrap();
+            assert_eq!(input, decompress_layered(&compressed, &layers).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_layered_tags() {
+        let full = Codebook::new(CODEBOOK.iter()).unwrap();
+        let tenant = Codebook::new(["acme"]).unwrap();
+        assert_eq!(
+            Err(CodebookError::TooManyEntries),
+            compress_layered(b"acme", &[&full, &tenant])
+        );
+        assert_eq!(
+            compress(b"the end"),
+            compress_layered(b"the end", &[&full]).unwrap()
+        );
+        assert_eq!(
+            vec![255, 1, b'h', b'i'],
+            compress_layered(b"hi", &[]).unwrap()
+        );
+
+        let global = full.with_escape(253).unwrap();
+        let layers = [&global, &tenant];
+        assert!(decompress_layered(&[253], &layers).is_err());
+        assert!(decompress_layered(&[253, 1], &layers).is_err());
+        assert!(decompress_layered(&[0], &[]).is_err());
+        assert_eq!(
+            b"acme".to_vec(),
+            decompress_layered(&[253, 0], &layers).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_layered_count() {
+        let empty = Codebook::new(Vec::<&str>::new()).unwrap();
+        let zz = Codebook::new(["zz"]).unwrap();
+        // tags run from 253 down to 1, the last layer being tagged 1
+        let mut layers = vec![&empty; CODEBOOK_SIZE - 1];
+        layers.push(&zz);
+        let compressed = compress_layered(b"zz", &layers).unwrap();
+        assert_eq!(vec![1, 0], compressed);
+        assert_eq!(
+            b"zz".to_vec(),
+            decompress_layered(&compressed, &layers).unwrap()
+        );
+
+        layers.insert(1, &empty);
+        assert_eq!(
+            Err(CodebookError::TooManyEntries),
+            compress_layered(b"zz", &layers)
+        );
+        layers.extend([&empty, &empty]);
+        assert_eq!(
+            Err(CodebookError::TooManyEntries),
+            compress_layered(b"zz", &layers)
+        );
+    }
+}
@@ -46,6 +46,43 @@
 //! - `try it against urls` compressed by 37%
 //! - `http://google.com` compressed by 59%
 //! - `http://programming.reddit.com` compressed by 52%
+//!
+//!
+//! ## Codebooks
+//!
+//! The default codebook is tuned for English text. Other codebooks are available
+//! through [`BuiltinCodebook`](enum.BuiltinCodebook.html), and any list of up to
+//! 254 entries can be turned into a [`Codebook`](struct.Codebook.html). Data must
+//! be decompressed with the codebook it was compressed with, see
+//! [`compress_with`](fn.compress_with.html) and
+//! [`decompress_with`](fn.decompress_with.html). A codebook tuned to your own
+//! data can be built from samples with
+//! [`CodebookBuilder`](struct.CodebookBuilder.html).
+//!
+//!
+//! ## Binary data
+//!
+//! Input is treated as opaque bytes: nothing in compression or decompression
+//! assumes or validates UTF-8, and any bytes round-trip exactly. Bytes that
+//! start no codebook entry, as in most binary data, are stored verbatim.
+//!
+//!
+//! ## Empty input
+//!
+//! Compressing empty input gives empty output, and decompressing empty input
+//! gives empty output, in every format without framing. Formats with framing,
+//! such as [`frame`](fn.frame.html) or
+//! [`compress_sentinel`](fn.compress_sentinel.html), still write their framing
+//! around an empty body, and reject empty input as truncated. Functions over
+//! lists of strings accept empty lists and empty strings alike.
+//!
+//!
+//! ## Features
+//!
+//! - `no-hashmap` looks up codebook entries by binary search in sorted arrays
+//!   instead of hash tables. Lookups are slower, but no hash map is ever built.
+//! - `test-vectors` exposes [`vectors`](vectors/index.html), inputs and their
+//!   compressed form for checking other implementations against this one.
 
 #![deny(
     missing_copy_implementations,
@@ -56,42 +93,124 @@
 #[macro_use]
 extern crate lazy_static;
 
-use std::collections::HashMap;
+mod analysis;
+mod backref;
+mod bits;
+mod builder;
+mod builtin;
+mod cased;
+mod checksum;
+mod codebook;
+mod compressor;
+mod consts;
+mod convert;
+mod decompressor;
+mod delta;
+mod extended;
+mod frame;
+mod ints;
+mod kv;
+mod layered;
+mod log;
+mod nibble;
+mod normalize;
+mod raw;
+mod reader;
+#[cfg(all(test, feature = "reference-vectors"))]
+mod reference;
+mod rle;
+mod stream;
+mod strict;
+#[cfg(any(feature = "test-vectors", all(test, feature = "reference-vectors")))]
+pub mod vectors;
+
+pub use analysis::{
+    byte_contributions, codebook_overlap, common_compressed_prefix_len, compare_codebooks,
+    compress_counted, compress_eq, compress_ex, explain_substring, hexdump_annotated,
+    marginal_savings, token_count, trace, CodebookComparison, CompressReport, HitCounter,
+    OverlapReport, TraceAction, TraceStep,
+};
+pub use backref::{compress_backref, decompress_backref};
+pub use bits::{
+    compress_adaptive, compress_bits, compress_bits_with, decompress_adaptive, decompress_bits,
+    decompress_bits_with, optimal_code_lengths,
+};
+pub use builder::{suggest_codebook_size, CodebookBuilder};
+pub use builtin::{warm_up, BuiltinCodebook};
+pub use cased::{compress_cased, decompress_cased};
+pub use checksum::{ChecksumError, ChecksummedDecoder, ChecksummedEncoder};
+pub use codebook::{export_codebook, import_codebook, Codebook, CodebookError};
+pub use compressor::{Compressor, TieBreak};
+pub use consts::{compress_array, compressed_len_const};
+pub use convert::{
+    compress_cstr, compress_utf16, decompress_cstring, decompress_truncated, decompress_utf16,
+};
+pub use decompressor::Decompressor;
+pub use delta::{compress_delta, decompress_delta};
+pub use extended::{compress_extended, decompress_extended, ExtendedCodebook};
+pub use frame::{
+    compress_avoiding, compress_blocks, compress_framed, compress_if_beneficial, compress_line,
+    compress_nullable, compress_or_store, compress_padded, compress_sentinel, compress_sorted,
+    decompress_avoiding, decompress_batch, decompress_blocks, decompress_framed,
+    decompress_nullable, decompress_one, decompress_padded, decompress_sentinel, decompress_sorted,
+    decompress_stored, frame, get, pack, unframe, StorageKind,
+};
+pub use ints::{compress_ints, decompress_ints};
+pub use kv::{compress_kv, decompress_kv};
+pub use layered::{compress_layered, decompress_layered};
+pub use log::CompressedLog;
+pub use nibble::{compress_nibble, decompress_nibble};
+pub use normalize::{compress_normalized, decompress_normalized};
+pub use raw::{compress_raw_blocks, decompress_raw_blocks};
+pub use rle::{compress_rle, decompress_rle};
+pub use stream::{compress_reader, decompress_to_writer, Decoder, Encoder};
+pub use strict::{decompress_strict, StrictError};
+
 use std::error::Error;
 use std::fmt;
+use std::iter;
 use std::result;
 use std::str;
 
+use crate::codebook::{DefaultCodebook, Dictionary};
+use crate::reader::Reader;
+
+/// Number of entries of [`CODEBOOK`](static.CODEBOOK.html), and the most a
+/// codebook can have: codes from `0` to `CODEBOOK_SIZE - 1` refer to entries.
+pub const CODEBOOK_SIZE: usize = 254;
+
+/// Length of the longest codebook entry, and so the longest match the
+/// compressor looks for at each position.
+///
+/// [`Codebook::new`](struct.Codebook.html#method.new) rejects longer entries,
+/// which could never be matched.
+pub const MAX_MATCH_LEN: usize = 7;
+
 /// Compression codebook, used for compression
-pub static CODEBOOK: [&str; 254] = [
-    " ", "the", "e", "t", "a", "of", "o", "and", "i", "n", "s", "e ", "r", " th", " t", "in", "he",
-    "th", "h", "he ", "to", "\r\n", "l", "s ", "d", " a", "an", "er", "c", " o", "d ", "on", " of",
-    "re", "of ", "t ", ", ", "is", "u", "at", "   ", "n ", "or", "which", "f", "m", "as", "it",
-    "that", "\n", "was", "en", "  ", " w", "es", " an", " i", "\r", "f ", "g", "p", "nd", " s",
-    "nd ", "ed ", "w", "ed", "http://", "for", "te", "ing", "y ", "The", " c", "ti", "r ", "his",
-    "st", " in", "ar", "nt", ",", " to", "y", "ng", " h", "with", "le", "al", "to ", "b", "ou",
-    "be", "were", " b", "se", "o ", "ent", "ha", "ng ", "their", "\"", "hi", "from", " f", "in ",
-    "de", "ion", "me", "v", ".", "ve", "all", "re ", "ri", "ro", "is ", "co", "f t", "are", "ea",
-    ". ", "her", " m", "er ", " p", "es ", "by", "they", "di", "ra", "ic", "not", "s, ", "d t",
-    "at ", "ce", "la", "h ", "ne", "as ", "tio", "on ", "n t", "io", "we", " a ", "om", ", a",
-    "s o", "ur", "li", "ll", "ch", "had", "this", "e t", "g ", "e\r\n", " wh", "ere", " co", "e o",
-    "a ", "us", " d", "ss", "\n\r\n", "\r\n\r", "=\"", " be", " e", "s a", "ma", "one", "t t",
-    "or ", "but", "el", "so", "l ", "e s", "s,", "no", "ter", " wa", "iv", "ho", "e a", " r",
-    "hat", "s t", "ns", "ch ", "wh", "tr", "ut", "/", "have", "ly ", "ta", " ha", " on", "tha",
-    "-", " l", "ati", "en ", "pe", " re", "there", "ass", "si", " fo", "wa", "ec", "our", "who",
-    "its", "z", "fo", "rs", ">", "ot", "un", "<", "im", "th ", "nc", "ate", "><", "ver", "ad",
-    " we", "ly", "ee", " n", "id", " cl", "ac", "il", "</", "rt", " wi", "div", "e, ", " it",
-    "whi", " ma", "ge", "x", "e c", "men", ".com",
-];
-
-lazy_static! {
-    static ref CODEBOOK_MAP: HashMap<Vec<u8>, u8> = {
-        let mut map: HashMap<Vec<u8>, u8> = HashMap::new();
-        for (i, code) in CODEBOOK.iter().enumerate() {
-            map.insert(code.to_string().into_bytes(), i as u8);
-        }
-        map
-    };
+pub static CODEBOOK: [&str; CODEBOOK_SIZE] = include!("codebook.in");
+
+const _: () = {
+    let entries: [&str; CODEBOOK_SIZE] = include!("codebook.in");
+    let mut i = 0;
+    while i < entries.len() {
+        assert!(
+            entries[i].len() <= MAX_MATCH_LEN,
+            "codebook entry is too long"
+        );
+        i += 1;
+    }
+};
+
+/// Returns the number of codes referring to codebook entries,
+/// [`CODEBOOK_SIZE`](constant.CODEBOOK_SIZE.html).
+pub const fn num_codes() -> usize {
+    CODEBOOK_SIZE
+}
+
+/// Returns the number of codes reserved as verbatim markers, the codes from
+/// [`CODEBOOK_SIZE`](constant.CODEBOOK_SIZE.html) to `255`.
+pub const fn num_markers() -> usize {
+    256 - CODEBOOK_SIZE
 }
 
 /// The error type for decompress operation.
@@ -115,18 +234,148 @@ impl Error for DecompressError {
 /// A specialized Result type for decompress operation.
 pub type Result<T> = result::Result<T, DecompressError>;
 
+/// Encodes verbatim bytes of any length, as consecutive runs of at most 256
+/// bytes.
 fn flush_verbatim(verbatim: &[u8]) -> Vec<u8> {
-    let mut chunk: Vec<u8> = Vec::new();
-    if verbatim.len() > 1 {
-        chunk.push(255);
-        chunk.push((verbatim.len() - 1) as u8);
-    } else {
-        chunk.push(254);
+    let mut chunk: Vec<u8> = Vec::with_capacity(verbatim.len() + verbatim.len() / 128 + 2);
+    Step::Verbatim(verbatim).write_to(&mut chunk);
+    chunk
+}
+
+/// A single step taken by the compressor, borrowing verbatim bytes from the
+/// input.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step<'a> {
+    Code(u8),
+    Verbatim(&'a [u8]),
+}
+
+impl<'a> Step<'a> {
+    /// Appends the compressed representation of the step, verbatim bytes as
+    /// consecutive runs of at most 256 bytes.
+    fn write_to<E: Extend<u8>>(&self, out: &mut E) {
+        match *self {
+            Step::Code(code) => out.extend(iter::once(code)),
+            Step::Verbatim(bytes) => {
+                for run in bytes.chunks(256) {
+                    match run {
+                        [b] => out.extend([254, *b]),
+                        _ => {
+                            out.extend([255, (run.len() - 1) as u8]);
+                            out.extend(run.iter().copied());
+                        }
+                    }
+                }
+            }
+        }
     }
-    for c in verbatim {
-        chunk.push(*c)
+
+    /// Returns the number of bytes `write_to` appends.
+    fn encoded_len(&self) -> usize {
+        match *self {
+            Step::Code(_) => 1,
+            Step::Verbatim(bytes) => bytes
+                .chunks(256)
+                .map(|run| if run.len() == 1 { 2 } else { run.len() + 2 })
+                .sum(),
+        }
+    }
+}
+
+/// Greedy walk over the input producing the same decisions as `compress`.
+#[derive(Debug, Clone)]
+struct Steps<'a, D> {
+    input: &'a [u8],
+    codebook: &'a D,
+    index: usize,
+}
+
+impl<'a, D: Dictionary> Steps<'a, D> {
+    fn new(input: &'a [u8], codebook: &'a D) -> Steps<'a, D> {
+        Steps {
+            input,
+            codebook,
+            index: 0,
+        }
+    }
+}
+
+impl<'a, D: Dictionary> Iterator for Steps<'a, D> {
+    type Item = Step<'a>;
+
+    fn next(&mut self) -> Option<Step<'a>> {
+        if self.index >= self.input.len() {
+            return None;
+        }
+
+        if let Some((code, len)) = self.codebook.find_match(&self.input[self.index..]) {
+            self.index += len;
+            return Some(Step::Code(code));
+        }
+
+        let start = self.index;
+        self.index += 1;
+        while self.index < self.input.len()
+            && self.index - start < 256
+            && self
+                .codebook
+                .find_match(&self.input[self.index..])
+                .is_none()
+        {
+            self.index += 1;
+        }
+        Some(Step::Verbatim(&self.input[start..self.index]))
     }
-    chunk
+}
+
+/// A single decision made by the compressor.
+///
+/// Serializing every token of [`tokenize`](fn.tokenize.html) in order with
+/// [`Token::write_to`](enum.Token.html#method.write_to) yields exactly the
+/// output of [`compress`](fn.compress.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Token {
+    /// A codebook entry, identified by its index in [`CODEBOOK`](static.CODEBOOK.html).
+    Code(u8),
+    /// A run of 1 to 256 bytes stored as is.
+    Verbatim(Vec<u8>),
+}
+
+impl Token {
+    /// Appends the compressed representation of the token to `out`.
+    pub fn write_to(&self, out: &mut Vec<u8>) {
+        match self {
+            Token::Code(code) => Step::Code(*code).write_to(out),
+            Token::Verbatim(bytes) => Step::Verbatim(bytes).write_to(out),
+        }
+    }
+}
+
+impl<'a> From<Step<'a>> for Token {
+    fn from(step: Step<'a>) -> Token {
+        match step {
+            Step::Code(code) => Token::Code(code),
+            Step::Verbatim(bytes) => Token::Verbatim(bytes.to_vec()),
+        }
+    }
+}
+
+/// Returns an iterator over the decisions `compress` makes for the input,
+/// without producing the packed bytes.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{tokenize, Token};
+///
+/// let tokens: Vec<Token> = tokenize(b"the 42").collect();
+/// assert_eq!(
+///     vec![Token::Code(1), Token::Code(0), Token::Verbatim(b"42".to_vec())],
+///     tokens
+/// );
+/// ```
+pub fn tokenize(input: &[u8]) -> impl Iterator<Item = Token> + '_ {
+    Steps::new(input, &DefaultCodebook).map(Token::from)
 }
 
 /// Returns compressed data as a vector of bytes.
@@ -141,48 +390,161 @@ fn flush_verbatim(verbatim: &[u8]) -> Vec<u8> {
 /// assert_eq!(vec![77, 114, 84], compressed);
 /// ```
 pub fn compress(input: &[u8]) -> Vec<u8> {
-    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
-    let mut verbatim: Vec<u8> = Vec::new();
-    let mut input_index = 0;
-
-    while input_index < input.len() {
-        let mut encoded = false;
-        let mut max_len = 7;
-        if (input.len() - input_index) < 7 {
-            max_len = input.len() - input_index
-        }
-
-        for i in (0..=max_len).rev() {
-            let code = CODEBOOK_MAP.get(&input[input_index..input_index + i]);
-            if let Some(v) = code {
-                if !verbatim.is_empty() {
-                    out.append(&mut flush_verbatim(&verbatim));
-                    verbatim.clear();
-                }
-                out.push(*v);
-                input_index += i;
-                encoded = true;
-                break;
+    if input.len() <= MAX_MATCH_LEN {
+        return encode_tiny(input);
+    }
+    encode(input, &DefaultCodebook)
+}
+
+/// Compresses an input of at most [`MAX_MATCH_LEN`] bytes into a buffer on
+/// the stack, then into a vector of the exact length.
+///
+/// Such an input has a single verbatim run at most, so a verbatim byte either
+/// starts a run, grows a `254` marker into a `255` and a length, or adds to
+/// the length. Each byte takes at most two bytes of output.
+fn encode_tiny(input: &[u8]) -> Vec<u8> {
+    let mut buf = [0; 2 * MAX_MATCH_LEN];
+    let mut len = 0;
+    // offset of the marker of the open verbatim run
+    let mut run = None;
+    let mut pos = 0;
+    while pos < input.len() {
+        if let Some((code, n)) = DefaultCodebook.find_match(&input[pos..]) {
+            buf[len] = code;
+            len += 1;
+            pos += n;
+            run = None;
+            continue;
+        }
+        match run {
+            None => {
+                run = Some(len);
+                buf[len] = 254;
+                buf[len + 1] = input[pos];
+                len += 2;
+            }
+            Some(r) if buf[r] == 254 => {
+                buf[r] = 255;
+                buf[r + 2] = buf[r + 1];
+                buf[r + 1] = 1;
+                buf[r + 3] = input[pos];
+                len += 2;
+            }
+            Some(r) => {
+                buf[r + 1] += 1;
+                buf[len] = input[pos];
+                len += 1;
             }
         }
+        pos += 1;
+    }
+    let out = buf[..len].to_vec();
+    if cfg!(debug_assertions) {
+        check_roundtrip(input, &out, &DefaultCodebook);
+    }
+    out
+}
 
-        if !encoded {
-            verbatim.push(input[input_index]);
-            input_index += 1;
-
-            if verbatim.len() == 256 {
-                out.append(&mut flush_verbatim(&verbatim));
-                verbatim.clear();
-            }
+/// Returns compressed data collected into any byte collection.
+///
+/// The bytes are identical to the output of [`compress`](fn.compress.html),
+/// extended into the collection a code or verbatim run at a time.
+/// [`compress`](fn.compress.html) stays the faster way to get a `Vec<u8>`, as
+/// it reserves room for the output up front.
+///
+/// A collection with inline storage, such as a `SmallVec<[u8; 32]>` of the
+/// smallvec crate, keeps the output of short strings off the heap.
+///
+/// # Examples
+///
+/// ```
+/// use std::collections::VecDeque;
+/// use smaz::{compress, compress_to};
+///
+/// let compressed: VecDeque<u8> = compress_to(b"the end");
+/// assert!(compressed.iter().eq(compress(b"the end").iter()));
+/// ```
+pub fn compress_to<C: Extend<u8> + Default>(input: &[u8]) -> C {
+    let mut out = C::default();
+    // the collection cannot be read back, so debug builds check a copy
+    let mut written = Vec::new();
+    for step in Steps::new(input, &DefaultCodebook) {
+        step.write_to(&mut out);
+        if cfg!(debug_assertions) {
+            step.write_to(&mut written);
         }
     }
+    if cfg!(debug_assertions) {
+        check_roundtrip(input, &written, &DefaultCodebook);
+    }
+    out
+}
+
+/// Returns the input as verbatim runs only, without looking up the codebook.
+///
+/// The output is the input framed in runs of at most 256 bytes, two bytes
+/// longer per run, and decompresses with [`decompress`](fn.decompress.html)
+/// or any codebook. Useful as a baseline, or for data known not to compress:
+/// there, short codes between verbatim bytes split the runs and
+/// [`compress`](fn.compress.html) can end up longer.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_literal, decompress};
+///
+/// let compressed = compress_literal(b"the end");
+/// assert_eq!(vec![255, 6, b't', b'h', b'e', b' ', b'e', b'n', b'd'], compressed);
+/// assert_eq!(b"the end".to_vec(), decompress(&compressed).unwrap());
+/// ```
+pub fn compress_literal(input: &[u8]) -> Vec<u8> {
+    flush_verbatim(input)
+}
 
-    if !verbatim.is_empty() {
-        out.append(&mut flush_verbatim(&verbatim));
+/// Returns data compressed with a custom codebook as a vector of bytes.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_with, BuiltinCodebook};
+///
+/// let compressed = compress_with(b"http://", BuiltinCodebook::Url.codebook());
+/// assert_eq!(1, compressed.len());
+/// ```
+pub fn compress_with(input: &[u8], codebook: &Codebook) -> Vec<u8> {
+    encode(input, codebook)
+}
+
+fn encode<D: Dictionary>(input: &[u8], codebook: &D) -> Vec<u8> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    for step in Steps::new(input, codebook) {
+        step.write_to(&mut out);
+    }
+    if cfg!(debug_assertions) {
+        check_roundtrip(input, &out, codebook);
     }
     out
 }
 
+/// Panics if `compressed` does not decompress back to `input`.
+///
+/// Only called in debug builds, to surface encoding bugs as early as possible.
+fn check_roundtrip<D: Dictionary>(input: &[u8], compressed: &[u8], codebook: &D) {
+    match decode(compressed, codebook) {
+        Ok(ref v) if v == input => {}
+        Ok(v) => panic!(
+            "compressed data decodes to {:?}, expected {:?}",
+            v.escape_ascii().to_string(),
+            input.escape_ascii().to_string()
+        ),
+        Err(e) => panic!(
+            "compressed data of {:?} does not decode: {}",
+            input.escape_ascii().to_string(),
+            e
+        ),
+    }
+}
+
 /// Returns decompressed data as a vector of bytes.
 ///
 /// # Errors
@@ -202,31 +564,313 @@ pub fn compress(input: &[u8]) -> Vec<u8> {
 /// assert_eq!("string", origin);
 /// ```
 pub fn decompress(input: &[u8]) -> Result<Vec<u8>> {
-    let mut out: Vec<u8> = Vec::with_capacity(input.len() * 3);
-    let mut i: usize = 0;
+    decode(input, &DefaultCodebook)
+}
 
-    while i < input.len() {
-        if input[i] == 254 {
-            if i + 1 > input.len() {
-                return Err(DecompressError);
-            }
-            out.push(input[i + 1]);
-            i += 2;
-        } else if input[i] == 255 {
-            if i + input[i + 1] as usize + 2 >= input.len() {
-                return Err(DecompressError);
-            }
-            for j in 0..=input[i + 1] {
-                out.push(input[i + 2 + j as usize])
+/// Returns data decompressed from the input starting at `offset`, e.g. after a
+/// fixed-size header.
+///
+/// # Errors
+///
+/// If `offset` is past the end of the input, or the compressed data is invalid
+/// or encoded incorrectly, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompress_at};
+///
+/// let mut buf = b"HDR".to_vec();
+/// buf.extend(compress(b"the end"));
+/// assert_eq!(b"the end".to_vec(), decompress_at(&buf, 3).unwrap());
+/// assert!(decompress_at(&buf, buf.len() + 1).is_err());
+/// ```
+pub fn decompress_at(input: &[u8], offset: usize) -> Result<Vec<u8>> {
+    decompress(input.get(offset..).ok_or(DecompressError)?)
+}
+
+/// Returns data decompressed with a custom codebook as a vector of bytes.
+///
+/// The codebook must be the one the data was compressed with.
+///
+/// # Errors
+///
+/// If the compressed data is invalid, encoded incorrectly or contains a code
+/// that is not in the codebook, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_with, decompress_with, BuiltinCodebook};
+///
+/// let codebook = BuiltinCodebook::Latin.codebook();
+/// let compressed = compress_with(b"la vita", codebook);
+/// assert_eq!(b"la vita".to_vec(), decompress_with(&compressed, codebook).unwrap());
+/// ```
+pub fn decompress_with(input: &[u8], codebook: &Codebook) -> Result<Vec<u8>> {
+    decode(input, codebook)
+}
+
+/// Returns data decompressed with a custom codebook, writing `replacement`
+/// for each code that is not in the codebook instead of failing.
+///
+/// This decodes on a best-effort basis data compressed with a newer or longer
+/// codebook than `codebook`, of which only the unknown codes are lost. With
+/// a full codebook, every code is known and this is the same as
+/// [`decompress_with`](fn.decompress_with.html).
+///
+/// # Errors
+///
+/// If the compressed data is invalid or encoded incorrectly, e.g. a verbatim
+/// run is cut short, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_with, decompress_lossy, Codebook};
+///
+/// let newer = Codebook::new(["foo", "bar"]).unwrap();
+/// let older = Codebook::new(["foo"]).unwrap();
+/// let compressed = compress_with(b"foo bar", &newer);
+/// assert_eq!(b"foo ?".to_vec(), decompress_lossy(&compressed, &older, b"?").unwrap());
+/// ```
+pub fn decompress_lossy(input: &[u8], codebook: &Codebook, replacement: &[u8]) -> Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        match reader.read_step()? {
+            Step::Code(code) => out.extend_from_slice(codebook.entry(code).unwrap_or(replacement)),
+            Step::Verbatim(bytes) => out.extend_from_slice(bytes),
+        }
+    }
+    Ok(out)
+}
+
+/// Returns data decompressed on a best-effort basis from a buffer that may
+/// have been written by a faulty encoder, and whether it had to be repaired.
+///
+/// This is meant for one-time migrations of legacy data, and is heuristic: a
+/// repaired buffer decodes to some data, not necessarily the data that was
+/// compressed. Three off-by-one faults of verbatim markers are recognized:
+///
+/// - a `255` run whose length byte is one too large, holding `n` bytes instead
+///   of `n + 1`;
+/// - a `255` run whose length byte is one too small, holding `n + 2` bytes;
+/// - a `254` marker followed by two verbatim bytes instead of one.
+///
+/// The buffer is read with the fewest repairs that make all of it decode, and
+/// without any if it is valid, in which case the output is that of
+/// [`decompress`](fn.decompress.html). When several repairs fit equally well,
+/// those earlier in the list are preferred. A fault is therefore only found when
+/// the buffer does not decode as it is: a run one too long that swallows the
+/// next code may still decode, to the wrong data.
+///
+/// # Errors
+///
+/// If no combination of repairs makes the buffer decode, then an error is
+/// returned [`DecompressError`](struct.DecompressError.html).
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompress, decompress_tolerant};
+///
+/// // "42" stored as a run of length byte 2 instead of 1
+/// let mut compressed = compress(b"the end 42");
+/// let len = compressed.len();
+/// compressed[len - 3] += 1;
+/// assert!(decompress(&compressed).is_err());
+/// assert_eq!((b"the end 42".to_vec(), true), decompress_tolerant(&compressed).unwrap());
+/// ```
+pub fn decompress_tolerant(input: &[u8]) -> Result<(Vec<u8>, bool)> {
+    // each way to read a token at `pos`, its end and number of repairs
+    let tokens = |pos: usize| {
+        let mut reader = Reader::at(input, pos);
+        // the lengths of verbatim bytes a marker may be followed by
+        let lens = match reader.read_u8() {
+            Ok(254) => vec![(1, 0), (2, 1)],
+            Ok(255) => match reader.read_u8().map(usize::from) {
+                Ok(0) => vec![(1, 0), (2, 1)],
+                Ok(n) => vec![(n + 1, 0), (n, 1), (n + 2, 1)],
+                Err(_) => vec![],
+            },
+            Ok(code) => {
+                return match DefaultCodebook.entry(code) {
+                    Some(_) => vec![(Step::Code(code), reader.position(), 0)],
+                    None => vec![],
+                };
             }
-            i += 3 + input[i + 1] as usize
-        } else {
-            for c in CODEBOOK[input[i] as usize].as_bytes().iter() {
-                out.push(*c);
+            Err(_) => vec![],
+        };
+        lens.into_iter()
+            .filter_map(|(n, repairs)| {
+                let mut reader = reader.clone();
+                let bytes = reader.read_slice(n).ok()?;
+                Some((Step::Verbatim(bytes), reader.position(), repairs))
+            })
+            .collect::<Vec<_>>()
+    };
+
+    // fewest repairs to decode the input from each position, and the end of
+    // the first token of the way to do it
+    let mut best: Vec<Option<(usize, usize)>> = vec![None; input.len() + 1];
+    best[input.len()] = Some((0, input.len()));
+    for pos in (0..input.len()).rev() {
+        for (_, end, repairs) in tokens(pos) {
+            if let Some((rest, _)) = best[end] {
+                if best[pos].is_none_or(|(r, _)| repairs + rest < r) {
+                    best[pos] = Some((repairs + rest, end));
+                }
             }
+        }
+    }
+
+    let (repairs, _) = best[0].ok_or(DecompressError)?;
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut pos = 0;
+    while pos < input.len() {
+        let end = best[pos].unwrap().1;
+        let (step, _, _) = tokens(pos).into_iter().find(|(_, e, _)| *e == end).unwrap();
+        DefaultCodebook.expand(step, &mut out)?;
+        pos = end;
+    }
+    Ok((out, repairs > 0))
+}
+
+/// Returns the largest possible length of data decompressed from `compressed_len`
+/// bytes with the default codebook.
+///
+/// A code byte expands to at most
+/// [`MAX_MATCH_LEN`](constant.MAX_MATCH_LEN.html) bytes, 7 for `"http://"`, while verbatim data
+/// never expands: a `254` marker and its byte yield 1 byte from 2, and a `255`
+/// run of `n` bytes yields `n` bytes from `n + 2`. The bound is therefore
+/// `7 * compressed_len`, reached by a buffer made only of the code for
+/// `"http://"`.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompress, max_decompressed_len};
+///
+/// let compressed = compress(b"the end");
+/// assert!(decompress(&compressed).unwrap().len() <= max_decompressed_len(compressed.len()));
+/// ```
+pub fn max_decompressed_len(compressed_len: usize) -> usize {
+    compressed_len.saturating_mul(MAX_MATCH_LEN)
+}
+
+/// Returns the length of data decompressed from the input with the default
+/// codebook, without decompressing it.
+///
+/// The input is walked once, summing the length of each code and verbatim
+/// run, so it takes time linear in the input and no allocation.
+///
+/// # Errors
+///
+/// If the compressed data is invalid or encoded incorrectly, then an error is
+/// returned [`DecompressError`](struct.DecompressError.html), exactly when
+/// [`decompress`](fn.decompress.html) would fail.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompressed_len};
+///
+/// assert_eq!(7, decompressed_len(&compress(b"the end")).unwrap());
+/// assert!(decompressed_len(&[255, 3, b'a']).is_err());
+/// ```
+pub fn decompressed_len(input: &[u8]) -> Result<usize> {
+    let mut reader = Reader::new(input);
+    let mut len = 0;
 
-            i += 1;
+    while !reader.is_empty() {
+        len += match reader.read_step()? {
+            Step::Code(code) => DefaultCodebook.entry(code).ok_or(DecompressError)?.len(),
+            Step::Verbatim(bytes) => bytes.len(),
+        };
+    }
+    Ok(len)
+}
+
+/// Returns the 64-bit FNV-1a hash of the decompressed data and its length,
+/// without decompressing it into a buffer.
+///
+/// FNV-1a starts from the offset basis `0xcbf29ce484222325` and, for each
+/// byte, XORs the byte into the hash and multiplies it by the prime
+/// `0x100000001b3`, wrapping around. The bytes of each code and verbatim run
+/// are hashed as they are read, so the hash is the same as hashing the output
+/// of [`decompress`](fn.decompress.html).
+///
+/// # Errors
+///
+/// If the compressed data is invalid or encoded incorrectly, then an error is
+/// returned [`DecompressError`](struct.DecompressError.html), exactly when
+/// [`decompress`](fn.decompress.html) would fail.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompress_hash};
+///
+/// assert_eq!((0xcbf29ce484222325, 0), decompress_hash(b"").unwrap());
+/// assert_eq!((0xaf63dc4c8601ec8c, 1), decompress_hash(&compress(b"a")).unwrap());
+/// ```
+pub fn decompress_hash(input: &[u8]) -> Result<(u64, usize)> {
+    let mut reader = Reader::new(input);
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    let mut len = 0;
+
+    while !reader.is_empty() {
+        let bytes = match reader.read_step()? {
+            Step::Code(code) => DefaultCodebook.entry(code).ok_or(DecompressError)?,
+            Step::Verbatim(bytes) => bytes,
+        };
+        for b in bytes {
+            hash = (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+        len += bytes.len();
+    }
+    Ok((hash, len))
+}
+
+/// Returns data decompressed with a custom codebook up to its escape code.
+///
+/// Alongside the data, returns the number of bytes consumed up to and
+/// including the escape code, or `None` if the input has no escape code, in
+/// which case all of it was decompressed. See
+/// [`Codebook::with_escape`](struct.Codebook.html#method.with_escape).
+///
+/// # Errors
+///
+/// If the compressed data before the escape code is invalid, encoded
+/// incorrectly or contains a code that is not in the codebook, then an error
+/// is returned [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_to_escape(input: &[u8], codebook: &Codebook) -> Result<(Vec<u8>, Option<usize>)> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        let step = reader.read_step()?;
+        if let Step::Code(code) = step {
+            if codebook.escape() == Some(code) {
+                return Ok((out, Some(reader.position())));
+            }
         }
+        codebook.expand(step, &mut out)?;
+    }
+    Ok((out, None))
+}
+
+fn decode<D: Dictionary>(input: &[u8], codebook: &D) -> Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        codebook.expand(reader.read_step()?, &mut out)?;
     }
     Ok(out)
 }
@@ -235,7 +879,7 @@ pub fn decompress(input: &[u8]) -> Result<Vec<u8>> {
 mod tests {
     use super::*;
 
-    static TEST_STRINGS: [&str; 16] = [
+    pub(crate) static TEST_STRINGS: [&str; 16] = [
         "",
         "This is a small string",
         "foobar",
@@ -254,7 +898,109 @@ mod tests {
         "http://programming.reddit.com",
     ];
 
+    /// Xorshift generator for reproducible pseudo-random test inputs.
+    pub(crate) struct Rng(u64);
+
+    impl Rng {
+        pub(crate) fn new(seed: u64) -> Rng {
+            Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15) | 1)
+        }
+
+        pub(crate) fn next_u64(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+
+        /// Returns bytes biased towards markers, NUL, high bytes and text.
+        pub(crate) fn bytes(&mut self, max_len: usize) -> Vec<u8> {
+            let len = self.next_u64() as usize % (max_len + 1);
+            (0..len)
+                .map(|_| {
+                    let v = self.next_u64();
+                    match v % 8 {
+                        0 => [0, 254, 255, 253][(v >> 8) as usize % 4],
+                        1 | 2 => (v >> 8) as u8,
+                        _ => b" etaoinshrdlu.,\r\n"[(v >> 8) as usize % 17],
+                    }
+                })
+                .collect()
+        }
+    }
+
+    /// Returns the default entries with code 253 as the escape, for the
+    /// formats built on the escape code.
+    pub(crate) fn escape_codebook() -> Codebook {
+        Codebook::new(CODEBOOK.iter())
+            .unwrap()
+            .with_escape(253)
+            .unwrap()
+    }
+
+    /// Returns the sample strings, then 300 pseudo-random inputs of up to 600
+    /// bytes generated from the seed.
+    pub(crate) fn inputs(seed: u64) -> Vec<Vec<u8>> {
+        let mut rng = Rng::new(seed);
+        TEST_STRINGS
+            .iter()
+            .map(|s| s.as_bytes().to_vec())
+            .chain((0..300).map(|_| rng.bytes(600)))
+            .collect()
+    }
+
+    #[test]
+    fn test_roundtrip_arbitrary() {
+        let mut rng = Rng::new(1);
+        for _ in 0..5000 {
+            let input = rng.bytes(600);
+            let compressed = compress(&input);
+            assert_eq!(input, decompress(&compressed).unwrap());
+        }
+
+        let mut input: Vec<u8> = (0..=255).collect();
+        input.extend((0..=255).rev());
+        assert_eq!(input, decompress(&compress(&input)).unwrap());
+        for b in 0..=255 {
+            for n in [1, 2, 255, 256, 257].iter() {
+                let input = vec![b; *n];
+                assert_eq!(input, decompress(&compress(&input)).unwrap());
+            }
+        }
+    }
+
+    fn assert_public<T: fmt::Debug + Clone + Send + Sync>() {}
+
+    #[test]
+    fn test_public_types() {
+        assert_public::<BuiltinCodebook>();
+        assert_public::<ChecksumError>();
+        assert_public::<ChecksummedDecoder>();
+        assert_public::<ChecksummedEncoder>();
+        assert_public::<Codebook>();
+        assert_public::<CodebookBuilder>();
+        assert_public::<CodebookComparison>();
+        assert_public::<CodebookError>();
+        assert_public::<CompressReport>();
+        assert_public::<CompressedLog>();
+        assert_public::<Compressor>();
+        assert_public::<Decoder>();
+        assert_public::<DecompressError>();
+        assert_public::<Decompressor>();
+        assert_public::<Encoder>();
+        assert_public::<ExtendedCodebook>();
+        assert_public::<HitCounter>();
+        assert_public::<OverlapReport>();
+        assert_public::<StorageKind>();
+        assert_public::<StrictError>();
+        assert_public::<TieBreak>();
+        assert_public::<Token>();
+        assert_public::<TraceAction>();
+        assert_public::<TraceStep>();
+    }
+
     #[test]
+    #[allow(clippy::needless_as_bytes)]
     fn test_compress() {
         for s in TEST_STRINGS.iter() {
             let compressed = compress(s.as_bytes());
@@ -273,4 +1019,621 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_encode_tiny() {
+        // every input of up to 7 bytes over a few bytes starting entries or not
+        const ALPHABET: [u8; 6] = [b't', b'h', b'e', b' ', b'4', 0xff];
+        let mut inputs = vec![Vec::new()];
+        let mut count = 0;
+        while let Some(input) = inputs.pop() {
+            assert_eq!(encode(&input, &DefaultCodebook), compress(&input));
+            count += 1;
+            if input.len() < MAX_MATCH_LEN {
+                for b in ALPHABET {
+                    inputs.push([&input[..], &[b]].concat());
+                }
+            }
+        }
+        assert_eq!((6usize.pow(8) - 1) / 5, count);
+
+        for s in TEST_STRINGS.iter().filter(|s| s.len() <= MAX_MATCH_LEN) {
+            assert_eq!(
+                encode(s.as_bytes(), &DefaultCodebook),
+                compress(s.as_bytes())
+            );
+        }
+        let mut rng = Rng::new(194);
+        for _ in 0..10_000 {
+            let input = rng.bytes(MAX_MATCH_LEN);
+            assert_eq!(encode(&input, &DefaultCodebook), compress(&input));
+        }
+    }
+
+    #[test]
+    fn test_compress_to() {
+        /// Collects the bytes it is extended with, one at a time.
+        #[derive(Default)]
+        struct Bytes(Vec<u8>);
+
+        impl Extend<u8> for Bytes {
+            fn extend<I: IntoIterator<Item = u8>>(&mut self, iter: I) {
+                for b in iter {
+                    self.0.push(b);
+                }
+            }
+        }
+
+        let inputs = inputs(183);
+        for input in &inputs {
+            let compressed = compress(input);
+            assert_eq!(compressed, compress_to::<Vec<u8>>(input));
+            let bytes: Bytes = compress_to(input);
+            assert_eq!(compressed, bytes.0);
+        }
+    }
+
+    #[test]
+    fn test_encoded_len() {
+        let bytes = [0x80; 513];
+        for len in [0, 1, 2, 256, 257, 513] {
+            let step = Step::Verbatim(&bytes[..len]);
+            let mut out = Vec::new();
+            step.write_to(&mut out);
+            assert_eq!(out.len(), step.encoded_len());
+        }
+        assert_eq!(1, Step::Code(0).encoded_len());
+    }
+
+    #[test]
+    fn test_tokenize() {
+        for s in TEST_STRINGS.iter() {
+            let mut out = Vec::new();
+            for token in tokenize(s.as_bytes()) {
+                token.write_to(&mut out);
+            }
+            assert_eq!(compress(s.as_bytes()), out);
+        }
+    }
+
+    /// Returns `n` bytes that match no codebook entry.
+    fn incompressible(n: usize) -> Vec<u8> {
+        (0..n).map(|i| 0x80 + (i % 0x7e) as u8).collect()
+    }
+
+    #[test]
+    fn test_verbatim_boundary() {
+        let mut input = incompressible(256);
+        let compressed = compress(&input);
+        assert_eq!(258, compressed.len());
+        assert_eq!(&[255, 255], &compressed[..2]);
+        assert_eq!(input, decompress(&compressed).unwrap());
+
+        input.extend_from_slice(b"the");
+        let compressed = compress(&input);
+        assert_eq!(259, compressed.len());
+        assert_eq!(1, compressed[258]);
+        assert_eq!(input, decompress(&compressed).unwrap());
+
+        let input = incompressible(512);
+        let compressed = compress(&input);
+        assert_eq!(516, compressed.len());
+        assert_eq!(&[255, 255], &compressed[258..260]);
+        assert_eq!(input, decompress(&compressed).unwrap());
+
+        let input = incompressible(257);
+        let compressed = compress(&input);
+        assert_eq!(&[254, input[256]], &compressed[258..]);
+        assert_eq!(input, decompress(&compressed).unwrap());
+    }
+
+    #[test]
+    fn test_flush_verbatim_long() {
+        let input = vec![0xff; 1000];
+        let flushed = flush_verbatim(&input);
+        assert_eq!(compress(&input), flushed);
+        assert_eq!(1008, flushed.len());
+        for (i, offset) in [0, 258, 516, 774].iter().enumerate() {
+            let count = if i < 3 { 255 } else { 231 };
+            assert_eq!(&[255, count], &flushed[*offset..*offset + 2]);
+        }
+        assert_eq!(input, decompress(&flushed).unwrap());
+
+        assert_eq!(&[255, 255], &flush_verbatim(&[1; 257])[..2]);
+        assert_eq!(&[254, 1], &flush_verbatim(&[1; 257])[258..]);
+        assert!(flush_verbatim(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_max_decompressed_len() {
+        assert_eq!(0, max_decompressed_len(0));
+        assert_eq!(usize::MAX, max_decompressed_len(usize::MAX));
+
+        let longest = CODEBOOK.iter().map(|e| e.len()).max().unwrap();
+        assert_eq!(longest, max_decompressed_len(1));
+
+        let code = CODEBOOK.iter().position(|e| e.len() == longest).unwrap() as u8;
+        for n in 1..10 {
+            let v = vec![code; n];
+            assert_eq!(max_decompressed_len(n), decompress(&v).unwrap().len());
+            let v = vec![254; n * 2];
+            assert!(decompress(&v).unwrap().len() <= max_decompressed_len(n * 2));
+        }
+        let v = compress(&[0; 1000]);
+        assert!(decompress(&v).unwrap().len() <= max_decompressed_len(v.len()));
+    }
+
+    #[test]
+    fn test_escape() {
+        let codebook = Codebook::new(["the", " ", "\n"])
+            .unwrap()
+            .with_escape(2)
+            .unwrap();
+        let mut buf = Vec::new();
+        for s in TEST_STRINGS.iter() {
+            buf.extend_from_slice(&compress_with(s.as_bytes(), &codebook));
+            buf.push(2);
+        }
+
+        let mut used = 0;
+        for s in TEST_STRINGS.iter() {
+            let (value, consumed) = decompress_to_escape(&buf[used..], &codebook).unwrap();
+            assert_eq!(s.as_bytes(), &value[..]);
+            used += consumed.unwrap();
+        }
+        assert_eq!(buf.len(), used);
+
+        let compressed = compress_with(b"a\nb", &codebook);
+        assert_eq!(
+            b"a\nb".to_vec(),
+            decompress_with(&compressed, &codebook).unwrap()
+        );
+        assert_eq!(
+            (b"a\nb".to_vec(), None),
+            decompress_to_escape(&compressed, &codebook).unwrap()
+        );
+        assert!(decompress_with(&[0, 2], &codebook).is_err());
+    }
+
+    #[test]
+    fn test_decompress_with_unknown_code() {
+        let codebook = Codebook::new(["foo", "bar"]).unwrap();
+        assert_eq!(
+            b"foobar".to_vec(),
+            decompress_with(&[0, 1], &codebook).unwrap()
+        );
+        assert!(decompress_with(&[0, 2, 1], &codebook).is_err());
+        assert!(decompress_with(&[253], &codebook).is_err());
+    }
+
+    #[test]
+    fn test_check_roundtrip() {
+        for s in TEST_STRINGS.iter() {
+            check_roundtrip(s.as_bytes(), &compress(s.as_bytes()), &DefaultCodebook);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "decodes to")]
+    fn test_check_roundtrip_mismatch() {
+        check_roundtrip(b"the", &compress(b"then"), &DefaultCodebook);
+    }
+
+    #[test]
+    fn test_reserved_bytes_only() {
+        let codebook = Codebook::new(["a"]).unwrap();
+        for input in [
+            &[254][..],
+            &[255],
+            &[255, 0],
+            &[255, 1, 0],
+            &[255, 255, 0][..],
+        ]
+        .iter()
+        {
+            assert!(matches!(decompress(input), Err(DecompressError)));
+            assert!(matches!(
+                decompress_with(input, &codebook),
+                Err(DecompressError)
+            ));
+            assert!(matches!(
+                decompress_to_escape(input, &codebook),
+                Err(DecompressError)
+            ));
+
+            let mut decoder = Decoder::new();
+            let mut out = Vec::new();
+            decoder.push(input, &mut out).unwrap();
+            assert!(out.is_empty());
+            assert!(matches!(decoder.finish(), Err(DecompressError)));
+        }
+    }
+
+    #[test]
+    fn test_decompress_at() {
+        for s in TEST_STRINGS.iter() {
+            for header in [&b""[..], b"\0", b"\xfe\xff", b"HEADER\x01"].iter() {
+                let mut buf = header.to_vec();
+                buf.extend(compress(s.as_bytes()));
+                assert_eq!(
+                    s.as_bytes(),
+                    &decompress_at(&buf, header.len()).unwrap()[..]
+                );
+            }
+        }
+
+        let buf = compress(b"the end");
+        assert!(decompress_at(&buf, buf.len()).unwrap().is_empty());
+        assert!(decompress_at(&buf, buf.len() + 1).is_err());
+        assert!(decompress_at(&buf, usize::MAX).is_err());
+        assert!(decompress_at(&[0, 255, 1], 1).is_err());
+    }
+
+    #[test]
+    fn test_codebook_size() {
+        assert_eq!(CODEBOOK.len(), CODEBOOK_SIZE);
+        assert_eq!(CODEBOOK_SIZE, num_codes());
+        assert_eq!(256, num_codes() + num_markers());
+        assert_eq!(2, num_markers());
+    }
+
+    #[test]
+    fn test_decompressed_len() {
+        for s in TEST_STRINGS.iter() {
+            assert_eq!(s.len(), decompressed_len(&compress(s.as_bytes())).unwrap());
+        }
+
+        let mut rng = Rng::new(151);
+        for _ in 0..10000 {
+            let input = rng.bytes(20);
+            match decompress(&input) {
+                Ok(out) => assert_eq!(out.len(), decompressed_len(&input).unwrap()),
+                Err(_) => assert!(decompressed_len(&input).is_err(), "{:?}", input),
+            }
+        }
+    }
+
+    #[test]
+    fn test_compress_literal() {
+        assert!(compress_literal(b"").is_empty());
+        assert_eq!(vec![254, b'a'], compress_literal(b"a"));
+
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_literal(s.as_bytes());
+            assert_eq!(s.as_bytes(), &decompress(&compressed).unwrap()[..]);
+            let smaz = compress(s.as_bytes());
+            if smaz.len() < s.len() {
+                assert!(compressed.len() > smaz.len());
+            }
+        }
+
+        // "Exampl333" splits into short codes and verbatim runs
+        let s = b"not-a-g00d-Exampl333";
+        assert!(compress_literal(s).len() < compress(s).len());
+
+        let mut rng = Rng::new(152);
+        for _ in 0..100 {
+            let input = rng.bytes(1000);
+            let compressed = compress_literal(&input);
+            assert_eq!(input, decompress(&compressed).unwrap());
+            // a lone byte in the last run takes a `254` marker only
+            let overhead = input.len().div_ceil(256) * 2 - usize::from(input.len() % 256 == 1);
+            assert_eq!(input.len() + overhead, compressed.len());
+        }
+    }
+
+    #[test]
+    fn test_invalid_utf8() {
+        // lone continuation bytes, a truncated sequence and an overlong encoding
+        let mut input = vec![
+            0x80, 0xbf, b't', b'h', b'e', 0xe2, 0x82, b' ', 0xc0, 0xaf, 0xff,
+        ];
+        input.extend((0x80..=0xbf).rev());
+        assert!(str::from_utf8(&input).is_err());
+
+        let compressed = compress(&input);
+        assert_eq!(input, decompress(&compressed).unwrap());
+        let codebook = Codebook::default();
+        let compressed = compress_with(&input, &codebook);
+        assert_eq!(input, decompress_with(&compressed, &codebook).unwrap());
+
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+        for b in compress(&input) {
+            decoder.push(&[b], &mut out).unwrap();
+        }
+        decoder.finish().unwrap();
+        assert_eq!(input, out);
+    }
+
+    #[test]
+    fn test_decompress_lossy() {
+        let full = Codebook::default();
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress(s.as_bytes());
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_lossy(&compressed, &full, b"?").unwrap()[..]
+            );
+        }
+
+        let short = Codebook::new(&CODEBOOK[..10]).unwrap();
+        let compressed = compress(b"the end of it");
+        assert!(decompress_with(&compressed, &short).is_err());
+        let lossy = decompress_lossy(&compressed, &short, b"\xef\xbf\xbd").unwrap();
+        // " e", "nd ", "of " and "it" are past the first ten entries
+        assert_eq!("the\u{fffd}\u{fffd}\u{fffd}\u{fffd}".as_bytes(), &lossy[..]);
+
+        // with an escape, the escape code is unknown too
+        let escaped = Codebook::new(["a", "b"]).unwrap().with_escape(1).unwrap();
+        assert_eq!(
+            b"a-x".to_vec(),
+            decompress_lossy(&[0, 1, 254, b'x'], &escaped, b"-").unwrap()
+        );
+        assert!(decompress_lossy(&[0, 255, 3, b'x'], &short, b"?").is_err());
+    }
+
+    #[test]
+    fn test_empty_input() {
+        let empty: &[u8] = b"";
+        let codebook = Codebook::default();
+        let escaped = Codebook::new(&CODEBOOK[..253])
+            .unwrap()
+            .with_escape(252)
+            .unwrap();
+        let dna = Codebook::new(["A", "C", "G", "T"]).unwrap();
+        let extended = ExtendedCodebook::new(CODEBOOK.iter()).unwrap();
+
+        // formats without framing compress empty input to nothing, and
+        // decompress nothing to empty output
+        let unframed: Vec<(Vec<u8>, Result<Vec<u8>>)> = vec![
+            (compress(empty), decompress(empty)),
+            (compress_literal(empty), decompress_at(empty, 0)),
+            (
+                compress_with(empty, &codebook),
+                decompress_with(empty, &codebook),
+            ),
+            (
+                compress_with(empty, &escaped),
+                decompress_lossy(empty, &escaped, b"?"),
+            ),
+            (
+                compress_ex(empty).0,
+                Decompressor::new().decompress(empty).map(|v| v.to_vec()),
+            ),
+            (
+                Compressor::new().greedy_lookahead(2).compress(empty),
+                decompress(empty),
+            ),
+            (compress_bits(empty), decompress_bits(empty)),
+            (
+                compress_bits_with(empty, &codebook),
+                decompress_bits_with(empty, &codebook),
+            ),
+            (
+                compress_blocks(empty, 4).concat(),
+                decompress_blocks::<&[u8]>(&[]),
+            ),
+            (
+                compress_backref(empty, &escaped).unwrap(),
+                decompress_backref(empty, &escaped),
+            ),
+            (
+                compress_cased(empty, &escaped).unwrap(),
+                decompress_cased(empty, &escaped),
+            ),
+            (
+                compress_rle(empty, &escaped).unwrap(),
+                decompress_rle(empty, &escaped),
+            ),
+            (
+                compress_layered(empty, &[&escaped, &dna]).unwrap(),
+                decompress_layered(empty, &[&escaped, &dna]),
+            ),
+            (
+                compress_nibble(empty, &dna).unwrap(),
+                decompress_nibble(empty, &dna),
+            ),
+            (
+                compress_extended(empty, &extended),
+                decompress_extended(empty, &extended),
+            ),
+            (
+                compress_or_store(empty).1,
+                decompress_stored(StorageKind::Raw, empty),
+            ),
+            (
+                compress_reader(&mut &b""[..]).unwrap(),
+                decompress_stored(StorageKind::Smaz, empty),
+            ),
+        ];
+        for (i, (compressed, decompressed)) in unframed.into_iter().enumerate() {
+            assert!(compressed.is_empty(), "case {}", i);
+            assert_eq!(vec![0; 0], decompressed.unwrap(), "case {}", i);
+        }
+        assert_eq!(0, decompressed_len(empty).unwrap());
+        assert_eq!(
+            (vec![], None),
+            decompress_to_escape(empty, &escaped).unwrap()
+        );
+        assert_eq!(0, decompress_to_writer(empty, &mut Vec::new()).unwrap());
+        assert_eq!(0, token_count(empty));
+        assert!(tokenize(empty).next().is_none());
+        assert!(trace(empty).is_empty());
+        assert!(hexdump_annotated(empty).is_empty());
+        assert!(decompress_batch::<&[u8]>(&[]).is_empty());
+        assert_eq!(
+            vec![0; 0],
+            decompress_batch(&[empty])[0].as_ref().unwrap()[..]
+        );
+
+        let mut out = Vec::new();
+        let mut encoder = Encoder::new();
+        encoder.push(empty, &mut out);
+        encoder.finish(&mut out);
+        assert!(out.is_empty());
+        let mut decoder = Decoder::new();
+        let mut decompressed = Vec::new();
+        decoder.push(&out, &mut decompressed).unwrap();
+        decoder.finish().unwrap();
+        assert!(decompressed.is_empty());
+
+        // framed formats keep their framing around an empty body, and reject
+        // input without it
+        let framed = vec![
+            (
+                compress_sentinel(empty, 0),
+                decompress_sentinel(&compress_sentinel(empty, 0), 0),
+                decompress_sentinel(empty, 0),
+            ),
+            (
+                frame(empty),
+                unframe(&frame(empty)).map(|v| v.0),
+                unframe(empty).map(|v| v.0),
+            ),
+            (
+                compress_line(empty),
+                decompress_one(&compress_line(empty)).map(|v| v.0),
+                decompress_one(empty).map(|v| v.0),
+            ),
+            (
+                compress_normalized(empty),
+                decompress_normalized(&compress_normalized(empty)),
+                decompress_normalized(empty),
+            ),
+            (pack(&[empty]), get(&pack(&[empty]), 0), get(empty, 0)),
+        ];
+        for (i, (compressed, decompressed, unframed)) in framed.into_iter().enumerate() {
+            assert!(!compressed.is_empty(), "case {}", i);
+            assert_eq!(vec![0; 0], decompressed.unwrap(), "case {}", i);
+            assert!(unframed.is_err(), "case {}", i);
+        }
+
+        // containers of strings hold no strings, or one empty string
+        assert_eq!(vec![0], compress_sorted(&[]));
+        assert!(decompress_sorted(&compress_sorted(&[])).unwrap().is_empty());
+        assert_eq!(
+            vec![empty],
+            decompress_sorted(&compress_sorted(&[empty])).unwrap()
+        );
+        assert!(decompress_nullable(&compress_nullable(&[]))
+            .unwrap()
+            .is_empty());
+        let values = [Some(empty), None];
+        assert_eq!(
+            vec![Some(vec![]), None],
+            decompress_nullable(&compress_nullable(&values)).unwrap()
+        );
+        assert!(decompress_sorted(empty).is_err());
+        assert!(decompress_nullable(empty).is_err());
+        assert!(get(&pack(&[]), 0).is_err());
+
+        // a delta always has a record, but an empty delta is accepted too
+        let delta = compress_delta(b"base", empty);
+        assert_eq!(vec![0, 0], delta);
+        assert!(decompress_delta(b"base", &delta).unwrap().is_empty());
+        assert!(decompress_delta(b"base", empty).unwrap().is_empty());
+
+        let mut out = Vec::new();
+        let mut encoder = ChecksummedEncoder::new(16);
+        encoder.push(empty, &mut out);
+        encoder.finish(&mut out);
+        let mut decoder = ChecksummedDecoder::new();
+        decoder.push(&out, &mut Vec::new()).unwrap();
+        assert_eq!(0, decoder.blocks());
+        decoder.finish().unwrap();
+        assert!(ChecksummedDecoder::new().finish().is_err());
+
+        let cstr = std::ffi::CString::default();
+        assert_eq!(cstr, decompress_cstring(&compress_cstr(&cstr)).unwrap());
+    }
+
+    #[test]
+    fn test_decompress_hash() {
+        fn fnv1a(data: &[u8]) -> u64 {
+            data.iter().fold(0xcbf2_9ce4_8422_2325, |hash, b| {
+                (hash ^ *b as u64).wrapping_mul(0x0100_0000_01b3)
+            })
+        }
+
+        // published FNV-1a test vectors
+        assert_eq!(0xaf63_dc4c_8601_ec8c, fnv1a(b"a"));
+        assert_eq!(0x8594_4171_f739_67e8, fnv1a(b"foobar"));
+
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress(s.as_bytes());
+            let decompressed = decompress(&compressed).unwrap();
+            assert_eq!(
+                (fnv1a(&decompressed), decompressed.len()),
+                decompress_hash(&compressed).unwrap()
+            );
+        }
+        let mut rng = Rng::new(171);
+        for _ in 0..300 {
+            let input = rng.bytes(300);
+            let (hash, len) = decompress_hash(&compress(&input)).unwrap();
+            assert_eq!((fnv1a(&input), input.len()), (hash, len));
+        }
+        assert!(decompress_hash(&[255, 3, b'a']).is_err());
+        assert!(decompress_hash(&[254]).is_err());
+    }
+
+    #[test]
+    fn test_decompress_tolerant() {
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress(s.as_bytes());
+            assert_eq!(
+                (s.as_bytes().to_vec(), false),
+                decompress_tolerant(&compressed).unwrap()
+            );
+        }
+
+        // a run one too small, its last byte read as a marker
+        let too_small = [255, 0, b'4', 254];
+        assert!(decompress(&too_small).is_err());
+        assert_eq!(
+            (b"4\xfe".to_vec(), true),
+            decompress_tolerant(&too_small).unwrap()
+        );
+
+        // a run one too large, cut short by the end of the buffer
+        let mut too_large = compress(b"the 42");
+        let run = too_large.iter().position(|b| *b == 255).unwrap();
+        too_large[run + 1] += 1;
+        assert!(decompress(&too_large).is_err());
+        assert_eq!(
+            (b"the 42".to_vec(), true),
+            decompress_tolerant(&too_large).unwrap()
+        );
+        // with more after it, a run one too small also explains the buffer,
+        // and the run one too large is preferred
+        too_large.extend(&[254, 255]);
+        assert!(decompress(&too_large).is_err());
+        assert_eq!(
+            (b"the 42\xff".to_vec(), true),
+            decompress_tolerant(&too_large).unwrap()
+        );
+
+        // a single-byte marker with two bytes
+        let mut two = compress(b"the end");
+        two.extend(&[254, b'x', 254]);
+        assert!(decompress(&two).is_err());
+        assert_eq!(
+            (b"the endx\xfe".to_vec(), true),
+            decompress_tolerant(&two).unwrap()
+        );
+
+        // two faults at once: a run one too large, then the marker
+        let both = [255, 3, b'a', b'b', b'c', 254, b'x', 254];
+        assert!(decompress(&both).is_err());
+        assert_eq!(
+            (b"abcx\xfe".to_vec(), true),
+            decompress_tolerant(&both).unwrap()
+        );
+
+        // too far off to repair
+        assert!(decompress_tolerant(&[255, 5, b'a']).is_err());
+        assert!(decompress_tolerant(&[254]).is_err());
+        assert_eq!((vec![], false), decompress_tolerant(&[]).unwrap());
+    }
 }
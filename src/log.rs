@@ -0,0 +1,138 @@
+use crate::reader::{write_varint, Reader};
+use crate::{compress, decompress, decompressed_len, Result};
+
+/// An append-only log of compressed records in a single buffer.
+///
+/// Each record is the length of its compressed data as an unsigned LEB128
+/// varint, followed by the data compressed with
+/// [`compress`](fn.compress.html), so a record takes one byte more than its
+/// compressed data when that is shorter than 128 bytes. Records are appended
+/// at the end of the buffer and read back in order.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::CompressedLog;
+///
+/// let mut log = CompressedLog::new();
+/// log.push(b"the end");
+/// log.push(b"foobar");
+/// let records: Vec<Vec<u8>> = log.iter().collect();
+/// assert_eq!(vec![b"the end".to_vec(), b"foobar".to_vec()], records);
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompressedLog {
+    buf: Vec<u8>,
+    len: usize,
+}
+
+impl CompressedLog {
+    /// Creates an empty log.
+    pub fn new() -> CompressedLog {
+        CompressedLog::default()
+    }
+
+    /// Creates a log from the bytes of another, as returned by
+    /// [`as_bytes`](#method.as_bytes).
+    ///
+    /// # Errors
+    ///
+    /// If a record is truncated or its data is invalid, then an error is
+    /// returned [`DecompressError`](struct.DecompressError.html).
+    pub fn from_bytes(buf: Vec<u8>) -> Result<CompressedLog> {
+        let mut reader = Reader::new(&buf);
+        let mut len = 0;
+        while !reader.is_empty() {
+            let n = reader.read_varint()?;
+            decompressed_len(reader.read_slice(n)?)?;
+            len += 1;
+        }
+        Ok(CompressedLog { buf, len })
+    }
+
+    /// Appends a record holding the compressed message.
+    pub fn push(&mut self, msg: &[u8]) {
+        let compressed = compress(msg);
+        write_varint(&mut self.buf, compressed.len());
+        self.buf.extend_from_slice(&compressed);
+        self.len += 1;
+    }
+
+    /// Returns the number of records.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// Returns `true` if the log has no records.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Returns the bytes of all records.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    /// Returns the messages of the records, decompressed in order.
+    pub fn iter(&self) -> impl Iterator<Item = Vec<u8>> + '_ {
+        // records are checked when pushed or loaded, so none can fail here
+        let mut reader = Reader::new(&self.buf);
+        (0..self.len).map(move |_| {
+            let n = reader.read_varint().unwrap();
+            decompress(reader.read_slice(n).unwrap()).unwrap()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TEST_STRINGS;
+
+    #[test]
+    fn test_log() {
+        let mut log = CompressedLog::new();
+        assert!(log.is_empty());
+        for s in TEST_STRINGS.iter() {
+            log.push(s.as_bytes());
+        }
+        assert_eq!(TEST_STRINGS.len(), log.len());
+        let records: Vec<Vec<u8>> = log.iter().collect();
+        let expected: Vec<&[u8]> = TEST_STRINGS.iter().map(|s| s.as_bytes()).collect();
+        assert_eq!(expected, records);
+
+        // one length byte per record on top of the compressed data
+        let compressed: usize = TEST_STRINGS
+            .iter()
+            .map(|s| compress(s.as_bytes()).len())
+            .sum();
+        assert_eq!(compressed + TEST_STRINGS.len(), log.as_bytes().len());
+
+        let copy = CompressedLog::from_bytes(log.as_bytes().to_vec()).unwrap();
+        assert_eq!(log, copy);
+        log.push(b"one more");
+        assert_eq!(b"one more".to_vec(), log.iter().last().unwrap());
+    }
+
+    #[test]
+    fn test_from_bytes_invalid() {
+        let mut log = CompressedLog::new();
+        log.push(b"the end");
+        log.push(b"foobar");
+        let bytes = log.as_bytes();
+        for i in 1..bytes.len() {
+            if i != 4 {
+                assert!(CompressedLog::from_bytes(bytes[..i].to_vec()).is_err());
+            }
+        }
+        // the first record alone is a valid log
+        assert_eq!(
+            1,
+            CompressedLog::from_bytes(bytes[..4].to_vec())
+                .unwrap()
+                .len()
+        );
+        assert!(CompressedLog::from_bytes(vec![1, 254]).is_err());
+        assert!(CompressedLog::from_bytes(vec![]).unwrap().is_empty());
+    }
+}
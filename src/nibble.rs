@@ -0,0 +1,181 @@
+use std::result;
+
+use crate::codebook::Dictionary;
+use crate::{Codebook, CodebookError, DecompressError, Result, Step, Steps};
+
+/// Maximum number of entries of a codebook used with the nibble format.
+const MAX_ENTRIES: usize = 15;
+
+const ESCAPE: u8 = 0xF;
+
+#[derive(Debug, Default)]
+struct NibbleWriter {
+    out: Vec<u8>,
+    half: bool,
+}
+
+impl NibbleWriter {
+    fn push(&mut self, nibble: u8) {
+        if self.half {
+            *self.out.last_mut().unwrap() |= nibble;
+        } else {
+            self.out.push(nibble << 4);
+        }
+        self.half = !self.half;
+    }
+
+    fn finish(mut self) -> Vec<u8> {
+        if self.half {
+            self.push(ESCAPE);
+        }
+        self.out
+    }
+}
+
+/// Returns data compressed in a denser format for codebooks of at most 15
+/// entries.
+///
+/// Every code takes a single 4-bit nibble and two nibbles are packed per byte,
+/// high nibble first. Nibble `0xF` is an escape that introduces a verbatim
+/// run: it is followed by one nibble holding the run length minus one (so a
+/// run is 1 to 16 bytes), then by each byte of the run as two nibbles, high
+/// first. If the stream ends on a half-filled byte, the low nibble is set to
+/// `0xF`; an escape in the last nibble is therefore padding.
+///
+/// # Errors
+///
+/// Returns [`CodebookError::TooManyEntries`](enum.CodebookError.html) if the
+/// codebook has more than 15 entries.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_nibble, decompress_nibble, Codebook};
+///
+/// let codebook = Codebook::new(["A", "C", "G", "T"]).unwrap();
+/// let compressed = compress_nibble(b"GATTACA", &codebook).unwrap();
+/// assert_eq!(4, compressed.len());
+/// assert_eq!(b"GATTACA".to_vec(), decompress_nibble(&compressed, &codebook).unwrap());
+/// ```
+pub fn compress_nibble(
+    input: &[u8],
+    codebook: &Codebook,
+) -> result::Result<Vec<u8>, CodebookError> {
+    if codebook.len() > MAX_ENTRIES {
+        return Err(CodebookError::TooManyEntries);
+    }
+
+    let mut writer = NibbleWriter::default();
+    for step in Steps::new(input, codebook) {
+        match step {
+            Step::Code(code) => writer.push(code),
+            Step::Verbatim(bytes) => {
+                for chunk in bytes.chunks(16) {
+                    writer.push(ESCAPE);
+                    writer.push((chunk.len() - 1) as u8);
+                    for b in chunk {
+                        writer.push(b >> 4);
+                        writer.push(b & 0xF);
+                    }
+                }
+            }
+        }
+    }
+    Ok(writer.finish())
+}
+
+/// Returns data decompressed from the format of
+/// [`compress_nibble`](fn.compress_nibble.html).
+///
+/// # Errors
+///
+/// If the compressed data is invalid or contains a code that is not in the
+/// codebook, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_nibble(input: &[u8], codebook: &Codebook) -> Result<Vec<u8>> {
+    let nibbles = input.len() * 2;
+    let nibble = |i: usize| -> Result<u8> {
+        if i >= nibbles {
+            return Err(DecompressError);
+        }
+        let b = input[i / 2];
+        Ok(if i % 2 == 0 { b >> 4 } else { b & 0xF })
+    };
+
+    let mut out = Vec::with_capacity(input.len() * 4);
+    let mut i = 0;
+    while i < nibbles {
+        let code = nibble(i)?;
+        if code == ESCAPE {
+            if i + 1 == nibbles {
+                break;
+            }
+            let len = nibble(i + 1)? as usize + 1;
+            for j in 0..len {
+                let at = i + 2 + j * 2;
+                out.push(nibble(at)? << 4 | nibble(at + 1)?);
+            }
+            i += 2 + len * 2;
+        } else {
+            match codebook.entry(code) {
+                Some(v) => out.extend_from_slice(v),
+                None => return Err(DecompressError),
+            }
+            i += 1;
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn codebook() -> Codebook {
+        Codebook::new(["A", "C", "G", "T", "AA", "CC", "GG", "TT", "ACGT", "\n"]).unwrap()
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let codebook = codebook();
+        let inputs: [&[u8]; 6] = [
+            b"",
+            b"A",
+            b"ACGTACGTTTGACCA\nGGTAC",
+            b"ACGNNTTGA",
+            b"xyz",
+            b"A0123456789abcdefghijklmnopqrstuvwxyzC",
+        ];
+        for input in inputs.iter() {
+            let compressed = compress_nibble(input, &codebook).unwrap();
+            assert_eq!(
+                *input,
+                &decompress_nibble(&compressed, &codebook).unwrap()[..]
+            );
+        }
+    }
+
+    #[test]
+    fn test_density() {
+        let codebook = codebook();
+        let input = b"ACGTACGTTTGACCA\nGGTAC";
+        let compressed = compress_nibble(input, &codebook).unwrap();
+        assert_eq!(6, compressed.len());
+        assert!(compressed.len() < crate::compress_with(input, &codebook).len());
+    }
+
+    #[test]
+    fn test_errors() {
+        let entries: Vec<String> = (0..16).map(|i| i.to_string()).collect();
+        let codebook = Codebook::new(&entries).unwrap();
+        assert_eq!(
+            CodebookError::TooManyEntries,
+            compress_nibble(b"1", &codebook).unwrap_err()
+        );
+
+        let codebook = self::codebook();
+        assert!(decompress_nibble(&[0xAF], &codebook).is_err());
+        assert!(decompress_nibble(&[0xF1, 0x41], &codebook).is_err());
+        assert!(decompress_nibble(&[0xFF], &codebook).is_err());
+    }
+}
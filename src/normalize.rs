@@ -0,0 +1,288 @@
+use std::iter;
+
+use crate::reader::{write_varint, Reader};
+use crate::{compress, decompress, DecompressError, Result};
+
+/// Kinds of whitespace runs in the side data.
+const SPACES: usize = 0;
+const TABS: usize = 1;
+const MIXED: usize = 2;
+
+/// Longest run of the side data, longer runs are split so that a short input
+/// cannot demand a huge output.
+const MAX_RUN: usize = 1 << 12;
+
+/// A run of the side data, expanded only when written out.
+#[derive(Debug, Clone, Copy)]
+struct Run<'a> {
+    /// Index of the space of the text it replaces.
+    index: usize,
+    kind: usize,
+    len: usize,
+    /// One bit per byte for a mixed run, set for tabs.
+    bits: &'a [u8],
+}
+
+impl Run<'_> {
+    fn write_to(&self, out: &mut Vec<u8>) {
+        match self.kind {
+            SPACES => out.extend(iter::repeat_n(b' ', self.len)),
+            TABS => out.extend(iter::repeat_n(b'\t', self.len)),
+            _ => out.extend((0..self.len).map(|i| {
+                if self.bits[i / 8] >> (i % 8) & 1 == 1 {
+                    b'\t'
+                } else {
+                    b' '
+                }
+            })),
+        }
+    }
+}
+
+fn is_blank(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// Returns the input with every run of spaces and tabs replaced by a single
+/// space, and side data restoring the runs that were not a single space.
+fn normalize(input: &[u8]) -> (Vec<u8>, Vec<u8>) {
+    let mut text = Vec::with_capacity(input.len());
+    let mut side = Vec::new();
+    let mut count = 0;
+    let mut runs = 0;
+    let mut last = 0;
+
+    let mut i = 0;
+    while i < input.len() {
+        if !is_blank(input[i]) {
+            text.push(input[i]);
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        while i < input.len() && is_blank(input[i]) {
+            i += 1;
+        }
+        for run in input[start..i].chunks(MAX_RUN) {
+            text.push(b' ');
+            runs += 1;
+            if run == b" " {
+                continue;
+            }
+
+            write_varint(&mut side, runs - 1 - last);
+            last = runs;
+            count += 1;
+            if run.iter().all(|b| *b == b' ') {
+                write_varint(&mut side, run.len() << 2 | SPACES);
+            } else if run.iter().all(|b| *b == b'\t') {
+                write_varint(&mut side, run.len() << 2 | TABS);
+            } else {
+                write_varint(&mut side, run.len() << 2 | MIXED);
+                for bits in run.chunks(8) {
+                    side.push(
+                        bits.iter()
+                            .rev()
+                            .fold(0, |v, b| v << 1 | (*b == b'\t') as u8),
+                    );
+                }
+            }
+        }
+    }
+
+    let mut header = Vec::with_capacity(side.len() + 2);
+    write_varint(&mut header, count);
+    header.extend_from_slice(&side);
+    (text, header)
+}
+
+/// Returns data compressed after normalizing whitespace, so that irregular
+/// spacing matches the space-oriented entries of the codebook.
+///
+/// Every run of spaces and tabs is compressed as a single space. The output
+/// starts with side data restoring the runs that were not a single space: the
+/// number of such runs as an unsigned LEB128 varint, then for each run the
+/// number of runs skipped since the previous one and a varint holding the run
+/// length shifted left by two, or'ed with its kind: `0` for spaces only, `1`
+/// for tabs only, or `2` for a mix, followed by one bit per byte, set for
+/// tabs, least significant bit first. Runs are at most 4096 bytes, a longer
+/// one is compressed as several spaces, each restored by its own run. The
+/// compressed text follows.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, compress_normalized, decompress_normalized};
+///
+/// let s = b"\t\tif the end\t\t\t\tis near\n\t\t\t\treturn";
+/// let compressed = compress_normalized(s);
+/// assert!(compressed.len() < compress(s).len());
+/// assert_eq!(s.to_vec(), decompress_normalized(&compressed).unwrap());
+/// ```
+pub fn compress_normalized(input: &[u8]) -> Vec<u8> {
+    let (text, mut out) = normalize(input);
+    out.extend(compress(&text));
+    out
+}
+
+/// Returns data decompressed from the format of
+/// [`compress_normalized`](fn.compress_normalized.html), with the original
+/// whitespace restored.
+///
+/// # Errors
+///
+/// If the side data or the compressed text is invalid, the side data has a
+/// run longer than 4096 bytes or refers to more runs than the text has, then
+/// an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_normalized(input: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = Reader::new(input);
+    let count = reader.read_varint()?;
+    let mut runs: Vec<Run> = Vec::with_capacity(count.min(input.len()));
+    let mut index: usize = 0;
+    for _ in 0..count {
+        index = index
+            .checked_add(reader.read_varint()?)
+            .ok_or(DecompressError)?;
+        let desc = reader.read_varint()?;
+        let (kind, len) = (desc & 3, desc >> 2);
+        if len == 0 || len > MAX_RUN || kind > MIXED {
+            return Err(DecompressError);
+        }
+        let bits = match kind {
+            MIXED => reader.read_slice(len.div_ceil(8))?,
+            _ => &[],
+        };
+        runs.push(Run {
+            index,
+            kind,
+            len,
+            bits,
+        });
+        index += 1;
+    }
+
+    let text = decompress(&input[reader.position()..])?;
+    let spaces = text.iter().filter(|b| **b == b' ').count();
+    if runs.last().is_some_and(|run| run.index >= spaces) {
+        return Err(DecompressError);
+    }
+    let len = text.len() + runs.iter().map(|run| run.len - 1).sum::<usize>();
+    let mut out = Vec::with_capacity(len);
+
+    let mut runs = runs.into_iter().peekable();
+    let mut index = 0;
+    for b in text {
+        if b != b' ' {
+            out.push(b);
+            continue;
+        }
+        match runs.next_if(|run| run.index == index) {
+            Some(run) => run.write_to(&mut out),
+            None => out.push(b' '),
+        }
+        index += 1;
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Rng, TEST_STRINGS};
+
+    #[test]
+    fn test_roundtrip() {
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_normalized(s.as_bytes());
+            // single spaces need no side data
+            assert_eq!(0, compressed[0]);
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_normalized(&compressed).unwrap()[..]
+            );
+        }
+
+        let mut rng = Rng::new(146);
+        for _ in 0..1000 {
+            let input: Vec<u8> = rng
+                .bytes(300)
+                .into_iter()
+                .map(|b| match b % 4 {
+                    0 => b'\t',
+                    1 => b' ',
+                    _ => b,
+                })
+                .collect();
+            let compressed = compress_normalized(&input);
+            assert_eq!(input, decompress_normalized(&compressed).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_mixed() {
+        let s = b"name\t\tvalue  \t  end\n\tthe key    is\t \tthere";
+        let compressed = compress_normalized(s);
+        assert!(compressed.len() < compress(s).len());
+        assert_eq!(s.to_vec(), decompress_normalized(&compressed).unwrap());
+
+        let (text, side) = normalize(b"a\t\tb c  \t d");
+        assert_eq!(b"a b c d".to_vec(), text);
+        // two runs: two tabs, then run 2 mixed "  \t " with the tab at bit 2
+        assert_eq!(
+            vec![2, 0, 2 << 2 | TABS as u8, 1, 4 << 2 | MIXED as u8, 0b100],
+            side
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        let compressed = compress_normalized(b"a\t\tb");
+        assert!(decompress_normalized(&compressed[..2]).is_err());
+        assert!(decompress_normalized(&[]).is_err());
+        // a run past the end of the text
+        assert!(decompress_normalized(&[1, 1, 4, 0]).is_err());
+        assert!(decompress_normalized(&[1, 0, 0, 0]).is_err());
+        assert!(decompress_normalized(&[1, 0, 5 << 2 | 3, 0]).is_err());
+        assert_eq!(
+            b"\t".to_vec(),
+            decompress_normalized(&[1, 0, 1 << 2 | 1, 0]).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_huge_run() {
+        // a run of 2^45 spaces, past the end of the text
+        assert!(decompress_normalized(&[1, 0, 128, 128, 128, 128, 128, 128, 32]).is_err());
+
+        // runs of 2^40 spaces and of the longest length a varint holds,
+        // replacing the only space
+        for len in [1 << 40, usize::MAX >> 2] {
+            let mut side = vec![1, 0];
+            write_varint(&mut side, len << 2 | SPACES);
+            let input = [&side[..], &compress(b" ")].concat();
+            assert!(decompress_normalized(&input).is_err());
+        }
+
+        let mut side = vec![1, 0];
+        write_varint(&mut side, MAX_RUN << 2 | TABS);
+        let input = [&side[..], &compress(b"a b")].concat();
+        assert_eq!(
+            [&b"a"[..], &[b'\t'; MAX_RUN], b"b"].concat(),
+            decompress_normalized(&input).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_long_run() {
+        // split into runs of 4096 tabs, 4096 mixed and a single space
+        let input = [&b"a"[..], &[b'\t'; 2 * MAX_RUN - 8], b"  \t   \t  b"].concat();
+        let (text, side) = normalize(&input);
+        assert_eq!(b"a   b".to_vec(), text);
+        assert_eq!(2, side[0]);
+
+        let compressed = compress_normalized(&input);
+        assert_eq!(input, decompress_normalized(&compressed).unwrap());
+    }
+}
@@ -0,0 +1,194 @@
+use std::result;
+
+use crate::codebook::Dictionary;
+use crate::reader::{write_varint, Reader};
+use crate::{flush_verbatim, Codebook, CodebookError, DecompressError, Result, Step, Steps};
+
+/// Appends the bytes matching no entry before a code or the end of the
+/// input, as a raw block if it is shorter than verbatim runs.
+fn write_literal(out: &mut Vec<u8>, literal: &[u8], escape: u8) {
+    let runs = flush_verbatim(literal);
+    let mut header = vec![escape];
+    write_varint(&mut header, literal.len());
+    if header.len() + literal.len() < runs.len() {
+        out.extend(header);
+        out.extend_from_slice(literal);
+    } else {
+        out.extend(runs);
+    }
+}
+
+/// Returns data compressed with a codebook, storing long stretches of bytes
+/// matching no entry as single raw blocks.
+///
+/// Verbatim runs hold at most 256 bytes, so a stretch of `n` bytes takes two
+/// bytes of markers per 256 bytes. A raw block is the escape code of the
+/// codebook, the length of the block as an unsigned LEB128 varint, then that
+/// many bytes, however many. A stretch is stored as a raw block only if that
+/// is shorter than its verbatim runs, which takes at least 258 bytes.
+///
+/// # Errors
+///
+/// Returns [`CodebookError::MissingEscape`](enum.CodebookError.html) if the
+/// codebook has no escape code.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_raw_blocks, compress_with, decompress_raw_blocks, Codebook, CODEBOOK};
+///
+/// let codebook = Codebook::new(CODEBOOK.iter()).unwrap().with_escape(253).unwrap();
+/// let mut s = b"blob: ".to_vec();
+/// s.extend(vec![0x80; 1000]);
+/// let compressed = compress_raw_blocks(&s, &codebook).unwrap();
+/// assert!(compressed.len() < compress_with(&s, &codebook).len());
+/// assert_eq!(s, decompress_raw_blocks(&compressed, &codebook).unwrap());
+/// ```
+pub fn compress_raw_blocks(
+    input: &[u8],
+    codebook: &Codebook,
+) -> result::Result<Vec<u8>, CodebookError> {
+    let escape = codebook.escape().ok_or(CodebookError::MissingEscape)?;
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    let mut steps = Steps::new(input, codebook);
+    let mut literal = 0;
+    loop {
+        let pos = steps.index;
+        match steps.next() {
+            Some(Step::Code(code)) => {
+                write_literal(&mut out, &input[literal..pos], escape);
+                out.push(code);
+                literal = steps.index;
+            }
+            Some(Step::Verbatim(_)) => {}
+            None => break,
+        }
+    }
+    write_literal(&mut out, &input[literal..], escape);
+    Ok(out)
+}
+
+/// Returns data decompressed from the format of
+/// [`compress_raw_blocks`](fn.compress_raw_blocks.html).
+///
+/// # Errors
+///
+/// If the compressed data is invalid, or a raw block is truncated, then an
+/// error is returned [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_raw_blocks(input: &[u8], codebook: &Codebook) -> Result<Vec<u8>> {
+    let escape = codebook.escape().ok_or(DecompressError)?;
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(2));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        let step = reader.read_step()?;
+        if step != Step::Code(escape) {
+            codebook.expand(step, &mut out)?;
+            continue;
+        }
+        let n = reader.read_varint()?;
+        out.extend_from_slice(reader.read_slice(n)?);
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress_with;
+    use crate::tests::{escape_codebook, Rng, TEST_STRINGS};
+
+    #[test]
+    fn test_roundtrip() {
+        let codebook = escape_codebook();
+        for s in TEST_STRINGS.iter() {
+            // no stretch is long enough for a raw block
+            let compressed = compress_raw_blocks(s.as_bytes(), &codebook).unwrap();
+            assert_eq!(compress_with(s.as_bytes(), &codebook), compressed);
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_raw_blocks(&compressed, &codebook).unwrap()[..]
+            );
+        }
+
+        let mut rng = Rng::new(195);
+        for _ in 0..300 {
+            let mut input = rng.bytes(2000);
+            if rng.next_u64() % 2 == 0 {
+                input.iter_mut().for_each(|b| *b |= 0x80);
+            }
+            let compressed = compress_raw_blocks(&input, &codebook).unwrap();
+            assert!(compressed.len() <= compress_with(&input, &codebook).len());
+            assert_eq!(
+                input,
+                decompress_raw_blocks(&compressed, &codebook).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_embedded_blob() {
+        let codebook = escape_codebook();
+        let mut rng = Rng::new(195);
+        let blob: Vec<u8> = (0..5000).map(|_| rng.next_u64() as u8 | 0x80).collect();
+        let head = b"this is the header of the file, ";
+        let tail = b" and this is the end of it";
+        let input = [&head[..], &blob, tail].concat();
+
+        let compressed = compress_raw_blocks(&input, &codebook).unwrap();
+        assert_eq!(
+            input,
+            decompress_raw_blocks(&compressed, &codebook).unwrap()
+        );
+
+        // the blob is one token: the escape, a 2-byte length and the bytes
+        let (before, after) = (
+            compress_with(head, &codebook),
+            compress_with(tail, &codebook),
+        );
+        assert_eq!(
+            [&before[..], &[253, 0x88, 0x27], &blob, &after].concat(),
+            compressed
+        );
+        // where verbatim runs take 40 bytes of markers
+        let plain = compress_with(&input, &codebook).len();
+        assert_eq!(plain - 37, compressed.len());
+    }
+
+    #[test]
+    fn test_threshold() {
+        let codebook = escape_codebook();
+        // a run of 256 bytes and a single byte take as long as a block
+        for (n, raw) in [
+            (1, false),
+            (256, false),
+            (257, false),
+            (258, true),
+            (600, true),
+        ] {
+            let input = vec![0x80; n];
+            let compressed = compress_raw_blocks(&input, &codebook).unwrap();
+            assert_eq!(raw, compressed[0] == 253, "{} bytes", n);
+            assert_eq!(
+                input,
+                decompress_raw_blocks(&compressed, &codebook).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        let codebook = escape_codebook();
+        assert!(compress_raw_blocks(b"abc", &Codebook::default()).is_err());
+        assert!(decompress_raw_blocks(&[0], &Codebook::default()).is_err());
+        assert!(decompress_raw_blocks(&[253], &codebook).is_err());
+        assert!(decompress_raw_blocks(&[253, 3, b'a', b'b'], &codebook).is_err());
+        assert_eq!(
+            b"ab".to_vec(),
+            decompress_raw_blocks(&[253, 2, b'a', b'b'], &codebook).unwrap()
+        );
+        assert!(decompress_raw_blocks(&[253, 0], &codebook)
+            .unwrap()
+            .is_empty());
+    }
+}
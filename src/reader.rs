@@ -0,0 +1,169 @@
+use crate::{DecompressError, Result, Step};
+
+/// Bounds-checked access to compressed data, shared by all decoders.
+#[derive(Debug, Clone)]
+pub(crate) struct Reader<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub(crate) fn new(input: &'a [u8]) -> Reader<'a> {
+        Reader { input, pos: 0 }
+    }
+
+    /// Creates a reader of `input` as if `pos` bytes had been read.
+    pub(crate) fn at(input: &'a [u8], pos: usize) -> Reader<'a> {
+        Reader { input, pos }
+    }
+
+    /// Returns the number of bytes read so far.
+    pub(crate) fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Returns `true` if all bytes have been read.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pos >= self.input.len()
+    }
+
+    pub(crate) fn read_u8(&mut self) -> Result<u8> {
+        let v = *self.input.get(self.pos).ok_or(DecompressError)?;
+        self.pos = self.pos.checked_add(1).ok_or(DecompressError)?;
+        Ok(v)
+    }
+
+    pub(crate) fn read_slice(&mut self, n: usize) -> Result<&'a [u8]> {
+        let end = self.pos.checked_add(n).ok_or(DecompressError)?;
+        match self.input.get(self.pos..end) {
+            Some(v) => {
+                self.pos = end;
+                Ok(v)
+            }
+            None => Err(DecompressError),
+        }
+    }
+
+    /// Reads an unsigned LEB128 varint.
+    pub(crate) fn read_varint(&mut self) -> Result<usize> {
+        let mut value: usize = 0;
+        let mut shift = 0;
+        loop {
+            let b = self.read_u8()?;
+            if shift >= usize::BITS || (b & 0x7f) as usize > usize::MAX >> shift {
+                return Err(DecompressError);
+            }
+            value |= ((b & 0x7f) as usize) << shift;
+            if b & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+        }
+    }
+
+    /// Reads a code or a whole verbatim run.
+    ///
+    /// The code is not checked against a codebook. On error the position is
+    /// left unchanged.
+    pub(crate) fn read_step(&mut self) -> Result<Step<'a>> {
+        let start = self.pos;
+        let step = match self.read_u8()? {
+            254 => self.read_slice(1).map(Step::Verbatim),
+            255 => self
+                .read_u8()
+                .and_then(|len| self.read_slice(usize::from(len) + 1))
+                .map(Step::Verbatim),
+            code => Ok(Step::Code(code)),
+        };
+        if step.is_err() {
+            self.pos = start;
+        }
+        step
+    }
+}
+
+/// Appends an unsigned LEB128 varint to `out`.
+pub(crate) fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        out.push((value as u8) | 0x80);
+        value >>= 7;
+    }
+    out.push(value as u8);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_varint() {
+        for value in [0, 1, 127, 128, 300, 16384, usize::MAX].iter() {
+            let mut out = Vec::new();
+            write_varint(&mut out, *value);
+            let mut reader = Reader::new(&out);
+            assert_eq!(*value, reader.read_varint().unwrap());
+            assert!(reader.is_empty());
+        }
+        assert!(Reader::new(&[0x80]).read_varint().is_err());
+        assert!(Reader::new(&[0xff; 11]).read_varint().is_err());
+    }
+
+    #[test]
+    fn test_read() {
+        let mut reader = Reader::new(&[1, 2, 3]);
+        assert_eq!(1, reader.read_u8().unwrap());
+        assert!(reader.read_slice(3).is_err());
+        assert_eq!(1, reader.position());
+        assert_eq!(&[2, 3], reader.read_slice(2).unwrap());
+        assert!(reader.is_empty());
+        assert!(reader.read_u8().is_err());
+        assert_eq!(&[] as &[u8], reader.read_slice(0).unwrap());
+        assert!(reader.read_slice(usize::MAX).is_err());
+    }
+
+    #[test]
+    fn test_read_step() {
+        let mut reader = Reader::new(&[7, 254, 0, 255, 1, 2, 3]);
+        assert_eq!(Step::Code(7), reader.read_step().unwrap());
+        assert_eq!(Step::Verbatim(&[0]), reader.read_step().unwrap());
+        assert_eq!(Step::Verbatim(&[2, 3]), reader.read_step().unwrap());
+        assert!(reader.is_empty());
+
+        for input in [&[254][..], &[255], &[255, 0], &[255, 2, 1, 1]].iter() {
+            let mut reader = Reader::new(input);
+            assert!(reader.read_step().is_err());
+            assert_eq!(0, reader.position());
+        }
+    }
+
+    #[test]
+    fn test_read_overflow() {
+        // a position no input can reach, so any advance would wrap
+        let mut reader = Reader {
+            input: &[255, 255, 0],
+            pos: usize::MAX,
+        };
+        assert!(reader.is_empty());
+        assert!(reader.read_u8().is_err());
+        assert!(reader.read_slice(1).is_err());
+        assert!(reader.read_step().is_err());
+        assert!(reader.read_varint().is_err());
+        assert_eq!(usize::MAX, reader.position());
+    }
+
+    #[test]
+    fn test_read_step_lengths() {
+        for len in 0..=255u8 {
+            let mut input = vec![255, len];
+            for _ in 0..=len {
+                let mut reader = Reader::new(&input);
+                assert!(reader.read_step().is_err());
+                assert_eq!(0, reader.position());
+                input.push(0);
+            }
+            let mut reader = Reader::new(&input);
+            assert_eq!(Step::Verbatim(&input[2..]), reader.read_step().unwrap());
+            assert!(reader.is_empty());
+        }
+    }
+}
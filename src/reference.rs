@@ -0,0 +1,83 @@
+//! Equivalence tests against a port of `smaz_compress` from antirez's C smaz,
+//! run with the `reference-vectors` feature.
+
+use crate::tests::{Rng, TEST_STRINGS};
+use crate::vectors::VECTORS;
+use crate::{compress, decompress, CODEBOOK};
+
+/// A line-by-line port of `smaz_compress`: a verbatim buffer of up to 256
+/// bytes, flushed before the next code, when full, or at the end of input.
+/// The C hash table is replaced by a linear scan of the same reverse
+/// codebook, which finds the same entries.
+fn smaz_compress(mut input: &[u8]) -> Vec<u8> {
+    let mut out = Vec::new();
+    let mut verb: Vec<u8> = Vec::with_capacity(256);
+
+    fn flush(verb: &mut Vec<u8>, out: &mut Vec<u8>) {
+        if verb.len() == 1 {
+            out.push(254);
+        } else {
+            out.push(255);
+            out.push((verb.len() - 1) as u8);
+        }
+        out.extend_from_slice(verb);
+        verb.clear();
+    }
+
+    while !input.is_empty() {
+        let mut j = if input.len() < 7 { input.len() } else { 7 };
+        let mut code = None;
+        while j > 0 {
+            if let Some(i) = CODEBOOK.iter().position(|e| e.as_bytes() == &input[..j]) {
+                code = Some(i as u8);
+                break;
+            }
+            j -= 1;
+        }
+
+        match code {
+            Some(code) => {
+                if !verb.is_empty() {
+                    flush(&mut verb, &mut out);
+                }
+                out.push(code);
+                input = &input[j..];
+            }
+            None => {
+                verb.push(input[0]);
+                input = &input[1..];
+            }
+        }
+        if verb.len() == 256 || (!verb.is_empty() && input.is_empty()) {
+            flush(&mut verb, &mut out);
+        }
+    }
+    out
+}
+
+#[test]
+fn test_vectors() {
+    // the first vectors are the sample strings of `smaz_test.c`
+    for (s, (input, expected)) in TEST_STRINGS.iter().zip(&VECTORS[..16]) {
+        assert_eq!(s.as_bytes(), *input);
+        assert_eq!(*expected, &smaz_compress(input)[..], "{:?}", s);
+        assert_eq!(*expected, &compress(input)[..], "{:?}", s);
+        assert_eq!(*input, &decompress(expected).unwrap()[..]);
+    }
+}
+
+#[test]
+fn test_equivalence() {
+    let mut rng = Rng::new(150);
+    for max_len in [8, 64, 600].iter() {
+        for _ in 0..2000 {
+            let input = rng.bytes(*max_len);
+            assert_eq!(smaz_compress(&input), compress(&input), "{:?}", input);
+        }
+    }
+
+    for len in [255, 256, 257, 512, 513].iter() {
+        let input = vec![0; *len];
+        assert_eq!(smaz_compress(&input), compress(&input));
+    }
+}
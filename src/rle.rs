@@ -0,0 +1,170 @@
+use std::result;
+
+use crate::codebook::Dictionary;
+use crate::reader::Reader;
+use crate::{Codebook, CodebookError, DecompressError, Result, Step, Steps};
+
+/// Fewest repeats of a code worth a run-length token of three bytes.
+const MIN_REPEATS: usize = 4;
+
+/// Returns data compressed with a codebook, replacing repeats of the same code
+/// with run-length tokens.
+///
+/// A run-length token is the escape code of the codebook, the repeated code and
+/// the number of repeats, from 1 to 255. Repeats of at least four codes use a
+/// token, longer repeats are split into several.
+///
+/// # Errors
+///
+/// Returns [`CodebookError::MissingEscape`](enum.CodebookError.html) if the
+/// codebook has no escape code.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress_rle, decompress_rle, Codebook, CODEBOOK};
+///
+/// let codebook = Codebook::new(CODEBOOK.iter()).unwrap().with_escape(253).unwrap();
+/// let spaces = [b' '; 20];
+/// let compressed = compress_rle(&spaces, &codebook).unwrap();
+/// assert_eq!(4, compressed.len());
+/// assert_eq!(spaces.to_vec(), decompress_rle(&compressed, &codebook).unwrap());
+/// ```
+pub fn compress_rle(input: &[u8], codebook: &Codebook) -> result::Result<Vec<u8>, CodebookError> {
+    let escape = codebook.escape().ok_or(CodebookError::MissingEscape)?;
+    let mut out: Vec<u8> = Vec::with_capacity(input.len() / 2);
+    let mut steps = Steps::new(input, codebook).peekable();
+
+    while let Some(step) = steps.next() {
+        let code = match step {
+            Step::Code(code) => code,
+            Step::Verbatim(_) => {
+                step.write_to(&mut out);
+                continue;
+            }
+        };
+
+        let mut repeats = 1;
+        while steps.next_if_eq(&Step::Code(code)).is_some() {
+            repeats += 1;
+        }
+        while repeats >= MIN_REPEATS {
+            let count = repeats.min(255);
+            out.extend_from_slice(&[escape, code, count as u8]);
+            repeats -= count;
+        }
+        out.extend(std::iter::repeat_n(code, repeats));
+    }
+    Ok(out)
+}
+
+/// Returns data decompressed from the format of
+/// [`compress_rle`](fn.compress_rle.html).
+///
+/// # Errors
+///
+/// If the compressed data is invalid, or a run-length token repeats a code
+/// missing from the codebook or zero times, then an error is returned
+/// [`DecompressError`](struct.DecompressError.html).
+pub fn decompress_rle(input: &[u8], codebook: &Codebook) -> Result<Vec<u8>> {
+    let mut out: Vec<u8> = Vec::with_capacity(input.len().saturating_mul(3));
+    let mut reader = Reader::new(input);
+
+    while !reader.is_empty() {
+        let step = reader.read_step()?;
+        if step != Step::Code(codebook.escape().ok_or(DecompressError)?) {
+            codebook.expand(step, &mut out)?;
+            continue;
+        }
+
+        let entry = codebook.entry(reader.read_u8()?).ok_or(DecompressError)?;
+        let count = reader.read_u8()?;
+        if count == 0 {
+            return Err(DecompressError);
+        }
+        for _ in 0..count {
+            out.extend_from_slice(entry);
+        }
+    }
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{escape_codebook, Rng, TEST_STRINGS};
+    use crate::{compress_with, CODEBOOK};
+
+    #[test]
+    fn test_roundtrip() {
+        let codebook = escape_codebook();
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress_rle(s.as_bytes(), &codebook).unwrap();
+            assert_eq!(compress_with(s.as_bytes(), &codebook), compressed);
+            assert_eq!(
+                s.as_bytes(),
+                &decompress_rle(&compressed, &codebook).unwrap()[..]
+            );
+        }
+
+        let mut rng = Rng::new(134);
+        for _ in 0..500 {
+            let mut input = rng.bytes(100);
+            let repeated = input.repeat(rng.next_u64() as usize % 4 + 1);
+            input.extend(std::iter::repeat_n(b' ', rng.next_u64() as usize % 600));
+            input.extend(repeated);
+            let compressed = compress_rle(&input, &codebook).unwrap();
+            assert_eq!(input, decompress_rle(&compressed, &codebook).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_spaces() {
+        let codebook = escape_codebook();
+        // six times "   " and once "  "
+        let spaces = [b' '; 20];
+        let compressed = compress_rle(&spaces, &codebook).unwrap();
+        assert_eq!(vec![253, 40, 6, 52], compressed);
+        assert_eq!(7, compress_with(&spaces, &codebook).len());
+        assert_eq!(
+            &spaces[..],
+            &decompress_rle(&compressed, &codebook).unwrap()[..]
+        );
+
+        let spaces = [b' '; 2000];
+        let compressed = compress_rle(&spaces, &codebook).unwrap();
+        assert_eq!(
+            vec![253, 40, 255, 253, 40, 255, 253, 40, 156, 52],
+            compressed
+        );
+        assert_eq!(667, compress_with(&spaces, &codebook).len());
+
+        // 257 repeats leave two plain codes
+        let the = codebook.lookup(b"the").unwrap();
+        let input = b"the".repeat(257);
+        assert_eq!(
+            vec![253, the, 255, the, the],
+            compress_rle(&input, &codebook).unwrap()
+        );
+        assert_eq!(vec![the; 3], compress_rle(b"thethethe", &codebook).unwrap());
+    }
+
+    #[test]
+    fn test_invalid() {
+        let codebook = escape_codebook();
+        assert!(decompress_rle(&[253, 0], &codebook).is_err());
+        assert!(decompress_rle(&[253, 0, 0], &codebook).is_err());
+        assert!(decompress_rle(&[253, 253, 4], &codebook).is_err());
+        assert_eq!(
+            b"  ".to_vec(),
+            decompress_rle(&[253, 0, 2], &codebook).unwrap()
+        );
+
+        let plain = Codebook::new(CODEBOOK.iter()).unwrap();
+        assert_eq!(
+            Err(CodebookError::MissingEscape),
+            compress_rle(b" ", &plain)
+        );
+        assert!(decompress_rle(&[0], &plain).is_err());
+    }
+}
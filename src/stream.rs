@@ -0,0 +1,452 @@
+use std::io::{self, ErrorKind, Read, Write};
+
+use crate::codebook::{DefaultCodebook, Dictionary};
+use crate::reader::Reader;
+use crate::{flush_verbatim, DecompressError, Result, Step, MAX_MATCH_LEN};
+
+/// Most input bytes an encoder takes at once from a push.
+const WINDOW: usize = 4096;
+
+/// A streaming compressor.
+///
+/// Input can be pushed in pieces of any size and the output is identical to
+/// [`compress`](fn.compress.html) of the whole input. Cloning an encoder copies
+/// all buffered state, so a partially fed stream can be forked.
+///
+/// Memory use is bounded whatever the size of the input or of each push.
+/// Pushed input is taken in windows of at most 4096 bytes, and between
+/// windows the encoder only keeps the bytes that may start a match straddling
+/// the boundary, fewer than [`MAX_MATCH_LEN`](constant.MAX_MATCH_LEN.html),
+/// and a verbatim run of at most 255 bytes.
+/// Only the output appended to `out` grows, and it can be written out and
+/// cleared between pushes.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, Encoder};
+///
+/// let mut encoder = Encoder::new();
+/// let mut out = Vec::new();
+/// encoder.push(b"this is a ", &mut out);
+/// encoder.push(b"small string", &mut out);
+/// encoder.finish(&mut out);
+/// assert_eq!(compress(b"this is a small string"), out);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Encoder {
+    tail: Vec<u8>,
+    verbatim: Vec<u8>,
+}
+
+impl Encoder {
+    /// Creates a new encoder.
+    pub fn new() -> Encoder {
+        Encoder::default()
+    }
+
+    /// Compresses more input, appending the output that can already be decided
+    /// to `out`.
+    pub fn push(&mut self, input: &[u8], out: &mut Vec<u8>) {
+        for window in input.chunks(WINDOW) {
+            self.tail.extend_from_slice(window);
+            let consumed = self.encode(out, false);
+            self.tail.drain(..consumed);
+        }
+    }
+
+    /// Compresses the rest of the input, appending the output to `out`.
+    pub fn finish(mut self, out: &mut Vec<u8>) {
+        self.encode(out, true);
+        if !self.verbatim.is_empty() {
+            out.append(&mut flush_verbatim(&self.verbatim));
+        }
+    }
+
+    fn encode(&mut self, out: &mut Vec<u8>, last: bool) -> usize {
+        let mut index = 0;
+        while index < self.tail.len() && (last || self.tail.len() - index >= MAX_MATCH_LEN) {
+            if let Some((code, len)) = DefaultCodebook.find_match(&self.tail[index..]) {
+                if !self.verbatim.is_empty() {
+                    out.append(&mut flush_verbatim(&self.verbatim));
+                    self.verbatim.clear();
+                }
+                out.push(code);
+                index += len;
+            } else {
+                self.verbatim.push(self.tail[index]);
+                index += 1;
+
+                if self.verbatim.len() == 256 {
+                    out.append(&mut flush_verbatim(&self.verbatim));
+                    self.verbatim.clear();
+                }
+            }
+        }
+        index
+    }
+}
+
+/// A streaming decompressor.
+///
+/// Compressed data can be pushed in pieces of any size, split anywhere. Cloning
+/// a decoder copies all buffered state.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, Decoder};
+///
+/// let compressed = compress(b"this is a small string");
+/// let mut decoder = Decoder::new();
+/// let mut out = Vec::new();
+/// decoder.push(&compressed[..3], &mut out).unwrap();
+/// decoder.push(&compressed[3..], &mut out).unwrap();
+/// decoder.finish().unwrap();
+/// assert_eq!(b"this is a small string".to_vec(), out);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Decoder {
+    pending: Vec<u8>,
+}
+
+impl Decoder {
+    /// Creates a new decoder.
+    pub fn new() -> Decoder {
+        Decoder::default()
+    }
+
+    /// Decompresses more input, appending the output to `out`.
+    ///
+    /// # Errors
+    ///
+    /// If the compressed data is invalid, then an error is returned
+    /// [`DecompressError`](struct.DecompressError.html).
+    pub fn push(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        self.pending.extend_from_slice(input);
+
+        let mut reader = Reader::new(&self.pending);
+        while let Ok(step) = reader.read_step() {
+            DefaultCodebook.expand(step, out)?;
+        }
+        let consumed = reader.position();
+        self.pending.drain(..consumed);
+        Ok(())
+    }
+
+    /// Finishes the stream.
+    ///
+    /// # Errors
+    ///
+    /// If the stream ends in the middle of a verbatim run, then an error is
+    /// returned [`DecompressError`](struct.DecompressError.html).
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use smaz::Decoder;
+    ///
+    /// let mut decoder = Decoder::new();
+    /// decoder.push(&[255, 2, b'a', b'b'], &mut Vec::new()).unwrap();
+    /// assert!(decoder.finish().is_err());
+    /// ```
+    pub fn finish(self) -> Result<()> {
+        if self.pending.is_empty() {
+            Ok(())
+        } else {
+            Err(DecompressError)
+        }
+    }
+}
+
+/// Returns the compressed data of everything read from `reader` until the end.
+///
+/// The input is compressed as it is read, with the output identical to
+/// [`compress`](fn.compress.html) of the whole input.
+///
+/// # Errors
+///
+/// Returns any error from `reader` other than
+/// [`ErrorKind::Interrupted`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html),
+/// on which the read is retried.
+///
+/// # Examples
+///
+/// ```
+/// use std::io::Cursor;
+/// use smaz::{compress, compress_reader};
+///
+/// let mut reader = Cursor::new("the end");
+/// assert_eq!(compress(b"the end"), compress_reader(&mut reader).unwrap());
+/// ```
+pub fn compress_reader<R: Read>(reader: &mut R) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new();
+    let mut out = Vec::new();
+    let mut buf = [0; 8192];
+    loop {
+        match reader.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => encoder.push(&buf[..n], &mut out),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    encoder.finish(&mut out);
+    Ok(out)
+}
+
+/// Decompresses the input straight into `writer`, returning the number of
+/// bytes written.
+///
+/// Each codebook entry and verbatim run is written as it is decoded, so no
+/// intermediate buffer holds the whole output.
+///
+/// # Errors
+///
+/// Returns any error from `writer`. If the compressed data is invalid, then an
+/// error of kind
+/// [`ErrorKind::InvalidData`](https://doc.rust-lang.org/std/io/enum.ErrorKind.html)
+/// wrapping [`DecompressError`](struct.DecompressError.html) is returned; the
+/// output before the invalid data has been written by then.
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{compress, decompress_to_writer};
+///
+/// let mut out = Vec::new();
+/// let n = decompress_to_writer(&compress(b"the end"), &mut out).unwrap();
+/// assert_eq!(7, n);
+/// assert_eq!(b"the end".to_vec(), out);
+/// ```
+pub fn decompress_to_writer<W: Write>(input: &[u8], writer: &mut W) -> io::Result<usize> {
+    let invalid = |e| io::Error::new(ErrorKind::InvalidData, e);
+    let mut reader = Reader::new(input);
+    let mut written = 0;
+    while !reader.is_empty() {
+        let bytes = match reader.read_step().map_err(invalid)? {
+            Step::Code(code) => DefaultCodebook
+                .entry(code)
+                .ok_or(DecompressError)
+                .map_err(invalid)?,
+            Step::Verbatim(bytes) => bytes,
+        };
+        writer.write_all(bytes)?;
+        written += bytes.len();
+    }
+    Ok(written)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::{Rng, TEST_STRINGS};
+    use crate::{compress, decompress};
+
+    #[test]
+    fn test_encoder() {
+        for s in TEST_STRINGS.iter() {
+            for size in 1..8 {
+                let mut encoder = Encoder::new();
+                let mut out = Vec::new();
+                for chunk in s.as_bytes().chunks(size) {
+                    encoder.push(chunk, &mut out);
+                }
+                encoder.finish(&mut out);
+                assert_eq!(compress(s.as_bytes()), out);
+            }
+        }
+
+        let input: Vec<u8> = (0..1000).map(|i| (i % 7 + 200) as u8).collect();
+        let mut encoder = Encoder::new();
+        let mut out = Vec::new();
+        for chunk in input.chunks(100) {
+            encoder.push(chunk, &mut out);
+        }
+        encoder.finish(&mut out);
+        assert_eq!(compress(&input), out);
+    }
+
+    #[test]
+    fn test_decoder() {
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress(s.as_bytes());
+            for size in 1..4 {
+                let mut decoder = Decoder::new();
+                let mut out = Vec::new();
+                for chunk in compressed.chunks(size) {
+                    decoder.push(chunk, &mut out).unwrap();
+                }
+                decoder.finish().unwrap();
+                assert_eq!(s.as_bytes(), &out[..]);
+            }
+        }
+
+        let mut decoder = Decoder::new();
+        decoder.push(&[255, 3, b'a'], &mut Vec::new()).unwrap();
+        assert!(decoder.finish().is_err());
+    }
+
+    #[test]
+    fn test_encoder_clone() {
+        let mut encoder = Encoder::new();
+        let mut out = Vec::new();
+        encoder.push(b"http://goo", &mut out);
+
+        let mut fork = encoder.clone();
+        let mut fork_out = out.clone();
+
+        encoder.push(b"gle.com", &mut out);
+        encoder.finish(&mut out);
+        fork.push(b"d-\xff\xfe.org", &mut fork_out);
+        fork.finish(&mut fork_out);
+
+        assert_eq!(b"http://google.com".to_vec(), decompress(&out).unwrap());
+        assert_eq!(
+            b"http://good-\xff\xfe.org".to_vec(),
+            decompress(&fork_out).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_decoder_clone() {
+        let compressed = compress(b"this is a small string");
+        let mut decoder = Decoder::new();
+        let mut out = Vec::new();
+        decoder.push(&compressed[..2], &mut out).unwrap();
+
+        let mut fork = decoder.clone();
+        let mut fork_out = out.clone();
+        fork.push(&compressed[2..], &mut fork_out).unwrap();
+        fork.finish().unwrap();
+        assert_eq!(b"this is a small string".to_vec(), fork_out);
+
+        decoder.push(&compress(b"foo"), &mut out).unwrap();
+        decoder.finish().unwrap();
+        let mut expected = decompress(&compressed[..2]).unwrap();
+        expected.extend_from_slice(b"foo");
+        assert_eq!(expected, out);
+    }
+
+    /// Reads one byte at a time, failing every other read.
+    struct Flaky<'a> {
+        input: &'a [u8],
+        kind: ErrorKind,
+        fail: bool,
+    }
+
+    impl<'a> Read for Flaky<'a> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.fail = !self.fail;
+            if self.fail {
+                return Err(io::Error::new(self.kind, "flaky"));
+            }
+            let len = buf.len().min(1);
+            self.input.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_compress_reader() {
+        for s in TEST_STRINGS.iter() {
+            let mut cursor = io::Cursor::new(s.as_bytes());
+            assert_eq!(
+                compress(s.as_bytes()),
+                compress_reader(&mut cursor).unwrap()
+            );
+
+            let mut flaky = Flaky {
+                input: s.as_bytes(),
+                kind: ErrorKind::Interrupted,
+                fail: false,
+            };
+            assert_eq!(compress(s.as_bytes()), compress_reader(&mut flaky).unwrap());
+        }
+
+        let long = TEST_STRINGS.concat().repeat(100);
+        let mut cursor = io::Cursor::new(long.as_bytes());
+        assert_eq!(
+            compress(long.as_bytes()),
+            compress_reader(&mut cursor).unwrap()
+        );
+
+        let mut flaky = Flaky {
+            input: b"the end",
+            kind: ErrorKind::UnexpectedEof,
+            fail: false,
+        };
+        let err = compress_reader(&mut flaky).unwrap_err();
+        assert_eq!(ErrorKind::UnexpectedEof, err.kind());
+    }
+
+    #[test]
+    fn test_decompress_to_writer() {
+        for s in TEST_STRINGS.iter() {
+            let compressed = compress(s.as_bytes());
+            let mut out = Vec::new();
+
+            let n = decompress_to_writer(&compressed, &mut out).unwrap();
+            assert_eq!(decompress(&compressed).unwrap(), out);
+            assert_eq!(s.len(), n);
+        }
+
+        let err = decompress_to_writer(&[0, 255, 3, b'a'], &mut Vec::new()).unwrap_err();
+        assert_eq!(ErrorKind::InvalidData, err.kind());
+        assert!(err.get_ref().unwrap().is::<DecompressError>());
+    }
+
+    #[test]
+    fn test_decompress_to_failing_writer() {
+        struct Full(usize);
+
+        impl Write for Full {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.0 == 0 {
+                    return Err(io::Error::new(ErrorKind::WriteZero, "full"));
+                }
+                let n = buf.len().min(self.0);
+                self.0 -= n;
+                Ok(n)
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let compressed = compress(TEST_STRINGS[6].as_bytes());
+        let err = decompress_to_writer(&compressed, &mut Full(10)).unwrap_err();
+        assert_eq!(ErrorKind::WriteZero, err.kind());
+        assert!(decompress_to_writer(&compressed, &mut Full(1000)).is_ok());
+    }
+
+    #[test]
+    fn test_encoder_bounded() {
+        let mut rng = Rng::new(180);
+        let mut input = Vec::new();
+        while input.len() < 1 << 20 {
+            input.extend_from_slice(TEST_STRINGS[rng.next_u64() as usize % 16].as_bytes());
+            input.extend(rng.bytes(40));
+        }
+        let expected = compress(&input);
+
+        for sizes in [&[1, 6, 7, 8][..], &[4095, 4096, 4097], &[1 << 20]] {
+            let mut encoder = Encoder::new();
+            let mut out = Vec::new();
+            let mut rest = &input[..];
+            for size in sizes.iter().cycle() {
+                if rest.is_empty() {
+                    break;
+                }
+                let (chunk, tail) = rest.split_at((*size).min(rest.len()));
+                encoder.push(chunk, &mut out);
+                rest = tail;
+                assert!(encoder.tail.len() < MAX_MATCH_LEN);
+                assert!(encoder.verbatim.len() < 256);
+            }
+            assert!(encoder.tail.capacity() <= 2 * (WINDOW + MAX_MATCH_LEN));
+            encoder.finish(&mut out);
+            assert!(out == expected, "sizes {:?}", sizes);
+        }
+    }
+}
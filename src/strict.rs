@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::fmt;
+use std::result;
+
+use crate::analysis::first_difference;
+use crate::decompress;
+
+/// The error type for [`decompress_strict`](fn.decompress_strict.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StrictError {
+    /// The data is invalid, as [`decompress`](fn.decompress.html) reports it.
+    Invalid,
+    /// The data decompresses, but [`compress`](fn.compress.html) writes it
+    /// differently. Holds the offset of the first token it writes differently.
+    NonCanonical(usize),
+}
+
+impl fmt::Display for StrictError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            StrictError::Invalid => write!(f, "invalid compressed data"),
+            StrictError::NonCanonical(offset) => {
+                write!(f, "non-canonical compressed data at byte {}", offset)
+            }
+        }
+    }
+}
+
+impl Error for StrictError {}
+
+/// Returns decompressed data, only if it is exactly what
+/// [`compress`](fn.compress.html) gives for it.
+///
+/// [`decompress`](fn.decompress.html) accepts encodings the encoder of this
+/// crate never writes. They are:
+///
+/// * a single verbatim byte written as a run, `255 0` and the byte, where the
+///   encoder writes `254` and the byte;
+/// * consecutive verbatim tokens where the first holds fewer than 256 bytes,
+///   which the encoder writes as a single run;
+/// * a verbatim byte at which a codebook entry matches, where the encoder
+///   writes the code;
+/// * a code for a shorter entry than the longest one matching at its
+///   position.
+///
+/// Such data can come from another encoder or have been tampered with.
+///
+/// # Errors
+///
+/// If the compressed data is invalid, then [`StrictError::Invalid`] is
+/// returned, and if it is non-canonical, then [`StrictError::NonCanonical`]
+/// with the offset of the first token written differently.
+///
+/// [`StrictError::Invalid`]: enum.StrictError.html#variant.Invalid
+/// [`StrictError::NonCanonical`]: enum.StrictError.html#variant.NonCanonical
+///
+/// # Examples
+///
+/// ```
+/// use smaz::{decompress, decompress_strict, StrictError};
+///
+/// assert_eq!(b"4".to_vec(), decompress_strict(&[254, b'4']).unwrap());
+/// // the same byte as a run of one
+/// assert_eq!(b"4".to_vec(), decompress(&[255, 0, b'4']).unwrap());
+/// assert_eq!(Err(StrictError::NonCanonical(0)), decompress_strict(&[255, 0, b'4']));
+/// ```
+pub fn decompress_strict(input: &[u8]) -> result::Result<Vec<u8>, StrictError> {
+    let out = decompress(input).map_err(|_| StrictError::Invalid)?;
+    match first_difference(&out, input) {
+        Some(offset) => Err(StrictError::NonCanonical(offset)),
+        None => Ok(out),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::compress;
+    use crate::tests::inputs;
+
+    #[test]
+    fn test_strict() {
+        let mut inputs = inputs(184);
+        inputs.push(vec![1; 1000]);
+        for input in &inputs {
+            assert_eq!(*input, decompress_strict(&compress(input)).unwrap());
+        }
+        assert!(decompress_strict(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_non_canonical() {
+        let the_ = [&compress(b"th")[..], &compress(b"e")[..]].concat();
+        let run: Vec<u8> = [&[255, 255][..], &[1; 256], &[255, 1, 1, 1]].concat();
+        let split: Vec<u8> = [&[255, 254][..], &[1; 255], &[255, 1, 1, 1]].concat();
+        let cases: &[(&[u8], &[u8], usize)] = &[
+            // a run of one
+            (&[255, 0, b'4'], b"4", 0),
+            (&[1, 255, 0, b'4'], b"the4", 1),
+            // a run split early
+            (&[254, b'4', 254, b'2'], b"42", 0),
+            (&[255, 1, b'4', b'2', 254, b'9'], b"429", 0),
+            (&split, &[1; 257], 0),
+            // a byte matching an entry stored verbatim
+            (&[254, b' '], b" ", 0),
+            (&[255, 1, b'4', b' '], b"4 ", 0),
+            // a shorter match than the longest
+            (&the_, b"the", 0),
+        ];
+        for (data, expected, offset) in cases {
+            assert_eq!(*expected, &decompress(data).unwrap()[..]);
+            assert_eq!(
+                Err(StrictError::NonCanonical(*offset)),
+                decompress_strict(data)
+            );
+        }
+        // a full run followed by another is canonical
+        assert_eq!(vec![1; 258], decompress_strict(&run).unwrap());
+    }
+
+    #[test]
+    fn test_strict_invalid() {
+        for data in [&[254][..], &[255, 1, b'a'], &[1, 255]] {
+            assert!(decompress(data).is_err());
+            assert_eq!(Err(StrictError::Invalid), decompress_strict(data));
+        }
+    }
+}
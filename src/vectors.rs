@@ -0,0 +1,142 @@
+//! Conformance vectors for the smaz format, enabled with the `test-vectors`
+//! feature.
+//!
+//! Each vector pairs an input with its output from
+//! [`compress`](../fn.compress.html). Ports of smaz and crates that read or
+//! write its format can check their output against these to make sure both
+//! sides agree. The vectors are checked in, so a change to them is a change to
+//! the format.
+//!
+//! # Examples
+//!
+//! ```
+//! use smaz::vectors::VECTORS;
+//!
+//! for (input, compressed) in VECTORS {
+//!     assert_eq!(*compressed, &smaz::compress(input)[..]);
+//!     assert_eq!(*input, &smaz::decompress(compressed).unwrap()[..]);
+//! }
+//! ```
+
+/// Inputs and their compressed form: the sample strings of the original C
+/// library, then binary inputs exercising verbatim bytes and runs.
+pub static VECTORS: &[(&[u8], &[u8])] = &[
+    (b"", &[]),
+    (
+        b"This is a small string",
+        &[254, 84, 76, 56, 172, 62, 173, 152, 62, 195, 70],
+    ),
+    (b"foobar", &[220, 6, 90, 79]),
+    (b"the end", &[1, 171, 61]),
+    (
+        b"not-a-g00d-Exampl333",
+        &[
+            132, 204, 4, 204, 59, 255, 1, 48, 48, 24, 204, 254, 69, 250, 4, 45, 60, 22, 255, 2, 51,
+            51, 51,
+        ],
+    ),
+    (
+        b"Smaz is a simple compression library",
+        &[
+            254, 83, 173, 219, 56, 172, 62, 226, 60, 87, 161, 45, 60, 33, 166, 107, 205, 8, 90,
+            130, 12, 83,
+        ],
+    ),
+    (
+        b"Nothing is more difficult, and therefore more precious, than to be able to decide",
+        &[
+            254, 78, 223, 102, 99, 116, 45, 42, 11, 129, 44, 44, 131, 38, 22, 3, 148, 63, 210, 68,
+            11, 45, 42, 11, 60, 33, 28, 144, 164, 36, 203, 143, 96, 92, 25, 90, 87, 82, 165, 215,
+            237, 2,
+        ],
+    ),
+    (
+        b"this is an example of what works very well with smaz",
+        &[
+            155, 56, 172, 41, 2, 250, 4, 45, 60, 87, 32, 159, 135, 65, 42, 254, 107, 23, 231, 71,
+            145, 152, 243, 227, 10, 173, 219,
+        ],
+    ),
+    (
+        b"1000 numbers 2000 will 10 20 30 compress very little",
+        &[
+            255, 3, 49, 48, 48, 48, 236, 38, 45, 92, 221, 0, 255, 3, 50, 48, 48, 48, 243, 152, 0,
+            255, 1, 49, 48, 0, 255, 1, 50, 48, 0, 255, 1, 51, 48, 161, 45, 60, 33, 166, 0, 231, 71,
+            151, 3, 3, 87,
+        ],
+    ),
+    (
+        b"and now a few italian sentences:",
+        &[
+            7, 236, 6, 65, 146, 44, 2, 65, 246, 88, 8, 26, 62, 97, 51, 136, 10, 254, 58,
+        ],
+    ),
+    (
+        b"Nel mezzo del cammin di nostra vita, mi ritrovai in una selva oscura",
+        &[
+            254, 78, 178, 123, 2, 219, 219, 96, 106, 180, 28, 4, 45, 45, 105, 129, 236, 6, 77, 130,
+            0, 109, 47, 4, 36, 45, 8, 189, 47, 115, 109, 4, 8, 78, 0, 224, 163, 95, 22, 109, 163,
+            6, 10, 28, 150, 4,
+        ],
+    ),
+    (
+        b"Mi illumino di immenso",
+        &[254, 77, 8, 56, 152, 38, 45, 15, 96, 129, 56, 45, 252, 179],
+    ),
+    (
+        b"L'autore di questa libreria vive in Sicilia",
+        &[
+            255, 1, 76, 39, 4, 196, 42, 11, 129, 0, 254, 113, 38, 54, 200, 205, 8, 90, 33, 114,
+            163, 109, 186, 11, 105, 254, 83, 131, 240, 8, 4,
+        ],
+    ),
+    (
+        b"try it against urls",
+        &[195, 71, 47, 25, 59, 4, 15, 77, 0, 150, 22, 10],
+    ),
+    (b"http://google.com", &[67, 59, 6, 6, 59, 87, 253]),
+    (
+        b"http://programming.reddit.com",
+        &[67, 60, 115, 59, 130, 45, 45, 70, 110, 33, 24, 129, 3, 253],
+    ),
+    (b"\x00", &[254, 0]),
+    (b"\xfe\xff", &[255, 1, 254, 255]),
+    (b"\xc3\x28 the \xff", &[255, 1, 195, 40, 13, 11, 254, 255]),
+    (
+        &[0x01; 257],
+        &[
+            255, 255, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1,
+            1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 254,
+            1,
+        ],
+    ),
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::TEST_STRINGS;
+    use crate::{compress, decompress};
+
+    #[test]
+    fn test_stable() {
+        for (input, compressed) in VECTORS {
+            assert_eq!(*compressed, &compress(input)[..], "{:?}", input);
+            assert_eq!(*input, &decompress(compressed).unwrap()[..]);
+        }
+    }
+
+    #[test]
+    fn test_samples() {
+        for (s, (input, _)) in TEST_STRINGS.iter().zip(VECTORS) {
+            assert_eq!(s.as_bytes(), *input);
+        }
+    }
+}